@@ -0,0 +1,173 @@
+//! actix-web 集成。需开启 `actix-web` feature。
+//!
+//! [`Notification`] 可作为 actix-web 的提取器：读取原始 body，经 app data 中的
+//! [`WechatPayClient`](也可为 `web::Data<WechatPayClient>`)验签、防重放检查并解密。
+//! 通知无效时直接以失败应答拒绝，不会调用 handler。
+//! handler 返回 [`NotificationAck`]，由 [`Notification::ack`] 根据业务处理的结果构造，
+//! 处理成功时记录通知 ID，处理失败时微信支付重发的通知仍按新通知处理。
+//!
+//! ```ignore
+//! async fn notify(notification: Notification) -> NotificationAck {
+//!     let result = process(&notification.event).await;
+//!     notification.ack(result).await
+//! }
+//!
+//! HttpServer::new(move || {
+//!     App::new()
+//!         .app_data(web::Data::new(wechatpay_client.clone()))
+//!         .route("/wechatpay/notify", web::post().to(notify))
+//! })
+//! ```
+
+use crate::notify::{Notification, NotificationAck};
+use crate::WechatPayClient;
+use actix_web::body::BoxBody;
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::web::{Bytes, Data};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder, ResponseError};
+use std::future::Future;
+use std::pin::Pin;
+
+impl FromRequest for Notification {
+    type Error = NotificationAck;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let client = req
+            .app_data::<WechatPayClient>()
+            .or_else(|| req.app_data::<Data<WechatPayClient>>().map(|d| d.get_ref()))
+            .cloned();
+        let head = to_http_request(req);
+        let body = Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let client =
+                client.ok_or_else(|| NotificationAck::fail("WechatPayClient is not configured"))?;
+            let body = body.await.map_err(|e| NotificationAck::Fail {
+                status: http::StatusCode::BAD_REQUEST,
+                message: e.to_string(),
+            })?;
+            let req = head
+                .body(body)
+                .map_err(|e| NotificationAck::from(&crate::Error::from(e)))?;
+            client.handle_notification(req).await.map_err(|e| {
+                log::warn!("invalid wechatpay notification: {}", e);
+                NotificationAck::from(&e)
+            })
+        })
+    }
+}
+
+/// actix-web 使用的 `http` 版本与本 crate 不同，按字符串转换请求头部。
+fn to_http_request(req: &HttpRequest) -> http::request::Builder {
+    let mut builder = http::Request::builder()
+        .method(req.method().as_str())
+        .uri(req.uri().to_string());
+    for (name, value) in req.headers() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    builder
+}
+
+impl ResponseError for NotificationAck {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status().as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.clone().respond()
+    }
+}
+
+impl Responder for NotificationAck {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse {
+        self.respond()
+    }
+}
+
+impl NotificationAck {
+    fn respond(self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.status_code());
+        if let NotificationAck::Fail { .. } = self {
+            builder.content_type("application/json");
+        }
+        builder.body(self.body())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::{native_params, test_client, test_server};
+    use crate::notify::NotificationEvent;
+    use actix_web::{test, web, App};
+
+    /// 将模拟服务构造的通知转换为 actix-web 的测试请求。
+    fn test_request(req: http::Request<bytes::Bytes>) -> test::TestRequest {
+        let mut test_req = test::TestRequest::post().uri("/notify");
+        for (name, value) in req.headers() {
+            test_req = test_req.insert_header((name.as_str(), value.as_bytes()));
+        }
+        test_req.set_payload(req.into_body())
+    }
+
+    async fn notify(notification: Notification) -> NotificationAck {
+        let result = match &notification.event {
+            NotificationEvent::Trade(trade) if trade.out_trade_no == "actix_2" => {
+                Err("database unavailable")
+            }
+            NotificationEvent::Trade(_) => Ok(()),
+            _ => Err("unexpected event"),
+        };
+        notification.ack(result).await
+    }
+
+    #[test]
+    fn test_notification_extractor() -> anyhow::Result<()> {
+        actix_web::rt::System::new().block_on(async {
+            let server = test_server();
+            let client = test_client(&server).await;
+            client
+                .native_create_trade(&native_params("actix_1"))
+                .await?;
+            client
+                .native_create_trade(&native_params("actix_2"))
+                .await?;
+            server.pay("actix_1")?;
+            server.pay("actix_2")?;
+
+            let app = test::init_service(
+                App::new()
+                    .app_data(Data::new(client))
+                    .route("/notify", web::post().to(notify)),
+            )
+            .await;
+
+            let req = server.trade_notification("actix_1")?;
+            let res = test::call_service(&app, test_request(req.clone()).to_request()).await;
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+            assert!(test::read_body(res).await.is_empty());
+
+            // 业务处理失败时应答 FAIL，微信支付会重发
+            let req2 = server.trade_notification("actix_2")?;
+            let res = test::call_service(&app, test_request(req2).to_request()).await;
+            assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            let body: serde_json::Value = test::read_body_json(res).await;
+            assert_eq!(body["code"], "FAIL");
+            assert_eq!(body["message"], "database unavailable");
+
+            // 重放及篡改的通知不会到达 handler
+            let res = test::call_service(&app, test_request(req).to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body: serde_json::Value = test::read_body_json(res).await;
+            assert_eq!(body["code"], "FAIL");
+            let req = server.trade_notification("actix_1")?;
+            let res =
+                test::call_service(&app, test_request(req).set_payload("{}").to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,135 @@
+//! axum 集成。需开启 `axum` feature。
+//!
+//! [`Notification`] 可作为 axum 的提取器：读取原始 body，经 state 中的 [`WechatPayClient`]
+//! 验签、防重放检查并解密。通知无效时直接以失败应答拒绝，不会调用 handler。
+//! handler 返回 [`NotificationAck`]，由 [`Notification::ack`] 根据业务处理的结果构造，
+//! 处理成功时记录通知 ID，处理失败时微信支付重发的通知仍按新通知处理。
+//!
+//! ```ignore
+//! async fn notify(notification: Notification) -> NotificationAck {
+//!     let result = process(&notification.event).await;
+//!     notification.ack(result).await
+//! }
+//!
+//! let app = Router::new()
+//!     .route("/wechatpay/notify", post(notify))
+//!     .with_state(wechatpay_client);
+//! ```
+
+use crate::notify::{Notification, NotificationAck};
+use crate::WechatPayClient;
+use axum::body::Body;
+use axum::extract::{FromRef, FromRequest, Request};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+
+impl<S> FromRequest<S> for Notification
+where
+    WechatPayClient: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = NotificationAck;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let client = WechatPayClient::from_ref(state);
+        let (parts, body) = req.into_parts();
+        let body = Bytes::from_request(Request::from_parts(parts.clone(), body), state)
+            .await
+            .map_err(|e| NotificationAck::Fail {
+                status: e.status(),
+                message: e.body_text(),
+            })?;
+        let req = http::Request::from_parts(parts, body);
+        client.handle_notification(req).await.map_err(|e| {
+            log::warn!("invalid wechatpay notification: {}", e);
+            NotificationAck::from(&e)
+        })
+    }
+}
+
+impl IntoResponse for NotificationAck {
+    fn into_response(self) -> Response {
+        NotificationAck::into_response(self).map(Body::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::{native_params, test_client, test_server};
+    use crate::notify::NotificationEvent;
+    use axum::routing::post;
+    use axum::Router;
+    use http::StatusCode;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    async fn send(app: &Router, req: http::Request<Bytes>) -> (StatusCode, Bytes) {
+        let res = app.clone().oneshot(req.map(Body::from)).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_notification_extractor() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+        client.native_create_trade(&native_params("axum_1")).await?;
+        client.native_create_trade(&native_params("axum_2")).await?;
+        server.pay("axum_1")?;
+        server.pay("axum_2")?;
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                "/notify",
+                post({
+                    let received = received.clone();
+                    move |notification: Notification| async move {
+                        let NotificationEvent::Trade(trade) = &notification.event else {
+                            return NotificationAck::fail("unexpected event");
+                        };
+                        received.lock().unwrap().push(trade.out_trade_no.clone());
+                        let result = match trade.out_trade_no.as_str() {
+                            "axum_2" => Err("database unavailable"),
+                            _ => Ok(()),
+                        };
+                        notification.ack(result).await
+                    }
+                }),
+            )
+            .with_state(client);
+        let notification = |out_trade_no: &str| -> anyhow::Result<http::Request<Bytes>> {
+            let mut req = server.trade_notification(out_trade_no)?;
+            *req.uri_mut() = "/notify".parse()?;
+            Ok(req)
+        };
+
+        let req = notification("axum_1")?;
+        let (status, body) = send(&app, req.clone()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(body.is_empty());
+
+        // 业务处理失败时应答 FAIL，微信支付会重发
+        let (status, body) = send(&app, notification("axum_2")?).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["code"], "FAIL");
+        assert_eq!(body["message"], "database unavailable");
+
+        // 重放及篡改的通知不会到达 handler
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["code"], "FAIL");
+        let (parts, _) = notification("axum_1")?.into_parts();
+        let tampered = http::Request::from_parts(parts, Bytes::from_static(b"{}"));
+        let (status, _) = send(&app, tampered).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(*received.lock().unwrap(), ["axum_1", "axum_2"]);
+        Ok(())
+    }
+}
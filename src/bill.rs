@@ -0,0 +1,932 @@
+//! 交易账单及资金账单。
+//!
+//! 下载账单分两步：先申请账单，得到账单文件的下载地址及摘要；再以签名的 GET 请求下载文件。
+//! 账单文件的响应没有签名，下载后(按需解压)通过摘要校验文件的完整性。
+//!
+//! 账单为 CSV 格式：第一行为表头，之后每行一条记录，每个字段以 `` ` `` 开头；
+//! 记录之后是汇总的表头及汇总数据。[`Bill`] 保存账单原文，遍历时逐行解析为
+//! [`TradeBillRow`]、[`FundFlowBillRow`] 等类型。
+
+use crate::client::WechatPayClient;
+use crate::error::{Error, Result};
+use crate::refund::RefundStatus;
+use crate::trade::{TradeState, TradeType};
+use bytes::Bytes;
+use chrono::{NaiveDate, NaiveDateTime};
+use flate2::read::GzDecoder;
+use serde::de::{value::StrDeserializer, DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::io::Read;
+use std::marker::PhantomData;
+
+impl WechatPayClient {
+    /// 申请交易账单，返回账单文件的下载地址及摘要。
+    /// 次日 9 点后可申请前一日的账单。
+    pub async fn trade_bill(&self, params: &TradeBillParams) -> Result<BillDownloadInfo> {
+        let url = format!("{}/bill/tradebill", self.v3_url());
+        let req = self.client.get(url).query(&params.query()).build()?;
+        let res = self.execute(req, None).await?;
+        let res = res.json().await?;
+        Ok(res)
+    }
+
+    /// 申请资金账单，返回账单文件的下载地址及摘要。
+    pub async fn fund_flow_bill(&self, params: &FundFlowBillParams) -> Result<BillDownloadInfo> {
+        let url = format!("{}/bill/fundflowbill", self.v3_url());
+        let req = self.client.get(url).query(&params.query()).build()?;
+        let res = self.execute(req, None).await?;
+        let res = res.json().await?;
+        Ok(res)
+    }
+
+    /// 下载账单文件，返回校验摘要后的账单原文。
+    /// `tar_type` 应与申请账单时一致，为 [`TarType::Gzip`] 时先解压。
+    pub async fn download_bill(
+        &self,
+        info: &BillDownloadInfo,
+        tar_type: Option<TarType>,
+    ) -> Result<Bytes> {
+        let file = self.download_bill_file(&info.download_url).await?;
+        let content = decompress(file, tar_type)?;
+        verify_hash(&content, &info.hash_type, &info.hash_value)?;
+        Ok(content)
+    }
+
+    /// 申请并下载交易账单。
+    pub async fn download_trade_bill(&self, params: &TradeBillParams) -> Result<TradeBill> {
+        let info = self.trade_bill(params).await?;
+        let content = self.download_bill(&info, params.tar_type).await?;
+        Bill::from_bytes(content)
+    }
+
+    /// 申请并下载资金账单。
+    pub async fn download_fund_flow_bill(
+        &self,
+        params: &FundFlowBillParams,
+    ) -> Result<FundFlowBill> {
+        let info = self.fund_flow_bill(params).await?;
+        let content = self.download_bill(&info, params.tar_type).await?;
+        Bill::from_bytes(content)
+    }
+
+    /// 下载账单文件的原始内容。响应没有签名，调用方需校验文件摘要。
+    pub(crate) async fn download_bill_file(&self, download_url: &str) -> Result<Bytes> {
+        let req = self.client.get(download_url).build()?;
+        let res = self.execute_unverified(req).await?;
+        Ok(res.bytes().await?)
+    }
+}
+
+/// 按压缩类型解压账单文件。
+pub(crate) fn decompress(file: Bytes, tar_type: Option<TarType>) -> Result<Bytes> {
+    match tar_type {
+        None => Ok(file),
+        Some(TarType::Gzip) => {
+            let mut content = Vec::new();
+            GzDecoder::new(file.as_ref()).read_to_end(&mut content)?;
+            Ok(content.into())
+        }
+    }
+}
+
+/// 校验账单原文的摘要。
+pub(crate) fn verify_hash(content: &[u8], hash_type: &str, hash_value: &str) -> Result<()> {
+    if !hash_type.eq_ignore_ascii_case("SHA1") {
+        return Err(Error::Verification(format!(
+            "unsupported bill hash type: {}",
+            hash_type
+        )));
+    }
+    let digest: String = Sha1::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if !digest.eq_ignore_ascii_case(hash_value) {
+        return Err(Error::Verification(format!(
+            "bill hash mismatch, expected {}, got {}",
+            hash_value, digest
+        )));
+    }
+    Ok(())
+}
+
+/// 申请账单接口的响应。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillDownloadInfo {
+    /// 摘要算法，固定为 SHA1
+    pub hash_type: String,
+    /// 账单原文(解压后)的摘要
+    pub hash_value: String,
+    /// 下载地址，5 分钟内有效
+    pub download_url: String,
+}
+
+/// 申请交易账单的参数
+#[derive(Debug, Clone)]
+pub struct TradeBillParams {
+    /// 账单日期
+    pub bill_date: NaiveDate,
+    /// 子商户号。服务商下载单个子商户的账单时填写，不填则返回所有子商户的账单。
+    pub sub_mchid: Option<String>,
+    /// 账单类型
+    pub bill_type: TradeBillType,
+    /// 压缩类型，不填时不压缩。
+    pub tar_type: Option<TarType>,
+}
+
+impl TradeBillParams {
+    /// 指定日期的全部交易账单，不压缩。
+    pub fn new(bill_date: NaiveDate) -> TradeBillParams {
+        TradeBillParams {
+            bill_date,
+            sub_mchid: None,
+            bill_type: TradeBillType::All,
+            tar_type: None,
+        }
+    }
+
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![
+            ("bill_date", self.bill_date.format("%Y-%m-%d").to_string()),
+            ("bill_type", self.bill_type.as_str().to_string()),
+        ];
+        if let Some(sub_mchid) = &self.sub_mchid {
+            query.push(("sub_mchid", sub_mchid.clone()));
+        }
+        if let Some(tar_type) = self.tar_type {
+            query.push(("tar_type", tar_type.as_str().to_string()));
+        }
+        query
+    }
+}
+
+/// 申请资金账单的参数
+#[derive(Debug, Clone)]
+pub struct FundFlowBillParams {
+    /// 账单日期
+    pub bill_date: NaiveDate,
+    /// 资金账户类型
+    pub account_type: FundFlowAccountType,
+    /// 压缩类型，不填时不压缩。
+    pub tar_type: Option<TarType>,
+}
+
+impl FundFlowBillParams {
+    /// 指定日期基本账户的资金账单，不压缩。
+    pub fn new(bill_date: NaiveDate) -> FundFlowBillParams {
+        FundFlowBillParams {
+            bill_date,
+            account_type: FundFlowAccountType::Basic,
+            tar_type: None,
+        }
+    }
+
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![
+            ("bill_date", self.bill_date.format("%Y-%m-%d").to_string()),
+            ("account_type", self.account_type.as_str().to_string()),
+        ];
+        if let Some(tar_type) = self.tar_type {
+            query.push(("tar_type", tar_type.as_str().to_string()));
+        }
+        query
+    }
+}
+
+/// 交易账单类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TradeBillType {
+    /// 当日所有订单信息(不含充值退款订单)
+    #[default]
+    All,
+    /// 当日成功支付的订单(不含充值退款订单)
+    Success,
+    /// 当日退款订单(不含充值退款订单)
+    Refund,
+}
+
+impl TradeBillType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeBillType::All => "ALL",
+            TradeBillType::Success => "SUCCESS",
+            TradeBillType::Refund => "REFUND",
+        }
+    }
+}
+
+/// 资金账户类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FundFlowAccountType {
+    /// 基本账户
+    #[default]
+    Basic,
+    /// 运营账户
+    Operation,
+    /// 手续费账户
+    Fees,
+}
+
+impl FundFlowAccountType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FundFlowAccountType::Basic => "BASIC",
+            FundFlowAccountType::Operation => "OPERATION",
+            FundFlowAccountType::Fees => "FEES",
+        }
+    }
+}
+
+/// 账单文件的压缩类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarType {
+    /// gzip 压缩
+    Gzip,
+}
+
+impl TarType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TarType::Gzip => "GZIP",
+        }
+    }
+}
+
+/// 交易账单
+pub type TradeBill = Bill<TradeBillRow, TradeBillSummary>;
+
+/// 资金账单
+pub type FundFlowBill = Bill<FundFlowBillRow, FundFlowBillSummary>;
+
+/// 账单原文。`R` 为每行记录的类型，`S` 为汇总数据的类型。
+/// 记录在遍历时才解析，可以逐行处理较大的账单。
+#[derive(Debug, Clone)]
+pub struct Bill<R, S> {
+    content: String,
+    columns: Vec<String>,
+    _marker: PhantomData<fn() -> (R, S)>,
+}
+
+impl<R: FromBillRecord, S: FromBillRecord> Bill<R, S> {
+    /// 解析账单原文，如已保存的账单文件。只解析表头，记录在遍历时解析。
+    pub fn parse(content: impl Into<String>) -> Result<Self> {
+        let mut content = content.into();
+        if content.starts_with('\u{feff}') {
+            content.drain(..'\u{feff}'.len_utf8());
+        }
+        let header = content.lines().next().unwrap_or_default();
+        if header.trim().is_empty() || header.starts_with('`') {
+            return Err(bill_error("missing bill header".to_string()));
+        }
+        let columns = split_header(header);
+        Ok(Bill {
+            content,
+            columns,
+            _marker: PhantomData,
+        })
+    }
+
+    /// 解析 UTF-8 编码的账单原文。
+    pub fn from_bytes(content: impl Into<Vec<u8>>) -> Result<Self> {
+        let content = String::from_utf8(content.into()).map_err(|e| Error::Serde(Box::new(e)))?;
+        Bill::parse(content)
+    }
+
+    /// 账单原文
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// 表头的各列名称。金额列名中的 `(元)` 已去掉。
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// 逐行解析账单记录。
+    pub fn rows(&self) -> BillRows<'_, R> {
+        BillRows {
+            lines: self.content.lines().enumerate().skip(1),
+            columns: &self.columns,
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 解析账单末尾的汇总数据。
+    pub fn summary(&self) -> Result<S> {
+        let mut lines = self
+            .content
+            .lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .skip_while(|line| line.starts_with('`'));
+        let (Some(header), Some(values)) = (lines.next(), lines.next()) else {
+            return Err(bill_error("missing bill summary".to_string()));
+        };
+        let columns = split_header(header);
+        let record = BillRecord::new(&columns, values)?;
+        S::from_record(&record)
+    }
+}
+
+/// [`Bill::rows`] 返回的迭代器。
+#[derive(Debug)]
+pub struct BillRows<'a, R> {
+    lines: std::iter::Skip<std::iter::Enumerate<std::str::Lines<'a>>>,
+    columns: &'a [String],
+    done: bool,
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<R: FromBillRecord> Iterator for BillRows<'_, R> {
+    type Item = Result<R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        for (index, line) in self.lines.by_ref() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            // 记录之后是汇总表头，其字段不以 ` 开头。
+            if !line.starts_with('`') {
+                break;
+            }
+            let row = BillRecord::new(self.columns, line).and_then(|r| R::from_record(&r));
+            return Some(row.map_err(|e| match e {
+                Error::Serde(e) => bill_error(format!("line {}: {}", index + 1, e)),
+                e => e,
+            }));
+        }
+        self.done = true;
+        None
+    }
+}
+
+/// 可由账单中一行记录解析得到的类型。
+/// 实现此 trait 可以解析本 crate 未提供的账单格式。
+pub trait FromBillRecord: Sized {
+    fn from_record(record: &BillRecord<'_>) -> Result<Self>;
+}
+
+/// 账单中的一行记录，按表头的列名取值。
+#[derive(Debug)]
+pub struct BillRecord<'a> {
+    columns: &'a [String],
+    fields: Vec<&'a str>,
+}
+
+impl<'a> BillRecord<'a> {
+    fn new(columns: &'a [String], line: &'a str) -> Result<BillRecord<'a>> {
+        let line = line.trim_end_matches('\r');
+        // 字段以 ` 开头，以 ",`" 分隔，字段内容(如商品名称)中可能有逗号。
+        let fields: Vec<&str> = line.strip_prefix('`').unwrap_or(line).split(",`").collect();
+        if fields.len() != columns.len() {
+            return Err(bill_error(format!(
+                "expected {} fields, got {}",
+                columns.len(),
+                fields.len()
+            )));
+        }
+        Ok(BillRecord { columns, fields })
+    }
+
+    /// 列名对应的字段值，已去掉开头的 `` ` ``。账单中没有此列时返回 None。
+    pub fn get(&self, column: &str) -> Option<&'a str> {
+        let index = self.columns.iter().position(|c| c == column)?;
+        Some(self.fields[index])
+    }
+
+    fn required(&self, column: &str) -> Result<&'a str> {
+        self.get(column)
+            .ok_or_else(|| bill_error(format!("missing column {}", column)))
+    }
+
+    fn string(&self, column: &str) -> Result<String> {
+        self.required(column).map(ToString::to_string)
+    }
+
+    /// 没有此列或字段为空时返回 None。
+    fn optional(&self, column: &str) -> Option<String> {
+        self.get(column)
+            .filter(|v| !v.is_empty())
+            .map(ToString::to_string)
+    }
+
+    fn parse<T: std::str::FromStr>(&self, column: &str) -> Result<T> {
+        let value = self.required(column)?;
+        value
+            .parse()
+            .map_err(|_| bill_error(format!("invalid {}: {}", column, value)))
+    }
+
+    fn amount(&self, column: &str) -> Result<i64> {
+        let value = self.required(column)?;
+        parse_fen(value).ok_or_else(|| bill_error(format!("invalid {}: {}", column, value)))
+    }
+
+    fn optional_amount(&self, column: &str) -> Result<Option<i64>> {
+        match self.get(column) {
+            Some(value) if !value.is_empty() => self.amount(column).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn datetime(&self, column: &str) -> Result<NaiveDateTime> {
+        let value = self.required(column)?;
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+            .map_err(|_| bill_error(format!("invalid {}: {}", column, value)))
+    }
+
+    /// 以接口中相同取值的枚举解析。
+    fn value<T: DeserializeOwned>(&self, column: &str) -> Result<T> {
+        let value = self.required(column)?;
+        let deserializer: StrDeserializer<'_, serde::de::value::Error> = value.into_deserializer();
+        T::deserialize(deserializer).map_err(|e| bill_error(format!("invalid {}: {}", column, e)))
+    }
+}
+
+/// 交易账单中的一条记录。
+/// 退款相关的字段只在账单类型为 ALL 及 REFUND 时存在。
+#[derive(Debug, Clone)]
+pub struct TradeBillRow {
+    /// 交易时间
+    pub trade_time: NaiveDateTime,
+    /// 公众账号ID
+    pub app_id: String,
+    /// 商户号
+    pub mch_id: String,
+    /// 特约商户号
+    pub sub_mch_id: Option<String>,
+    /// 设备号
+    pub device_id: Option<String>,
+    /// 微信支付订单号
+    pub transaction_id: String,
+    /// 商户订单号
+    pub out_trade_no: String,
+    /// 用户标识
+    pub openid: Option<String>,
+    /// 交易类型
+    pub trade_type: TradeType,
+    /// 交易状态
+    pub trade_state: TradeState,
+    /// 付款银行
+    pub bank_type: String,
+    /// 货币种类
+    pub currency: String,
+    /// 应结订单金额，单位为分。
+    pub settlement_total: i64,
+    /// 代金券金额，单位为分。
+    pub coupon_amount: i64,
+    /// 微信退款单号。非退款记录为 None。
+    pub refund_id: Option<String>,
+    /// 商户退款单号。非退款记录为 None。
+    pub out_refund_no: Option<String>,
+    /// 退款金额，单位为分。
+    pub settlement_refund: Option<i64>,
+    /// 充值券退款金额，单位为分。
+    pub recharge_coupon_refund: Option<i64>,
+    /// 退款类型
+    pub refund_type: Option<String>,
+    /// 退款状态
+    pub refund_status: Option<RefundStatus>,
+    /// 商品名称
+    pub description: String,
+    /// 商户数据包
+    pub attach: Option<String>,
+    /// 手续费，单位为元，保留原文(可能有 5 位小数)。
+    pub fee: String,
+    /// 费率
+    pub fee_rate: String,
+    /// 订单金额，单位为分。
+    pub total: Option<i64>,
+    /// 申请退款金额，单位为分。
+    pub refund_amount: Option<i64>,
+    /// 费率备注
+    pub fee_rate_remark: Option<String>,
+}
+
+impl TradeBillRow {
+    /// 是否为退款记录。
+    pub fn is_refund(&self) -> bool {
+        self.refund_id.is_some()
+    }
+}
+
+impl FromBillRecord for TradeBillRow {
+    fn from_record(r: &BillRecord<'_>) -> Result<TradeBillRow> {
+        // 没有特约商户号、非退款记录的退款单号时为 0
+        let id = |column| r.optional(column).filter(|v| v != "0");
+        let refund_status = match r.get("退款状态").filter(|v| !v.is_empty()) {
+            Some(status) => Some(parse_refund_status(status)?),
+            None => None,
+        };
+        Ok(TradeBillRow {
+            trade_time: r.datetime("交易时间")?,
+            app_id: r.string("公众账号ID")?,
+            mch_id: r.string("商户号")?,
+            sub_mch_id: id("特约商户号"),
+            device_id: r.optional("设备号"),
+            transaction_id: r.string("微信订单号")?,
+            out_trade_no: r.string("商户订单号")?,
+            openid: r.optional("用户标识"),
+            trade_type: r.value("交易类型")?,
+            trade_state: r.value("交易状态")?,
+            bank_type: r.string("付款银行")?,
+            currency: r.string("货币种类")?,
+            settlement_total: r.amount("应结订单金额")?,
+            coupon_amount: r.amount("代金券金额")?,
+            refund_id: id("微信退款单号"),
+            out_refund_no: id("商户退款单号"),
+            settlement_refund: r.optional_amount("退款金额")?,
+            recharge_coupon_refund: r.optional_amount("充值券退款金额")?,
+            refund_type: r.optional("退款类型"),
+            refund_status,
+            description: r.string("商品名称")?,
+            attach: r.optional("商户数据包"),
+            fee: r.string("手续费")?,
+            fee_rate: r.string("费率")?,
+            total: r.optional_amount("订单金额")?,
+            refund_amount: r.optional_amount("申请退款金额")?,
+            fee_rate_remark: r.optional("费率备注"),
+        })
+    }
+}
+
+/// 交易账单的汇总数据
+#[derive(Debug, Clone)]
+pub struct TradeBillSummary {
+    /// 总交易单数
+    pub total_count: u64,
+    /// 应结订单总金额，单位为分。
+    pub settlement_total: i64,
+    /// 退款总金额，单位为分。
+    pub refund_total: i64,
+    /// 充值券退款总金额，单位为分。
+    pub recharge_coupon_refund_total: i64,
+    /// 手续费总金额，单位为元，保留原文。
+    pub fee_total: String,
+    /// 订单总金额，单位为分。
+    pub order_total: Option<i64>,
+    /// 申请退款总金额，单位为分。
+    pub apply_refund_total: Option<i64>,
+}
+
+impl FromBillRecord for TradeBillSummary {
+    fn from_record(r: &BillRecord<'_>) -> Result<TradeBillSummary> {
+        Ok(TradeBillSummary {
+            total_count: r.parse("总交易单数")?,
+            settlement_total: r.amount("应结订单总金额")?,
+            refund_total: r.amount("退款总金额")?,
+            recharge_coupon_refund_total: r.amount("充值券退款总金额")?,
+            fee_total: r.string("手续费总金额")?,
+            order_total: r.optional_amount("订单总金额")?,
+            apply_refund_total: r.optional_amount("申请退款总金额")?,
+        })
+    }
+}
+
+/// 资金账单中的一条记录。
+#[derive(Debug, Clone)]
+pub struct FundFlowBillRow {
+    /// 记账时间
+    pub accounting_time: NaiveDateTime,
+    /// 微信支付业务单号，如微信支付订单号、微信退款单号。
+    pub biz_order_id: String,
+    /// 资金流水单号
+    pub flow_id: String,
+    /// 业务名称
+    pub biz_name: String,
+    /// 业务类型，如交易、退款。
+    pub biz_type: String,
+    /// 收支类型
+    pub flow_type: FundFlowType,
+    /// 收支金额，单位为分。
+    pub amount: i64,
+    /// 账户结余，单位为分。
+    pub balance: i64,
+    /// 资金变更提交申请人
+    pub applicant: String,
+    /// 备注
+    pub remark: Option<String>,
+    /// 业务凭证号，如商户订单号、商户退款单号。
+    pub voucher_no: Option<String>,
+}
+
+impl FromBillRecord for FundFlowBillRow {
+    fn from_record(r: &BillRecord<'_>) -> Result<FundFlowBillRow> {
+        let flow_type = match r.required("收支类型")? {
+            "收入" => FundFlowType::Income,
+            "支出" => FundFlowType::Expense,
+            other => return Err(bill_error(format!("invalid 收支类型: {}", other))),
+        };
+        Ok(FundFlowBillRow {
+            accounting_time: r.datetime("记账时间")?,
+            biz_order_id: r.string("微信支付业务单号")?,
+            flow_id: r.string("资金流水单号")?,
+            biz_name: r.string("业务名称")?,
+            biz_type: r.string("业务类型")?,
+            flow_type,
+            amount: r.amount("收支金额")?,
+            balance: r.amount("账户结余")?,
+            applicant: r.string("资金变更提交申请人")?,
+            remark: r.optional("备注"),
+            voucher_no: r.optional("业务凭证号"),
+        })
+    }
+}
+
+/// 资金流水的收支类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FundFlowType {
+    /// 收入
+    Income,
+    /// 支出
+    Expense,
+}
+
+/// 资金账单的汇总数据
+#[derive(Debug, Clone)]
+pub struct FundFlowBillSummary {
+    /// 资金流水总笔数
+    pub total_count: u64,
+    /// 收入笔数
+    pub income_count: u64,
+    /// 收入金额，单位为分。
+    pub income_amount: i64,
+    /// 支出笔数
+    pub expense_count: u64,
+    /// 支出金额，单位为分。
+    pub expense_amount: i64,
+}
+
+impl FromBillRecord for FundFlowBillSummary {
+    fn from_record(r: &BillRecord<'_>) -> Result<FundFlowBillSummary> {
+        Ok(FundFlowBillSummary {
+            total_count: r.parse("资金流水总笔数")?,
+            income_count: r.parse("收入笔数")?,
+            income_amount: r.amount("收入金额")?,
+            expense_count: r.parse("支出笔数")?,
+            expense_amount: r.amount("支出金额")?,
+        })
+    }
+}
+
+/// 解析表头，去掉金额列名中的 `(元)`。
+fn split_header(line: &str) -> Vec<String> {
+    line.trim_end_matches('\r')
+        .split(',')
+        .map(|c| c.trim().replace("(元)", "").replace("（元）", ""))
+        .collect()
+}
+
+/// 将以元为单位、最多两位小数的金额转换为分。
+fn parse_fen(value: &str) -> Option<i64> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(v) => (-1, v),
+        None => (1, value),
+    };
+    let (yuan, fen) = value.split_once('.').unwrap_or((value, ""));
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if yuan.is_empty() || fen.len() > 2 || !is_digits(yuan) || !is_digits(fen) {
+        return None;
+    }
+    let yuan: i64 = yuan.parse().ok()?;
+    let fen: i64 = format!("{:0<2}", fen).parse().ok()?;
+    Some(sign * (yuan * 100 + fen))
+}
+
+/// 账单中的退款状态。除接口中的取值外，还可能是 REFUNDCLOSE、CHANGE。
+fn parse_refund_status(status: &str) -> Result<RefundStatus> {
+    match status {
+        "SUCCESS" => Ok(RefundStatus::Success),
+        "PROCESSING" => Ok(RefundStatus::Processing),
+        "CLOSED" | "REFUNDCLOSE" => Ok(RefundStatus::Closed),
+        "ABNORMAL" | "CHANGE" => Ok(RefundStatus::Abnormal),
+        _ => Err(bill_error(format!("invalid 退款状态: {}", status))),
+    }
+}
+
+fn bill_error(message: String) -> Error {
+    Error::Serde(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::*;
+    use crate::partner::shou_fu_tong;
+    use chrono::Local;
+    use http::header::AUTHORIZATION;
+    use serde_json::json;
+
+    const TRADE_BILL: &str = "\
+交易时间,公众账号ID,商户号,特约商户号,设备号,微信订单号,商户订单号,用户标识,交易类型,交易状态,付款银行,货币种类,应结订单金额,代金券金额,微信退款单号,商户退款单号,退款金额,充值券退款金额,退款类型,退款状态,商品名称,商户数据包,手续费,费率,订单金额,申请退款金额,费率备注
+`2024-05-21 11:50:55,`wx8888888888888888,`1900000001,`0,`,`4200000001202405211234567890,`order-1,`oUpF8uMuAJO_M2pxb1Q9zNjWeS6o,`JSAPI,`SUCCESS,`OTHERS,`CNY,`1.00,`0.00,`0,`0,`0.00,`0.00,`,`,`咖啡,豆浆,`,`0.00600,`0.60%,`1.00,`0.00,`
+`2024-05-21 12:00:01,`wx8888888888888888,`1900000001,`0,`,`4200000001202405211234567890,`order-1,`oUpF8uMuAJO_M2pxb1Q9zNjWeS6o,`JSAPI,`REFUND,`OTHERS,`CNY,`0.00,`0.00,`50300000012024052100000000001,`refund-1,`0.30,`0.00,`ORIGINAL,`SUCCESS,`咖啡,豆浆,`,`-0.00180,`0.60%,`0.00,`0.30,`
+总交易单数,应结订单总金额,退款总金额,充值券退款总金额,手续费总金额,订单总金额,申请退款总金额
+`2,`1.00,`0.30,`0.00,`0.00420,`1.00,`0.30
+";
+
+    #[test]
+    fn test_parse_trade_bill() {
+        let bill = TradeBill::parse(TRADE_BILL).unwrap();
+        let rows: Vec<TradeBillRow> = bill.rows().collect::<Result<_>>().unwrap();
+        assert_eq!(rows.len(), 2);
+
+        let paid = &rows[0];
+        assert_eq!(paid.out_trade_no, "order-1");
+        assert_eq!(paid.trade_state, TradeState::Success);
+        assert_eq!(paid.trade_type, TradeType::JsApi);
+        assert_eq!(paid.settlement_total, 100);
+        assert_eq!(paid.description, "咖啡,豆浆");
+        assert_eq!(paid.fee, "0.00600");
+        assert!(!paid.is_refund());
+        assert_eq!(paid.refund_status, None);
+
+        let refund = &rows[1];
+        assert!(refund.is_refund());
+        assert_eq!(refund.out_refund_no.as_deref(), Some("refund-1"));
+        assert_eq!(refund.settlement_refund, Some(30));
+        assert_eq!(refund.refund_status, Some(RefundStatus::Success));
+
+        let summary = bill.summary().unwrap();
+        assert_eq!(summary.total_count, 2);
+        assert_eq!(summary.settlement_total, 100);
+        assert_eq!(summary.refund_total, 30);
+        assert_eq!(summary.fee_total, "0.00420");
+    }
+
+    #[test]
+    fn test_parse_fund_flow_bill() {
+        let content = "\u{feff}记账时间,微信支付业务单号,资金流水单号,业务名称,业务类型,收支类型,收支金额（元）,账户结余（元）,资金变更提交申请人,备注,业务凭证号\r
+`2024-05-21 11:50:56,`4200000001202405211234567890,`4200000001202405211234567890,`交易,`交易,`收入,`1.00,`101.00,`system,`,`order-1\r
+资金流水总笔数,收入笔数,收入金额,支出笔数,支出金额\r
+`1,`1,`1.00,`0,`0.00\r
+";
+        let bill = FundFlowBill::parse(content).unwrap();
+        let rows: Vec<FundFlowBillRow> = bill.rows().collect::<Result<_>>().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].flow_type, FundFlowType::Income);
+        assert_eq!(rows[0].amount, 100);
+        assert_eq!(rows[0].balance, 10100);
+        assert_eq!(rows[0].voucher_no.as_deref(), Some("order-1"));
+        let summary = bill.summary().unwrap();
+        assert_eq!(summary.income_count, 1);
+        assert_eq!(summary.income_amount, 100);
+        assert_eq!(summary.expense_amount, 0);
+    }
+
+    #[test]
+    fn test_invalid_row() {
+        let content = TRADE_BILL.replace("`1.00,`0.00,`0,`0", "`1.0.0,`0.00,`0,`0");
+        let bill = TradeBill::parse(content).unwrap();
+        let rows: Vec<Result<TradeBillRow>> = bill.rows().collect();
+        assert_eq!(rows.len(), 2);
+        let err = rows[0].as_ref().unwrap_err().to_string();
+        assert!(err.contains("line 2"), "{}", err);
+        assert!(rows[1].is_ok());
+    }
+
+    #[test]
+    fn test_parse_fen() {
+        assert_eq!(parse_fen("0.01"), Some(1));
+        assert_eq!(parse_fen("12.3"), Some(1230));
+        assert_eq!(parse_fen("5"), Some(500));
+        assert_eq!(parse_fen("-0.30"), Some(-30));
+        assert_eq!(parse_fen("0.001"), None);
+        assert_eq!(parse_fen(""), None);
+        assert_eq!(parse_fen("1,000.00"), None);
+    }
+
+    #[test]
+    fn test_verify_hash() {
+        // sha1("abc")
+        let hash = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        verify_hash(b"abc", "SHA1", hash).unwrap();
+        verify_hash(b"abc", "SHA1", &hash.to_uppercase()).unwrap();
+        assert!(matches!(
+            verify_hash(b"abd", "SHA1", hash),
+            Err(Error::Verification(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bill() -> anyhow::Result<()> {
+        use crate::bill::{
+            FundFlowBillParams, FundFlowType, TarType, TradeBillParams, TradeBillType,
+        };
+        use shou_fu_tong::bill::SubMchFundFlowBillParams;
+
+        let server = test_server();
+        let client = test_client(&server).await;
+        client
+            .jsapi_create_trade(&jsapi_params("1217752501201407033233368018"))
+            .await?;
+        server.pay("1217752501201407033233368018")?;
+        client
+            .native_create_trade(&native_params("1217752501201407033233368019"))
+            .await?;
+        server.pay("1217752501201407033233368019")?;
+        let params = refund_params("1217752501201407033233368018", "refund_1", 30);
+        client.apply_refund(&params).await?;
+        server.complete_refund("refund_1", RefundStatus::Success)?;
+        let today = Local::now().date_naive();
+
+        let mut params = TradeBillParams::new(today);
+        params.tar_type = Some(TarType::Gzip);
+        let bill = client.download_trade_bill(&params).await?;
+        let rows = bill.rows().collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows.len(), 3);
+        let refund = rows.iter().find(|r| r.is_refund()).unwrap();
+        assert_eq!(refund.out_trade_no, "1217752501201407033233368018");
+        assert_eq!(refund.out_refund_no.as_deref(), Some("refund_1"));
+        assert_eq!(refund.settlement_refund, Some(30));
+        assert_eq!(refund.refund_status, Some(RefundStatus::Success));
+        let paid = rows
+            .iter()
+            .find(|r| r.trade_type == TradeType::Native)
+            .unwrap();
+        assert_eq!(paid.trade_state, TradeState::Success);
+        assert_eq!(paid.settlement_total, 100);
+        assert_eq!(paid.fee, "0.00600");
+        let summary = bill.summary()?;
+        assert_eq!(summary.total_count, 3);
+        assert_eq!(summary.settlement_total, 200);
+        assert_eq!(summary.refund_total, 30);
+        // 下载请求经过签名
+        let download = server.requests().pop().unwrap();
+        assert_eq!(download.uri().path(), "/v3/billdownload/file");
+        assert!(download.headers().contains_key(AUTHORIZATION));
+
+        params.bill_type = TradeBillType::Success;
+        let bill = client.download_trade_bill(&params).await?;
+        assert_eq!(bill.rows().count(), 2);
+
+        let bill = client
+            .download_fund_flow_bill(&FundFlowBillParams::new(today))
+            .await?;
+        let rows = bill.rows().collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2].flow_type, FundFlowType::Expense);
+        assert_eq!(rows[2].voucher_no.as_deref(), Some("refund_1"));
+        assert_eq!(rows[2].balance, 170);
+        let summary = bill.summary()?;
+        assert_eq!((summary.income_amount, summary.expense_amount), (200, 30));
+
+        // 摘要不一致
+        let mut info = client.trade_bill(&TradeBillParams::new(today)).await?;
+        info.hash_value = "0".repeat(40);
+        let err = client.download_bill(&info, None).await.unwrap_err();
+        assert!(matches!(err, Error::Verification(_)), "{}", err);
+
+        // 二级商户资金账单，文件加密
+        let combine = json!({
+            "combine_appid": "wxd678efh567hg6787",
+            "combine_mchid": "1900000001",
+            "combine_out_trade_no": "P20150806125346",
+            "sub_orders": [{
+                "mchid": "1900000001",
+                "sub_mchid": "1900000109",
+                "attach": "深圳分店",
+                "amount": { "total_amount": 88, "currency": "CNY" },
+                "out_trade_no": "20150806125346",
+                "description": "腾讯充值中心-QQ会员充值",
+            }],
+            "combine_payer_info": { "openid": "oUpF8uMuAJO_M2pxb1Q9zNjWeS6o" },
+            "notify_url": NOTIFY_URL,
+        });
+        let url = format!("{}/combine-transactions/jsapi", client.v3_url());
+        let req = client.client.post(url).json(&combine).build()?;
+        client.execute(req, None).await?;
+        server.pay_combine("P20150806125346")?;
+        let params = SubMchFundFlowBillParams {
+            sub_mchid: "1900000109".to_string(),
+            bill_date: today,
+            account_type: Default::default(),
+            tar_type: Some(TarType::Gzip),
+        };
+        let bills = shou_fu_tong::bill::download_sub_mch_fund_flow_bill(&client, &params).await?;
+        assert_eq!(bills.len(), 1);
+        let rows = bills[0].rows().collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].amount, 88);
+        assert_eq!(rows[0].voucher_no.as_deref(), Some("20150806125346"));
+
+        // 通过 HTTP 访问时，下载地址指向模拟服务
+        let handle = server.start().await?;
+        let client = WechatPayClient::builder()
+            .mch_credential(test_credential())
+            .base_url(handle.base_url())
+            .platform_certificate(server.platform_certificate())
+            .build()
+            .await?;
+        let bill = client
+            .download_trade_bill(&TradeBillParams::new(today))
+            .await?;
+        assert_eq!(bill.rows().count(), 4);
+        Ok(())
+    }
+}
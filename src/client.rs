@@ -1,17 +1,53 @@
-use crate::credential::MchCredential;
-use crate::error::WechatPayApiError;
-use crate::platform_certificate::PlatformCertificate;
-use anyhow::Result;
-use reqwest::{Client, Request, Response};
+use crate::credential::{MchCredential, Merchant, Signer};
+use crate::error::{Error, Result};
+use crate::middleware::{
+    self, BoxError, HttpService, LayerStack, SignLayer, Verifier, VerifyLayer, WechatPayService,
+};
+use crate::platform_certificate::{self, PlatformCertificate, PlatformKeyring, PlatformPublicKey};
+use crate::replay::ReplayProtection;
+use crate::retry::RetryPolicy;
+use crate::sensitive;
+use crate::transport::{HttpTransport, Transport};
+use arc_swap::ArcSwap;
+use chrono::Local;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Request, RequestBuilder, Response};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tower::{Layer, Service, ServiceBuilder, ServiceExt};
 
 #[derive(Debug, Clone)]
 pub struct WechatPayClient {
+    /// 仅用于构造请求，请求通过 `transport` 发送。
     pub(crate) client: Client,
-    pub(crate) mch_credential: MchCredential,
-    pub(crate) platform_certificate: PlatformCertificate,
+    pub(crate) transport: HttpTransport,
+    pub(crate) merchant: Merchant,
+    /// 平台证书。所有 clone 共享同一份，更新时整体替换，读取时无需加锁。
+    pub(crate) platform_keyring: Arc<ArcSwap<PlatformKeyring>>,
+    /// 响应验签。持有平台证书自动更新的任务。
+    pub(crate) verifier: Verifier,
+    /// 签名、用户添加的中间件、验签及 HTTP 传输组成的 service。
+    pub(crate) service: WechatPayService,
+    /// 请求重试策略。默认不重试。
+    pub(crate) retry_policy: RetryPolicy,
 }
 
-pub(crate) const BASE_URL: &str = "https://api.mch.weixin.qq.com/v3";
+/// 微信支付 API 的默认域名。
+pub const DEFAULT_BASE_URL: &str = "https://api.mch.weixin.qq.com";
+
+/// 微信支付 API 的备用域名，用于容灾切换。
+pub const BACKUP_BASE_URL: &str = "https://api2.mch.weixin.qq.com";
+
+/// 默认的 User-Agent。
+const DEFAULT_USER_AGENT: &str = concat!("wechatpay-rust/", env!("CARGO_PKG_VERSION"));
+
+/// 遇到未知的 `Wechatpay-Serial` 时，两次按需更新平台证书的最小间隔。
+/// 避免伪造的序列号导致频繁调用证书下载接口。
+const ON_DEMAND_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
 
 impl WechatPayClient {
     pub fn new(
@@ -19,51 +55,752 @@ impl WechatPayClient {
         mch_credential: MchCredential,
         platform_certificate: PlatformCertificate,
     ) -> Result<WechatPayClient> {
-        let client = Client::builder().user_agent(ua).build()?;
-        Ok(WechatPayClient {
+        mch_credential.validate()?;
+        let merchant = Merchant::from(mch_credential);
+        let client = Client::builder().user_agent(&ua).build()?;
+        let transport = HttpTransport::new(Arc::new(client.clone()), &ua, DEFAULT_BASE_URL, None)?;
+        let platform_keyring = Arc::new(ArcSwap::from_pointee(platform_certificate.into()));
+        let verifier = Verifier::new(platform_keyring.clone(), None, ReplayProtection::default());
+        Ok(WechatPayClient::assemble(
+            client,
+            transport,
+            merchant,
+            platform_keyring,
+            verifier,
+            LayerStack::default(),
+            RetryPolicy::default(),
+        ))
+    }
+
+    /// 组装 tower service: VerifyLayer -> SignLayer -> 用户添加的 layer -> HTTP 传输。
+    fn assemble(
+        client: Client,
+        transport: HttpTransport,
+        merchant: Merchant,
+        platform_keyring: Arc<ArcSwap<PlatformKeyring>>,
+        verifier: Verifier,
+        layers: LayerStack,
+        retry_policy: RetryPolicy,
+    ) -> WechatPayClient {
+        let service = ServiceBuilder::new()
+            .layer(VerifyLayer::with_verifier(verifier.clone()))
+            .layer(SignLayer::with_merchant(merchant.clone()))
+            .service(layers.apply(HttpService::new(transport.clone())));
+        WechatPayClient {
             client,
-            mch_credential,
-            platform_certificate,
-        })
+            transport,
+            merchant,
+            platform_keyring,
+            verifier,
+            service: WechatPayService::new(service),
+            retry_policy,
+        }
+    }
+
+    /// 以 builder 方式构造 WechatPayClient。
+    pub fn builder() -> WechatPayClientBuilder {
+        WechatPayClientBuilder::default()
+    }
+
+    /// 接口的根地址，默认为 [`DEFAULT_BASE_URL`]。
+    pub fn base_url(&self) -> &str {
+        self.transport.base_url()
+    }
+
+    /// APIv3 接口的根地址，即 `{base_url}/v3`。
+    pub(crate) fn v3_url(&self) -> String {
+        format!("{}/v3", self.base_url())
     }
 
     /// get mch_id
     pub fn get_mch_id(&self) -> &str {
-        &self.mch_credential.mch_id
+        &self.merchant.mch_id
+    }
+
+    /// 返回使用指定重试策略的 client，用于单次调用覆盖 client 的重试策略。
+    /// 返回的 client 与原 client 共享连接池及平台证书。
+    ///
+    /// ```ignore
+    /// let res = wechatpay_client
+    ///     .with_retry_policy(RetryPolicy::new(5))
+    ///     .apply_refund(&refund_params)
+    ///     .await?;
+    /// ```
+    pub fn with_retry_policy(&self, retry_policy: RetryPolicy) -> WechatPayClient {
+        WechatPayClient {
+            retry_policy,
+            ..self.clone()
+        }
+    }
+
+    /// 当前的重试策略。
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
     }
 
     /// 执行 HTTP 请求
-    /// 请求发送时，先进行签名；收到响应时，先进行验签，通过后再返回。
+    /// 请求经过 tower service 发送：先进行签名；收到响应时，先进行验签，通过后再返回。
+    /// 遇到可重试的错误时，按重试策略重新签名并发送。
     /// (本 crate 未实现的接口，可以通过此方法访问)
     pub async fn execute(&self, req: Request, meta: Option<String>) -> Result<Response> {
+        self.send(req, meta, true).await
+    }
+
+    /// 执行 HTTP 请求，但不对响应验签。用于下载账单文件等响应没有签名的接口，
+    /// 调用方需自行校验响应内容。
+    pub(crate) async fn execute_unverified(&self, req: Request) -> Result<Response> {
+        self.send(req, None, false).await
+    }
+
+    async fn send(&self, req: Request, meta: Option<String>, verify: bool) -> Result<Response> {
         let mut req = req;
         // 根据 https://pay.weixin.qq.com/wiki/doc/apiv3/wechatpay/wechatpay2_0.shtml#part-1
         // 给所有请求都加上 accept header。
         req.headers_mut()
             .append("Accept", "application/json".parse().unwrap());
-        req.headers_mut().insert(
-            "Wechatpay-Serial",
-            self.platform_certificate.public_id.parse().unwrap(),
-        );
+        if !req.headers().contains_key("Wechatpay-Serial") {
+            if let Some(serial) = self.platform_keyring.load().current_serial() {
+                let value = serial
+                    .parse()
+                    .map_err(|_| Error::InvalidArgument(format!("invalid serial: {}", serial)))?;
+                req.headers_mut().insert("Wechatpay-Serial", value);
+            }
+        }
 
-        let req = self.mch_credential.sign_request(req, meta)?;
-        let res = self.client.execute(req).await?;
+        if meta.is_some() || !verify {
+            req = middleware::with_extensions(req, |ext| {
+                if let Some(meta) = meta {
+                    ext.insert(middleware::SignMeta(meta));
+                }
+                if !verify {
+                    ext.insert(middleware::SkipVerify);
+                }
+            })?
+            .0;
+        }
 
-        // 请求出错时，响应中可能不存在验签相关的字段。因此直接返回 error。
-        if !res.status().is_success() {
-            let e: WechatPayApiError = res.json().await?;
-            Err(e.into())
-        } else {
-            self.verify_response(res).await
+        let mut attempt = 1;
+        loop {
+            // 流式 body(如 multipart)无法复制，只能发送一次。
+            let retry_req = match req.try_clone() {
+                Some(r) if attempt < self.retry_policy.max_attempts() => Some(r),
+                _ => None,
+            };
+            let result = self.service.clone().oneshot(req).await;
+            match (result, retry_req) {
+                (Err(e), Some(r)) if self.retry_policy.should_retry(attempt, &e) => {
+                    let backoff = self.retry_policy.backoff_for(attempt);
+                    log::debug!(
+                        "wechatpay request failed (attempt {}), retry in {:?}: {}",
+                        attempt,
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    req = r;
+                    attempt += 1;
+                }
+                (result, _) => return result,
+            }
         }
     }
 
     /// 对响应进行数字签名验证。
+    /// 根据响应的 `Wechatpay-Serial` 选择对应的平台证书。
+    /// 若开启了平台证书自动更新，遇到未知的序列号时会先更新平台证书再验签。
     pub(crate) async fn verify_response(&self, res: Response) -> Result<Response> {
-        self.platform_certificate.verify_response(res).await
+        self.verifier.verify_response(res).await
     }
 
+    /// 使用当前平台证书加密敏感信息。
+    /// 所用证书与请求的 `Wechatpay-Serial` 一致。
     pub fn encrypt(&self, data: &str) -> Result<String> {
-        self.platform_certificate.encrypt(data.as_bytes())
+        let (_, ciphertext) = self.platform_keyring.load().encrypt(data.as_bytes())?;
+        Ok(ciphertext)
+    }
+
+    /// 构造以 JSON 为 body 的请求。
+    /// body 中的 [`SensitiveString`](crate::sensitive::SensitiveString) 字段使用当前平台证书加密，
+    /// 并将所用证书的序列号设置为请求的 `Wechatpay-Serial`。
+    pub fn json_request<T: Serialize + ?Sized>(
+        &self,
+        builder: RequestBuilder,
+        body: &T,
+    ) -> Result<Request> {
+        let (body, serial) = sensitive::to_json_encrypted(self.platform_keyring.load_full(), body)?;
+        let mut builder = builder.header(CONTENT_TYPE, "application/json").body(body);
+        if let Some(serial) = serial {
+            builder = builder.header("Wechatpay-Serial", serial);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// 当前持有的平台证书。
+    pub fn platform_keyring(&self) -> Arc<PlatformKeyring> {
+        self.platform_keyring.load_full()
+    }
+
+    /// 重新下载平台证书，与已有证书合并，并移除已过期的证书。
+    pub async fn refresh_platform_certificates(&self) -> Result<()> {
+        refresh_keyring(&self.transport, &self.merchant, &self.platform_keyring).await
+    }
+}
+
+/// 重新下载平台证书，与已有证书合并，并移除已过期的证书。
+/// 证书下载不经过 tower service，而是由下载接口自身的响应完成验签。
+/// 后台更新、按需更新及手动更新可能同时进行，因此通过 `rcu` 基于最新的证书合并，
+/// 不会覆盖其他更新的结果。
+async fn refresh_keyring(
+    transport: &HttpTransport,
+    merchant: &Merchant,
+    platform_keyring: &ArcSwap<PlatformKeyring>,
+) -> Result<()> {
+    let downloaded = platform_certificate::download_certificates(transport, merchant).await?;
+    let now = Local::now();
+    platform_keyring.rcu(|current| {
+        let mut keyring = PlatformKeyring::clone(current);
+        keyring.merge(downloaded.clone());
+        keyring.remove_expired(now);
+        keyring
+    });
+    Ok(())
+}
+
+/// 平台证书自动更新。
+/// 持有后台定时更新的任务，最后一个持有它的 WechatPayClient 被 drop 时，任务随之结束。
+pub(crate) struct CertificateRefresher {
+    transport: HttpTransport,
+    merchant: Merchant,
+    platform_keyring: Arc<ArcSwap<PlatformKeyring>>,
+    /// 上一次按需更新的时间。同时保证同一时刻只有一个按需更新在进行。
+    last_on_demand: Mutex<Option<Instant>>,
+    task: JoinHandle<()>,
+}
+
+impl CertificateRefresher {
+    /// 启动后台任务，每隔 `interval` 更新一次平台证书。
+    fn spawn(
+        transport: HttpTransport,
+        merchant: Merchant,
+        platform_keyring: Arc<ArcSwap<PlatformKeyring>>,
+        interval: Duration,
+    ) -> CertificateRefresher {
+        let task = tokio::spawn({
+            let transport = transport.clone();
+            let merchant = merchant.clone();
+            let platform_keyring = platform_keyring.clone();
+            async move {
+                let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+                loop {
+                    ticker.tick().await;
+                    if let Err(e) = refresh_keyring(&transport, &merchant, &platform_keyring).await
+                    {
+                        log::warn!("failed to refresh wechatpay platform certificates: {}", e);
+                    }
+                }
+            }
+        });
+        CertificateRefresher {
+            transport,
+            merchant,
+            platform_keyring,
+            last_on_demand: Mutex::new(None),
+            task,
+        }
+    }
+
+    /// 遇到未知的 `Wechatpay-Serial` 时，按需更新平台证书。
+    pub(crate) async fn refresh_on_demand(&self, serial: &str) -> Result<()> {
+        let mut last = self.last_on_demand.lock().await;
+        // 等待锁期间，其他请求可能已经完成了更新。
+        if self.platform_keyring.load().contains(serial) {
+            return Ok(());
+        }
+        if last.is_some_and(|t| t.elapsed() < ON_DEMAND_REFRESH_INTERVAL) {
+            return Ok(());
+        }
+        *last = Some(Instant::now());
+        refresh_keyring(&self.transport, &self.merchant, &self.platform_keyring).await
+    }
+}
+
+impl Drop for CertificateRefresher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// WechatPayClient 的 builder。
+///
+/// ```ignore
+/// let wechatpay_client = WechatPayClient::builder()
+///     .mch_credential(credential)
+///     .fetch_platform_certificates()
+///     .build()
+///     .await?;
+/// ```
+#[derive(Default)]
+pub struct WechatPayClientBuilder {
+    user_agent: Option<String>,
+    transport: Option<Arc<dyn Transport>>,
+    base_url: Option<String>,
+    backup_base_url: Option<String>,
+    merchant: Option<Merchant>,
+    platform_keyring: PlatformKeyring,
+    fetch_platform_certificates: bool,
+    auto_refresh_interval: Option<Duration>,
+    replay_protection: ReplayProtection,
+    retry_policy: RetryPolicy,
+    layers: LayerStack,
+}
+
+impl std::fmt::Debug for WechatPayClientBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WechatPayClientBuilder")
+            .field("user_agent", &self.user_agent)
+            .field("custom_transport", &self.transport.is_some())
+            .field("base_url", &self.base_url)
+            .field("backup_base_url", &self.backup_base_url)
+            .field("platform_keyring", &self.platform_keyring)
+            .field(
+                "fetch_platform_certificates",
+                &self.fetch_platform_certificates,
+            )
+            .field("auto_refresh_interval", &self.auto_refresh_interval)
+            .field("replay_protection", &self.replay_protection)
+            .field("retry_policy", &self.retry_policy)
+            .field("layers", &self.layers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WechatPayClientBuilder {
+    /// 设置请求的 User-Agent。微信支付要求所有请求都带有 User-Agent。
+    pub fn user_agent(mut self, ua: impl Into<String>) -> Self {
+        self.user_agent = Some(ua.into());
+        self
+    }
+
+    /// 设置发送请求所用的传输。默认使用 `reqwest::Client`。
+    /// 可以传入预先配置了代理、mTLS、DNS 等的 `reqwest::Client`，
+    /// 或测试用的 [`MockTransport`](crate::transport::MockTransport)。
+    pub fn transport(mut self, transport: impl Transport) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// 设置接口的根地址，默认为 [`DEFAULT_BASE_URL`]。
+    /// 可指向本地的测试服务，如 `http://127.0.0.1:8080`。
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// 设置备用域名，开启容灾切换：请求主域名遇到连接错误或 5xx 响应时，
+    /// 使用备用域名重新发送一次。微信支付的备用域名为 [`BACKUP_BASE_URL`]。
+    pub fn backup_base_url(mut self, backup_base_url: impl Into<String>) -> Self {
+        self.backup_base_url = Some(backup_base_url.into());
+        self
+    }
+
+    /// 设置商户的证书和密钥。与 [`signer`](Self::signer) 二者必须设置其一。
+    pub fn mch_credential(mut self, mch_credential: MchCredential) -> Self {
+        self.merchant = Some(Merchant::from(mch_credential));
+        self
+    }
+
+    /// 设置商户号、APIv3 密钥及持有商户私钥的签名器，用于商户私钥保存在 HSM、KMS 等处，
+    /// 不加载到本进程的场景。请求签名、调起支付签名及敏感信息解密均由签名器完成。
+    pub fn signer(
+        mut self,
+        mch_id: impl Into<String>,
+        mch_api_v3_key: impl Into<String>,
+        signer: impl Signer + 'static,
+    ) -> Self {
+        self.merchant = Some(Merchant::new(
+            mch_id.into(),
+            mch_api_v3_key.into(),
+            Arc::new(signer),
+        ));
+        self
+    }
+
+    /// 加入已有的平台证书。可多次调用，以同时持有多个证书。
+    pub fn platform_certificate(mut self, platform_certificate: PlatformCertificate) -> Self {
+        self.platform_keyring.insert(platform_certificate);
+        self
+    }
+
+    /// 加入微信支付公钥(公钥模式)。
+    /// 与平台证书同时设置时，为切换期间的混合模式，两种 `Wechatpay-Serial` 均可验签。
+    pub fn platform_public_key(mut self, public_key: PlatformPublicKey) -> Self {
+        self.platform_keyring.insert_public_key(public_key);
+        self
+    }
+
+    /// 加入一组已有的平台证书及公钥。
+    pub fn platform_keyring(mut self, platform_keyring: PlatformKeyring) -> Self {
+        self.platform_keyring.merge(platform_keyring);
+        self
+    }
+
+    /// 构造时调用平台证书下载接口，获取全部平台证书。
+    /// 下载的证书会与已设置的证书合并。
+    pub fn fetch_platform_certificates(mut self) -> Self {
+        self.fetch_platform_certificates = true;
+        self
+    }
+
+    /// 开启平台证书自动更新，隐含 `fetch_platform_certificates`。
+    /// 后台任务每隔 `interval` 重新下载平台证书，并移除已过期的证书；
+    /// 响应或通知的 `Wechatpay-Serial` 未知时，也会立即更新一次。
+    /// 后台任务运行在当前的 tokio runtime 上。
+    pub fn auto_refresh_platform_certificates(mut self, interval: Duration) -> Self {
+        self.fetch_platform_certificates = true;
+        self.auto_refresh_interval = Some(interval);
+        self
+    }
+
+    /// 设置防重放配置，包括响应及通知的时间戳检查、通知随机串及通知 ID 的存储。
+    /// 默认为 [`ReplayProtection::default`]。
+    pub fn replay_protection(mut self, replay_protection: ReplayProtection) -> Self {
+        self.replay_protection = replay_protection;
+        self
+    }
+
+    /// 设置请求重试策略。默认不重试。
+    /// 单次调用可通过 [`WechatPayClient::with_retry_policy`] 覆盖。
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// 添加 tower layer，如超时、限流、tracing、metrics 等。
+    /// layer 位于签名、验签与 HTTP 传输之间：请求已签名，响应尚未验签。
+    /// 与 `tower::ServiceBuilder` 一致，先添加的 layer 位于外层。
+    ///
+    /// ```ignore
+    /// let wechatpay_client = WechatPayClient::builder()
+    ///     .mch_credential(credential)
+    ///     .fetch_platform_certificates()
+    ///     .layer(TimeoutLayer::new(Duration::from_secs(10)))
+    ///     .layer(ConcurrencyLimitLayer::new(64))
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<HttpService> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Response> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers.push(layer);
+        self
+    }
+
+    /// 构造 WechatPayClient。
+    /// 若设置了 `fetch_platform_certificates`，会先下载平台证书，因此为 async 方法。
+    pub async fn build(self) -> Result<WechatPayClient> {
+        let merchant = self.merchant.ok_or_else(|| {
+            Error::InvalidArgument("one of `mch_credential` or `signer` is required".to_string())
+        })?;
+        merchant.validate()?;
+        let ua = self
+            .user_agent
+            .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+        let client = Client::builder().user_agent(&ua).build()?;
+        let transport = self.transport.unwrap_or_else(|| Arc::new(client.clone()));
+        let base_url = self.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL);
+        let transport =
+            HttpTransport::new(transport, &ua, base_url, self.backup_base_url.as_deref())?;
+
+        let mut platform_keyring = self.platform_keyring;
+        if self.fetch_platform_certificates {
+            let downloaded =
+                platform_certificate::download_certificates(&transport, &merchant).await?;
+            platform_keyring.merge(downloaded);
+        }
+        if platform_keyring.is_empty() {
+            return Err(Error::InvalidArgument(
+                "one of `platform_certificate`, `platform_public_key` or `fetch_platform_certificates` is required".to_string(),
+            ));
+        }
+
+        let platform_keyring = Arc::new(ArcSwap::from_pointee(platform_keyring));
+        let certificate_refresher = self.auto_refresh_interval.map(|interval| {
+            Arc::new(CertificateRefresher::spawn(
+                transport.clone(),
+                merchant.clone(),
+                platform_keyring.clone(),
+                interval,
+            ))
+        });
+        let verifier = Verifier::new(
+            platform_keyring.clone(),
+            certificate_refresher,
+            self.replay_protection,
+        );
+        Ok(WechatPayClient::assemble(
+            client,
+            transport,
+            merchant,
+            platform_keyring,
+            verifier,
+            self.layers,
+            self.retry_policy,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::mock::fixtures::{
+        jsapi_params, test_credential, test_platform_certificate, test_server, PLATFORM_SERIAL,
+    };
+    use crate::mock::MockServer;
+    use std::sync::Mutex as StdMutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tower::ServiceExt;
+
+    /// 本地 HTTP 服务，依次返回给定的错误响应，并记录每个请求的 `Authorization`。
+    async fn serve(responses: Vec<(u16, &'static str)>) -> (String, Arc<StdMutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let authorizations = Arc::new(StdMutex::new(Vec::new()));
+        let recorded = authorizations.clone();
+        tokio::spawn(async move {
+            for (status, code) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 8192];
+                let n = stream.read(&mut buf).await.unwrap();
+                let head = String::from_utf8_lossy(&buf[..n]).to_string();
+                let authorization = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("authorization: "))
+                    .unwrap_or_default()
+                    .to_string();
+                recorded.lock().unwrap().push(authorization);
+
+                let body = format!(r#"{{"code":"{}","message":"error"}}"#, code);
+                let res = format!(
+                    "HTTP/1.1 {} Error\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}", addr), authorizations)
+    }
+
+    fn builder() -> WechatPayClientBuilder {
+        WechatPayClient::builder()
+            .mch_credential(test_credential())
+            .platform_certificate(test_platform_certificate())
+    }
+
+    async fn client(retry_policy: RetryPolicy) -> WechatPayClient {
+        builder().retry_policy(retry_policy).build().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let policy =
+            RetryPolicy::new(5).backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let client = client(policy).await;
+        let (base_url, authorizations) = serve(vec![
+            (500, "SYSTEM_ERROR"),
+            (429, "FREQUENCY_LIMITED"),
+            (400, "PARAM_ERROR"),
+        ])
+        .await;
+
+        let req = client
+            .client
+            .get(format!("{}/v3/pay/transactions/id/1", base_url));
+        let err = client
+            .execute(req.build().unwrap(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::ParamError));
+
+        // 每次重试都重新签名。
+        let authorizations = authorizations.lock().unwrap().clone();
+        assert_eq!(authorizations.len(), 3);
+        assert!(authorizations
+            .iter()
+            .all(|a| a.starts_with("WECHATPAY2-SHA256-RSA2048")));
+        assert_ne!(authorizations[0], authorizations[1]);
+        assert_ne!(authorizations[1], authorizations[2]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_per_call() {
+        let client = client(RetryPolicy::new(3).jitter(false)).await;
+        let (base_url, authorizations) = serve(vec![(502, "SYSTEM_ERROR")]).await;
+
+        let req = client
+            .client
+            .get(format!("{}/v3/pay/transactions/id/1", base_url));
+        let err = client
+            .with_retry_policy(RetryPolicy::none())
+            .execute(req.build().unwrap(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::SystemError));
+        assert_eq!(authorizations.lock().unwrap().len(), 1);
+        assert_eq!(client.retry_policy().max_attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_layer() {
+        // 记录经过 layer 的请求是否已签名。
+        let signed = Arc::new(StdMutex::new(Vec::new()));
+        let recorded = signed.clone();
+        let layer = tower::layer::layer_fn(move |inner: HttpService| {
+            let recorded = recorded.clone();
+            tower::service_fn(move |req: Request| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(req.headers().contains_key("Authorization"));
+                inner.clone().oneshot(req)
+            })
+        });
+        let client = builder()
+            .layer(layer)
+            .retry_policy(RetryPolicy::new(2).backoff(Duration::ZERO, Duration::ZERO))
+            .build()
+            .await
+            .unwrap();
+        let (base_url, _) = serve(vec![(500, "SYSTEM_ERROR"), (403, "NO_AUTH")]).await;
+
+        let req = client
+            .client
+            .get(format!("{}/v3/pay/transactions/id/1", base_url));
+        let err = client
+            .execute(req.build().unwrap(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::NoAuth));
+        assert_eq!(*signed.lock().unwrap(), vec![true, true]);
+    }
+
+    #[tokio::test]
+    async fn test_layer_error() {
+        let layer = tower::layer::layer_fn(|_: HttpService| {
+            tower::service_fn(|_: Request| async {
+                Err::<Response, _>(BoxError::from("rate limited"))
+            })
+        });
+        let client = builder().layer(layer).build().await.unwrap();
+
+        let req = client
+            .client
+            .get("http://127.0.0.1:1/v3/pay/transactions/id/1");
+        let err = client
+            .execute(req.build().unwrap(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Service(_)));
+    }
+
+    /// 平台证书下载接口的调用次数。
+    fn certificate_downloads(server: &MockServer) -> usize {
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.uri().path() == "/v3/certificates")
+            .count()
+    }
+
+    /// 开启自动更新的客户端。构造时已下载一次证书，之后将持有的证书替换为给定的证书，
+    /// 模拟微信支付更换了平台证书。证书均使用模拟服务的平台公钥。
+    async fn refreshing_client(
+        server: &MockServer,
+        certificates: &[(&str, Option<chrono::DateTime<Local>>)],
+    ) -> WechatPayClient {
+        let client = WechatPayClient::builder()
+            .mch_credential(test_credential())
+            .transport(server.clone())
+            .auto_refresh_platform_certificates(Duration::from_secs(3600))
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(certificate_downloads(server), 1);
+        let mut keyring = PlatformKeyring::new();
+        for (serial, expire_time) in certificates {
+            keyring.insert(PlatformCertificate {
+                public_id: serial.to_string(),
+                expire_time: *expire_time,
+                ..server.platform_certificate()
+            });
+        }
+        client.platform_keyring.store(Arc::new(keyring));
+        client
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_auto_refresh() {
+        let server = test_server();
+        let expired = Local::now() - chrono::Duration::days(1);
+        let client = refreshing_client(&server, &[("OLD", None), ("EXPIRED", Some(expired))]).await;
+
+        // 未到更新时间
+        tokio::time::sleep(Duration::from_secs(3599)).await;
+        assert_eq!(certificate_downloads(&server), 1);
+
+        // 定时更新：合并新证书，移除过期证书
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        for _ in 0..100 {
+            if client.platform_keyring().contains(PLATFORM_SERIAL) {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(certificate_downloads(&server), 2);
+        let keyring = client.platform_keyring();
+        assert!(keyring.contains(PLATFORM_SERIAL));
+        assert!(keyring.contains("OLD"));
+        assert!(!keyring.contains("EXPIRED"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_on_demand() {
+        let server = test_server();
+        let client = refreshing_client(&server, &[("OLD", None)]).await;
+
+        // 并发的请求遇到未知的序列号，只下载一次证书
+        let params = ["on_demand_1", "on_demand_2", "on_demand_3"].map(jsapi_params);
+        let (a, b, c) = tokio::join!(
+            client.jsapi_create_trade(&params[0]),
+            client.jsapi_create_trade(&params[1]),
+            client.jsapi_create_trade(&params[2]),
+        );
+        assert!(a.is_ok() && b.is_ok() && c.is_ok());
+        assert_eq!(certificate_downloads(&server), 2);
+        assert!(client.platform_keyring().contains(PLATFORM_SERIAL));
+        assert!(client.platform_keyring().contains("OLD"));
+
+        // 间隔内再次遇到未知的序列号，不再下载
+        server.pay("on_demand_1").unwrap();
+        let unknown_serial = || {
+            let mut req = server.trade_notification("on_demand_1").unwrap();
+            req.headers_mut()
+                .insert("Wechatpay-Serial", "UNKNOWN".parse().unwrap());
+            req
+        };
+        assert!(client.verify_notification(unknown_serial()).await.is_err());
+        assert_eq!(certificate_downloads(&server), 2);
+
+        // 超过间隔后允许再次下载
+        tokio::time::sleep(ON_DEMAND_REFRESH_INTERVAL).await;
+        assert!(client.verify_notification(unknown_serial()).await.is_err());
+        assert_eq!(certificate_downloads(&server), 3);
     }
 }
@@ -0,0 +1,347 @@
+//! 付款码支付及撤销订单。
+//!
+//! 商户扫描用户的付款码下单。需要用户输入密码时，下单返回 `USERPAYING`，
+//! 商户应查询订单直至支付成功或失败；超时仍未确认结果的订单应撤销，
+//! 以免用户在收银员离开后完成支付。[`WechatPayClient::codepay_and_wait`] 实现了这一流程。
+
+use crate::client::WechatPayClient;
+use crate::error::{ErrorCode, Result, WechatPayApiError};
+use crate::trade::{Amount, SettleInfo, TradeQueryResponse, TradeState};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+impl WechatPayClient {
+    /// 付款码支付。支付成功时返回订单。
+    /// 用户需要输入密码时返回错误码为 [`ErrorCode::UserPaying`] 的错误，此时应查询订单确认支付结果。
+    pub async fn codepay(&self, params: &CodepayParams) -> Result<TradeQueryResponse> {
+        let url = format!("{}/pay/transactions/codepay", self.v3_url());
+        let req = self.client.post(url).json(params).build()?;
+        let res = self.execute(req, None).await?;
+        // 用户支付中时，微信支付以 202 Accepted 返回 USERPAYING，body 为错误码而非订单
+        if res.status() == StatusCode::ACCEPTED {
+            return Err(WechatPayApiError::from_response(res).await);
+        }
+        let res: TradeQueryResponse = res.json().await?;
+        Ok(res)
+    }
+
+    /// 撤销订单。
+    /// 付款码支付失败或结果未知时调用；已支付的订单撤销后，款项原路退回给用户。
+    pub async fn reverse_trade(&self, out_trade_no: &str) -> Result<()> {
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        struct ReverseTradeRequest {
+            #[serde(rename = "mchid")]
+            mch_id: String,
+        }
+
+        let url = format!(
+            "{}/pay/transactions/out-trade-no/{}/reverse",
+            self.v3_url(),
+            out_trade_no
+        );
+        let req = ReverseTradeRequest {
+            mch_id: self.merchant.mch_id.clone(),
+        };
+        let req = self.client.post(url).json(&req).build()?;
+        let _res = self.execute(req, None).await?;
+        Ok(())
+    }
+
+    /// 付款码支付，并等待支付结果。
+    /// 下单返回 `USERPAYING` 或结果未知(如系统错误、网络超时)时，按 `polling` 查询订单，
+    /// 直至支付成功或失败。支付失败或超时仍为 `USERPAYING` 时撤销订单。
+    /// 下单明确失败(如参数错误、余额不足)时直接返回错误，无需撤销。
+    /// 查询订单出现不可重试的错误时，撤销订单后返回该错误。
+    pub async fn codepay_and_wait(
+        &self,
+        params: &CodepayParams,
+        polling: &CodepayPolling,
+    ) -> Result<CodepayOutcome> {
+        match self.codepay(params).await {
+            Ok(trade) if trade.trade_state == TradeState::Success => {
+                return Ok(CodepayOutcome::Paid(Box::new(trade)))
+            }
+            Ok(_) => {}
+            Err(e) if e.api_code() == Some(&ErrorCode::UserPaying) || e.is_retryable() => {
+                log::debug!("codepay {} pending: {}", params.out_trade_no, e);
+            }
+            Err(e) => return Err(e),
+        }
+
+        let deadline = tokio::time::Instant::now() + polling.timeout;
+        let mut last_state = None;
+        let timed_out = loop {
+            if tokio::time::Instant::now() + polling.interval > deadline {
+                break true;
+            }
+            tokio::time::sleep(polling.interval).await;
+            match self.query_trade_by_out_trade_no(&params.out_trade_no).await {
+                Ok(trade) => match trade.trade_state {
+                    TradeState::Success => return Ok(CodepayOutcome::Paid(Box::new(trade))),
+                    TradeState::UserPaying => last_state = Some(TradeState::UserPaying),
+                    state => {
+                        last_state = Some(state);
+                        break false;
+                    }
+                },
+                // 订单可能尚未生成，或查询暂时失败，继续查询
+                Err(e) if e.api_code() == Some(&ErrorCode::OrderNotExist) || e.is_retryable() => {
+                    log::debug!("query codepay trade {} failed: {}", params.out_trade_no, e);
+                }
+                Err(e) => {
+                    log::warn!(
+                        "query codepay trade {} failed, reverse it: {}",
+                        params.out_trade_no,
+                        e
+                    );
+                    self.reverse_trade(&params.out_trade_no).await?;
+                    return Err(e);
+                }
+            }
+        };
+
+        log::info!(
+            "reverse codepay trade {}, last state: {:?}, timed out: {}",
+            params.out_trade_no,
+            last_state,
+            timed_out
+        );
+        self.reverse_trade(&params.out_trade_no).await?;
+        Ok(CodepayOutcome::Reversed {
+            last_state,
+            timed_out,
+        })
+    }
+}
+
+/// 付款码支付参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodepayParams {
+    /// 应用 ID
+    #[serde(rename = "appid")]
+    pub app_id: String,
+    /// 商户号
+    #[serde(rename = "mchid")]
+    pub mch_id: String,
+    /// 商品描述。不超过 127 字符。
+    pub description: String,
+    /// 商户订单号。只能是数字、大小写字母_-*组成，长度应在 [6, 32] 字符之间。
+    pub out_trade_no: String,
+    /// 附加数据，在查询API中原样返回。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub attach: Option<String>,
+    /// 订单优惠标记
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub goods_tag: Option<String>,
+    /// 电子发票入口开放标识。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub support_fapiao: Option<bool>,
+    /// 订单金额
+    pub amount: Amount,
+    /// 支付者
+    pub payer: CodepayPayer,
+    /// 场景信息
+    pub scene_info: CodepaySceneInfo,
+    /// 结算信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub settle_info: Option<SettleInfo>,
+}
+
+/// 付款码支付的支付者
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodepayPayer {
+    /// 付款码。用户付款码上的 18 位数字，每次打开付款码都会变化。
+    pub auth_code: String,
+}
+
+/// 付款码支付的场景信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodepaySceneInfo {
+    /// 商户门店信息
+    pub store_info: CodepayStoreInfo,
+    /// 商户端设备(收银终端)的 IP
+    pub device_ip: String,
+}
+
+/// 付款码支付的商户门店信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodepayStoreInfo {
+    /// 商户侧门店编号
+    pub out_id: String,
+}
+
+/// [`WechatPayClient::codepay_and_wait`] 查询订单的间隔及超时时间。
+/// 默认每 5 秒查询一次，30 秒后仍未确认结果时撤销订单。
+#[derive(Debug, Clone)]
+pub struct CodepayPolling {
+    timeout: Duration,
+    interval: Duration,
+}
+
+impl CodepayPolling {
+    /// 查询间隔为 `interval`，超过 `timeout` 后撤销订单。
+    pub fn new(timeout: Duration, interval: Duration) -> CodepayPolling {
+        CodepayPolling { timeout, interval }
+    }
+}
+
+impl Default for CodepayPolling {
+    fn default() -> CodepayPolling {
+        CodepayPolling::new(Duration::from_secs(30), Duration::from_secs(5))
+    }
+}
+
+/// 付款码支付的最终结果。
+#[derive(Debug, Clone)]
+pub enum CodepayOutcome {
+    /// 支付成功
+    Paid(Box<TradeQueryResponse>),
+    /// 支付失败或超时，订单已撤销。
+    Reversed {
+        /// 撤销前最后查询到的订单状态，未能查询到订单时为 `None`。
+        last_state: Option<TradeState>,
+        /// 是否因超时仍未确认结果而撤销。
+        timed_out: bool,
+    },
+}
+
+impl CodepayOutcome {
+    /// 是否支付成功。
+    pub fn is_paid(&self) -> bool {
+        matches!(self, CodepayOutcome::Paid(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::{test_client, test_server};
+    use crate::trade::TradeType;
+
+    fn codepay_params(out_trade_no: &str) -> CodepayParams {
+        CodepayParams {
+            app_id: "wxd678efh567hg6787".to_string(),
+            mch_id: "1900000001".to_string(),
+            description: "Image形象店-深圳腾大-QQ公仔".to_string(),
+            out_trade_no: out_trade_no.to_string(),
+            attach: None,
+            goods_tag: None,
+            support_fapiao: None,
+            amount: Amount::new_with_cny(100),
+            payer: CodepayPayer {
+                auth_code: "134650720866361395".to_string(),
+            },
+            scene_info: CodepaySceneInfo {
+                store_info: CodepayStoreInfo {
+                    out_id: "1234".to_string(),
+                },
+                device_ip: "203.0.113.10".to_string(),
+            },
+            settle_info: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_codepay() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+        let polling = CodepayPolling::new(
+            std::time::Duration::from_secs(5),
+            std::time::Duration::from_millis(10),
+        );
+        // 模拟用户稍后输入密码
+        let later = |out_trade_no: &'static str, trade_state: TradeState| {
+            let server = server.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+                server.set_trade_state(out_trade_no, trade_state).unwrap();
+            })
+        };
+
+        let err = client
+            .codepay(&codepay_params("codepay_1"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::UserPaying));
+        later("codepay_1", TradeState::Success);
+        let outcome = client
+            .codepay_and_wait(&codepay_params("codepay_1"), &polling)
+            .await?;
+        let CodepayOutcome::Paid(trade) = outcome else {
+            panic!("unexpected outcome: {:?}", outcome);
+        };
+        assert_eq!(trade.trade_type, Some(TradeType::Micropay));
+        assert_eq!(trade.trade_state, TradeState::Success);
+        // 已支付的订单再次下单直接返回订单
+        let trade = client.codepay(&codepay_params("codepay_1")).await?;
+        assert_eq!(trade.trade_state, TradeState::Success);
+
+        // 支付失败时撤销
+        later("codepay_2", TradeState::PayError);
+        let outcome = client
+            .codepay_and_wait(&codepay_params("codepay_2"), &polling)
+            .await?;
+        assert!(!outcome.is_paid());
+        assert!(matches!(
+            outcome,
+            CodepayOutcome::Reversed {
+                last_state: Some(TradeState::PayError),
+                timed_out: false
+            }
+        ));
+        let trade = client.query_trade_by_out_trade_no("codepay_2").await?;
+        assert_eq!(trade.trade_state, TradeState::Revoked);
+
+        // 超时仍在支付中时撤销
+        let polling = CodepayPolling::new(
+            std::time::Duration::from_millis(50),
+            std::time::Duration::from_millis(10),
+        );
+        let outcome = client
+            .codepay_and_wait(&codepay_params("codepay_3"), &polling)
+            .await?;
+        assert!(matches!(
+            outcome,
+            CodepayOutcome::Reversed {
+                last_state: Some(TradeState::UserPaying),
+                timed_out: true
+            }
+        ));
+        let trade = client.query_trade_by_out_trade_no("codepay_3").await?;
+        assert_eq!(trade.trade_state, TradeState::Revoked);
+
+        // 下单明确失败时不撤销
+        let mut params = codepay_params("codepay_4");
+        params.payer.auth_code = "invalid".to_string();
+        let err = client
+            .codepay_and_wait(&params, &polling)
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::ParamError));
+        let last = server.requests().pop().unwrap();
+        assert_eq!(last.uri().path(), "/v3/pay/transactions/codepay");
+
+        // 查询订单出现不可重试的错误时，撤销后返回该错误
+        let err = client
+            .codepay(&codepay_params("codepay_5"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::UserPaying));
+        server.fail_next(500, "SYSTEM_ERROR", "系统错误");
+        server.fail_next(403, "NO_AUTH", "商户无权限");
+        let err = client
+            .codepay_and_wait(&codepay_params("codepay_5"), &polling)
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::NoAuth));
+        let last = server.requests().pop().unwrap();
+        assert_eq!(
+            last.uri().path(),
+            "/v3/pay/transactions/out-trade-no/codepay_5/reverse"
+        );
+        let trade = client.query_trade_by_out_trade_no("codepay_5").await?;
+        assert_eq!(trade.trade_state, TradeState::Revoked);
+        Ok(())
+    }
+}
@@ -1,20 +1,26 @@
 //! 微信支付商户的证书和密钥。
 //! 这些信息均为敏感信息，注意确保安全，避免泄露。
 
+use crate::error::{Error, Result};
+use crate::platform_certificate::serial_number_to_hex;
 use aes_gcm::aead::{Aead, KeyInit, Payload};
 use aes_gcm::Aes256Gcm;
-use anyhow::Result;
 use base64::prelude::*;
 use bytes::{BufMut, BytesMut};
+use chrono::Local;
 use rand::Rng;
 use reqwest::header::AUTHORIZATION;
 use reqwest::Request;
-use rsa::pkcs1v15::SigningKey;
-use rsa::sha2::Sha256;
-use rsa::signature::{RandomizedSigner, SignatureEncoding};
-use rsa::RsaPrivateKey;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use rsa::sha2::{Digest, Sha256};
+use rsa::{Oaep, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha1::Sha1;
 use std::fmt::Debug;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::Path;
+use std::sync::Arc;
+use x509_cert::der::{DecodePem, Encode};
+use x509_cert::Certificate;
 
 /// 微信支付商户的证书和密钥
 #[derive(Clone)]
@@ -29,72 +35,151 @@ pub struct MchCredential {
     pub mch_api_v3_key: String,
 }
 
+/// 商户私钥的文件名。
+const KEY_FILE: &str = "apiclient_key.pem";
+/// 商户 API 证书的文件名。
+const CERT_FILE: &str = "apiclient_cert.pem";
+/// PKCS#12 格式的商户 API 证书的文件名。
+#[cfg(feature = "p12")]
+const P12_FILE: &str = "apiclient_cert.p12";
+
 impl MchCredential {
-    /// 使用商户 RSA 私钥，对请求进行数字签名。
-    /// <https://pay.weixin.qq.com/wiki/doc/apiv3/wechatpay/wechatpay4_0.shtml>
-    pub fn sign_request(&self, mut req: Request, meta: Option<String>) -> Result<Request> {
-        const SIGNATURE_TYPE: &str = "WECHATPAY2-SHA256-RSA2048";
+    /// 由 PEM 格式的商户私钥(apiclient_key.pem)及商户 API 证书(apiclient_cert.pem)构造。
+    /// 证书序列号取自证书，并检查私钥与证书是否匹配。
+    pub fn from_pem(
+        mch_id: impl Into<String>,
+        private_key_pem: &str,
+        certificate_pem: &str,
+        api_v3_key: impl Into<String>,
+    ) -> Result<MchCredential> {
+        let private_key = parse_private_key(private_key_pem)?;
+        let certificate =
+            Certificate::from_pem(certificate_pem.as_bytes()).map_err(crypto_error)?;
+        let serial_no = check_certificate(&certificate, &private_key)?;
+        MchCredential::new(mch_id, serial_no, private_key, api_v3_key)
+    }
+
+    /// 由 PEM 格式的商户私钥及证书序列号构造。
+    pub fn from_key_pem(
+        mch_id: impl Into<String>,
+        mch_certificate_serial_no: impl Into<String>,
+        private_key_pem: &str,
+        api_v3_key: impl Into<String>,
+    ) -> Result<MchCredential> {
+        let private_key = parse_private_key(private_key_pem)?;
+        MchCredential::new(mch_id, mch_certificate_serial_no, private_key, api_v3_key)
+    }
 
-        let mut msg = BytesMut::new();
+    /// 由 PKCS#12 格式的商户 API 证书(apiclient_cert.p12)构造。
+    /// 微信支付下发的证书文件，密码默认为商户号。
+    #[cfg(feature = "p12")]
+    pub fn from_p12(
+        mch_id: impl Into<String>,
+        p12: &[u8],
+        password: &str,
+        api_v3_key: impl Into<String>,
+    ) -> Result<MchCredential> {
+        use x509_cert::der::Decode;
 
-        msg.put_slice(req.method().as_str().as_bytes());
-        msg.put_u8(b'\n');
+        let keystore = p12_keystore::KeyStore::from_pkcs12(p12, password).map_err(crypto_error)?;
+        let (_, chain) = keystore
+            .private_key_chain()
+            .ok_or_else(|| Error::Crypto("no private key in PKCS#12".to_string()))?;
+        let private_key = RsaPrivateKey::from_pkcs8_der(chain.key()).map_err(crypto_error)?;
+        let certificate = chain
+            .chain()
+            .first()
+            .ok_or_else(|| Error::Crypto("no certificate in PKCS#12".to_string()))?;
+        let certificate = Certificate::from_der(certificate.as_der()).map_err(crypto_error)?;
+        let serial_no = check_certificate(&certificate, &private_key)?;
+        MchCredential::new(mch_id, serial_no, private_key, api_v3_key)
+    }
 
-        let url = if let Some(query) = req.url().query() {
-            format!("{}?{}", req.url().path(), query)
-        } else {
-            req.url().path().to_string()
-        };
-        msg.put_slice(url.as_bytes());
-        msg.put_u8(b'\n');
+    /// 从目录中读取商户私钥及商户 API 证书，即 `apiclient_key.pem` 和 `apiclient_cert.pem`。
+    /// 开启 `p12` feature 时，若目录中没有 `apiclient_key.pem`，则读取 `apiclient_cert.p12`，密码为商户号。
+    pub fn from_dir(
+        mch_id: impl Into<String>,
+        dir: impl AsRef<Path>,
+        api_v3_key: impl Into<String>,
+    ) -> Result<MchCredential> {
+        let dir = dir.as_ref();
+        #[cfg(feature = "p12")]
+        if !dir.join(KEY_FILE).exists() && dir.join(P12_FILE).exists() {
+            let mch_id = mch_id.into();
+            let p12 = read(&dir.join(P12_FILE))?;
+            return MchCredential::from_p12(mch_id.clone(), &p12, &mch_id, api_v3_key);
+        }
+        let private_key_pem = read_to_string(&dir.join(KEY_FILE))?;
+        let certificate_pem = read_to_string(&dir.join(CERT_FILE))?;
+        MchCredential::from_pem(mch_id, &private_key_pem, &certificate_pem, api_v3_key)
+    }
 
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-        msg.put_slice(format!("{}", timestamp).as_bytes());
-        msg.put_u8(b'\n');
+    /// 从环境变量中读取：
+    /// * `WECHATPAY_MCH_ID`：商户号
+    /// * `WECHATPAY_API_V3_KEY`：商户 API v3 密钥
+    /// * `WECHATPAY_CERT_DIR`：证书目录，参见 [`MchCredential::from_dir`]。设置后忽略以下变量
+    /// * `WECHATPAY_PRIVATE_KEY` 或 `WECHATPAY_PRIVATE_KEY_PATH`：商户私钥的 PEM 内容或文件路径
+    /// * `WECHATPAY_CERT_SERIAL_NO` 或 `WECHATPAY_CERT_PATH`：商户 API 证书序列号或证书文件路径
+    pub fn from_env() -> Result<MchCredential> {
+        MchCredential::from_vars(|name| std::env::var(name).ok())
+    }
 
-        let nonce_str = generate_none_str(32);
-        msg.put_slice(nonce_str.as_bytes());
-        msg.put_u8(b'\n');
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<MchCredential> {
+        let required = |name: &str| {
+            var(name).ok_or_else(|| {
+                Error::InvalidArgument(format!("environment variable `{}` is not set", name))
+            })
+        };
+        let mch_id = required("WECHATPAY_MCH_ID")?;
+        let api_v3_key = required("WECHATPAY_API_V3_KEY")?;
+        if let Some(dir) = var("WECHATPAY_CERT_DIR") {
+            return MchCredential::from_dir(mch_id, dir, api_v3_key);
+        }
 
-        match meta {
-            Some(meta) => {
-                msg.put_slice(meta.as_bytes());
-                msg.put_u8(b'\n');
+        let private_key_pem = match var("WECHATPAY_PRIVATE_KEY") {
+            Some(pem) => pem,
+            None => read_to_string(Path::new(&required("WECHATPAY_PRIVATE_KEY_PATH")?))?,
+        };
+        match var("WECHATPAY_CERT_SERIAL_NO") {
+            Some(serial_no) => {
+                MchCredential::from_key_pem(mch_id, serial_no, &private_key_pem, api_v3_key)
             }
             None => {
-                if let Some(body) = req.body() {
-                    // 由本项目保证 body.as_bytes() 返回 Some(...)。
-                    // 也即，由本项目保证 body 为  `Reusable`，而非 `Streaming`。
-                    msg.put_slice(body.as_bytes().unwrap());
-                }
-                msg.put_u8(b'\n');
+                let certificate_pem = read_to_string(Path::new(&required("WECHATPAY_CERT_PATH")?))?;
+                MchCredential::from_pem(mch_id, &private_key_pem, &certificate_pem, api_v3_key)
             }
         }
-        // if let Some(body) = req.body() {
-        //     // 由本项目保证 body.as_bytes() 返回 Some(...)。
-        //     // 也即，由本项目保证 body 为  `Reusable`，而非 `Streaming`。
-        //     msg.put_slice(body.as_bytes().unwrap());
-        // }
-        // msg.put_u8(b'\n');
-
-        let mut rng = rand::thread_rng();
-        let signing_key = SigningKey::<Sha256>::new(self.mch_rsa_private_key.clone());
-        let signature = signing_key.sign_with_rng(&mut rng, &msg).to_bytes();
-        let signature = BASE64_STANDARD.encode(&signature);
-
-        let authorization_value = format!(
-            r#"{} mchid="{}",nonce_str="{}",signature="{}",timestamp="{}",serial_no="{}""#,
-            SIGNATURE_TYPE,
-            self.mch_id,
-            nonce_str,
-            signature,
-            timestamp,
-            self.mch_certificate_serial_no
-        );
-        req.headers_mut()
-            .insert(AUTHORIZATION, authorization_value.parse().unwrap());
+    }
+
+    fn new(
+        mch_id: impl Into<String>,
+        mch_certificate_serial_no: impl Into<String>,
+        mch_rsa_private_key: RsaPrivateKey,
+        mch_api_v3_key: impl Into<String>,
+    ) -> Result<MchCredential> {
+        let credential = MchCredential {
+            mch_id: mch_id.into(),
+            mch_certificate_serial_no: mch_certificate_serial_no.into(),
+            mch_rsa_private_key,
+            mch_api_v3_key: mch_api_v3_key.into(),
+        };
+        credential.validate()?;
+        Ok(credential)
+    }
+
+    /// 检查配置是否有效：商户号及证书序列号不能为空，API v3 密钥须为 32 字节。
+    pub fn validate(&self) -> Result<()> {
+        validate(
+            &self.mch_id,
+            &self.mch_certificate_serial_no,
+            &self.mch_api_v3_key,
+        )
+    }
 
-        Ok(req)
+    /// 使用商户 RSA 私钥，对请求进行数字签名。
+    /// <https://pay.weixin.qq.com/wiki/doc/apiv3/wechatpay/wechatpay4_0.shtml>
+    pub fn sign_request(&self, req: Request, meta: Option<String>) -> Result<Request> {
+        sign_request(&self.mch_id, self, req, meta)
     }
 
     /// 使用商户 API v3 密钥解密
@@ -104,17 +189,7 @@ impl MchCredential {
         associated_data: &str,
         nonce: &str,
     ) -> Result<Vec<u8>> {
-        let ciphertext = BASE64_STANDARD.decode(ciphertext.as_bytes())?;
-        let cipher = Aes256Gcm::new(self.mch_api_v3_key.as_bytes().into());
-
-        let payload = Payload {
-            msg: ciphertext.as_slice(),
-            aad: associated_data.as_bytes(),
-        };
-
-        cipher
-            .decrypt(nonce.as_bytes().into(), payload)
-            .map_err(Into::into)
+        aes_decrypt(&self.mch_api_v3_key, ciphertext, associated_data, nonce)
     }
 
     /// 使用商户 API v3 密钥解密，并转换为字符串
@@ -125,10 +200,291 @@ impl MchCredential {
         nonce: &str,
     ) -> Result<String> {
         let bytes = self.aes_decrypt(ciphertext, associated_data, nonce)?;
-        Ok(String::from_utf8(bytes)?)
+        String::from_utf8(bytes).map_err(|e| Error::Decryption(e.to_string()))
+    }
+
+    /// 使用商户 RSA 私钥解密应答中的敏感信息字段。
+    /// 微信支付使用商户公钥加密，填充方案为 RSAES-OAEP，密文经过 base64 编码。
+    /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/wechatpay/wechatpay4_3.shtml>
+    pub fn rsa_decrypt(&self, ciphertext: &str) -> Result<String> {
+        rsa_decrypt(self, ciphertext)
+    }
+}
+
+/// 持有商户私钥的签名器，用于请求签名、调起支付签名及敏感信息解密。
+/// [`MchCredential`] 是使用内存中 RSA 私钥的默认实现。
+/// 私钥保存在 HSM、KMS 或其他进程中时，可自行实现此 trait，私钥无需加载到本进程，
+/// 参见 [`WechatPayClientBuilder::signer`](crate::client::WechatPayClientBuilder::signer)。
+pub trait Signer: Send + Sync {
+    /// 商户 API 证书序列号。
+    fn serial_no(&self) -> &str;
+
+    /// 使用商户私钥进行 SHA256 with RSA(PKCS#1 v1.5)签名，返回签名的原始字节。
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>>;
+
+    /// 使用商户私钥进行 RSAES-OAEP(SHA-1)解密，返回明文。
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>>;
+}
+
+impl Signer for MchCredential {
+    fn serial_no(&self) -> &str {
+        &self.mch_certificate_serial_no
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let digest = Sha256::digest(message);
+        self.mch_rsa_private_key
+            .sign_with_rng(
+                &mut rand::thread_rng(),
+                Pkcs1v15Sign::new::<Sha256>(),
+                &digest,
+            )
+            .map_err(crypto_error)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.mch_rsa_private_key
+            .decrypt(Oaep::new::<Sha1>(), ciphertext)
+            .map_err(|e| Error::Decryption(e.to_string()))
+    }
+}
+
+/// 客户端所用的商户身份：商户号、API v3 密钥及签名器。
+#[derive(Clone)]
+pub(crate) struct Merchant {
+    pub(crate) mch_id: String,
+    api_v3_key: String,
+    pub(crate) signer: Arc<dyn Signer>,
+}
+
+impl Merchant {
+    pub(crate) fn new(mch_id: String, api_v3_key: String, signer: Arc<dyn Signer>) -> Merchant {
+        Merchant {
+            mch_id,
+            api_v3_key,
+            signer,
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        validate(&self.mch_id, self.signer.serial_no(), &self.api_v3_key)
+    }
+
+    pub(crate) fn sign_request(&self, req: Request, meta: Option<String>) -> Result<Request> {
+        sign_request(&self.mch_id, self.signer.as_ref(), req, meta)
+    }
+
+    /// 签名，并进行 base64 编码。
+    pub(crate) fn sign(&self, message: &[u8]) -> Result<String> {
+        Ok(BASE64_STANDARD.encode(self.signer.sign(message)?))
+    }
+
+    pub(crate) fn aes_decrypt(
+        &self,
+        ciphertext: &str,
+        associated_data: &str,
+        nonce: &str,
+    ) -> Result<Vec<u8>> {
+        aes_decrypt(&self.api_v3_key, ciphertext, associated_data, nonce)
+    }
+
+    pub(crate) fn aes_decrypt_to_string(
+        &self,
+        ciphertext: &str,
+        associated_data: &str,
+        nonce: &str,
+    ) -> Result<String> {
+        let bytes = self.aes_decrypt(ciphertext, associated_data, nonce)?;
+        String::from_utf8(bytes).map_err(|e| Error::Decryption(e.to_string()))
+    }
+}
+
+impl From<MchCredential> for Merchant {
+    fn from(credential: MchCredential) -> Merchant {
+        Merchant::new(
+            credential.mch_id.clone(),
+            credential.mch_api_v3_key.clone(),
+            Arc::new(credential),
+        )
     }
 }
 
+impl Debug for Merchant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Merchant")
+            .field("mch_id", &self.mch_id)
+            .field("serial_no", &self.signer.serial_no())
+            .finish_non_exhaustive()
+    }
+}
+
+/// 使用签名器对请求进行数字签名，设置 `Authorization` 头部。
+/// <https://pay.weixin.qq.com/wiki/doc/apiv3/wechatpay/wechatpay4_0.shtml>
+pub(crate) fn sign_request(
+    mch_id: &str,
+    signer: &dyn Signer,
+    mut req: Request,
+    meta: Option<String>,
+) -> Result<Request> {
+    const SIGNATURE_TYPE: &str = "WECHATPAY2-SHA256-RSA2048";
+
+    let mut msg = BytesMut::new();
+
+    msg.put_slice(req.method().as_str().as_bytes());
+    msg.put_u8(b'\n');
+
+    let url = if let Some(query) = req.url().query() {
+        format!("{}?{}", req.url().path(), query)
+    } else {
+        req.url().path().to_string()
+    };
+    msg.put_slice(url.as_bytes());
+    msg.put_u8(b'\n');
+
+    let timestamp = Local::now().timestamp();
+    msg.put_slice(format!("{}", timestamp).as_bytes());
+    msg.put_u8(b'\n');
+
+    let nonce_str = generate_none_str(32);
+    msg.put_slice(nonce_str.as_bytes());
+    msg.put_u8(b'\n');
+
+    match meta {
+        Some(meta) => {
+            msg.put_slice(meta.as_bytes());
+            msg.put_u8(b'\n');
+        }
+        None => {
+            if let Some(body) = req.body() {
+                // 由本项目保证 body.as_bytes() 返回 Some(...)。
+                // 也即，由本项目保证 body 为  `Reusable`，而非 `Streaming`。
+                msg.put_slice(body.as_bytes().unwrap());
+            }
+            msg.put_u8(b'\n');
+        }
+    }
+    // if let Some(body) = req.body() {
+    //     // 由本项目保证 body.as_bytes() 返回 Some(...)。
+    //     // 也即，由本项目保证 body 为  `Reusable`，而非 `Streaming`。
+    //     msg.put_slice(body.as_bytes().unwrap());
+    // }
+    // msg.put_u8(b'\n');
+
+    let signature = BASE64_STANDARD.encode(signer.sign(&msg)?);
+
+    let authorization_value = format!(
+        r#"{} mchid="{}",nonce_str="{}",signature="{}",timestamp="{}",serial_no="{}""#,
+        SIGNATURE_TYPE,
+        mch_id,
+        nonce_str,
+        signature,
+        timestamp,
+        signer.serial_no()
+    );
+    req.headers_mut()
+        .insert(AUTHORIZATION, authorization_value.parse().unwrap());
+
+    Ok(req)
+}
+
+/// 使用商户 API v3 密钥解密，用于回调通知及平台证书。
+pub(crate) fn aes_decrypt(
+    api_v3_key: &str,
+    ciphertext: &str,
+    associated_data: &str,
+    nonce: &str,
+) -> Result<Vec<u8>> {
+    let ciphertext = BASE64_STANDARD
+        .decode(ciphertext.as_bytes())
+        .map_err(|e| Error::Decryption(e.to_string()))?;
+    let cipher = Aes256Gcm::new_from_slice(api_v3_key.as_bytes())
+        .map_err(|_| Error::Decryption("`mch_api_v3_key` must be 32 bytes".to_string()))?;
+
+    let payload = Payload {
+        msg: ciphertext.as_slice(),
+        aad: associated_data.as_bytes(),
+    };
+
+    cipher
+        .decrypt(nonce.as_bytes().into(), payload)
+        .map_err(|e| Error::Decryption(e.to_string()))
+}
+
+/// 使用签名器解密经 base64 编码的敏感信息字段。
+pub(crate) fn rsa_decrypt(signer: &dyn Signer, ciphertext: &str) -> Result<String> {
+    let ciphertext = BASE64_STANDARD
+        .decode(ciphertext.as_bytes())
+        .map_err(|e| Error::Decryption(e.to_string()))?;
+    let bytes = signer.decrypt(&ciphertext)?;
+    String::from_utf8(bytes).map_err(|e| Error::Decryption(e.to_string()))
+}
+
+fn validate(mch_id: &str, serial_no: &str, api_v3_key: &str) -> Result<()> {
+    if mch_id.is_empty() {
+        return Err(Error::InvalidArgument("`mch_id` is empty".to_string()));
+    }
+    if serial_no.is_empty() {
+        return Err(Error::InvalidArgument(
+            "`mch_certificate_serial_no` is empty".to_string(),
+        ));
+    }
+    if api_v3_key.len() != 32 {
+        return Err(Error::InvalidArgument(format!(
+            "`mch_api_v3_key` must be 32 bytes, got {}",
+            api_v3_key.len()
+        )));
+    }
+    Ok(())
+}
+
+/// 解析 PEM 格式的 RSA 私钥，支持 PKCS#8(`BEGIN PRIVATE KEY`)及 PKCS#1(`BEGIN RSA PRIVATE KEY`)。
+pub fn parse_private_key(pem: &str) -> Result<RsaPrivateKey> {
+    RsaPrivateKey::from_pkcs8_pem(pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+        .map_err(|e| Error::Crypto(format!("invalid RSA private key: {}", e)))
+}
+
+/// 从 PEM 格式的商户 API 证书中读取证书序列号，以大写十六进制表示。
+pub fn certificate_serial_no(pem: &str) -> Result<String> {
+    let certificate = Certificate::from_pem(pem.as_bytes()).map_err(crypto_error)?;
+    Ok(serial_number_to_hex(
+        certificate.tbs_certificate.serial_number.as_bytes(),
+    ))
+}
+
+/// 检查证书与私钥是否匹配，并返回证书序列号。
+fn check_certificate(certificate: &Certificate, private_key: &RsaPrivateKey) -> Result<String> {
+    let tbs = &certificate.tbs_certificate;
+    let spki = tbs.subject_public_key_info.to_der().map_err(crypto_error)?;
+    let public_key = RsaPublicKey::from_public_key_der(&spki).map_err(crypto_error)?;
+    if public_key != private_key.to_public_key() {
+        return Err(Error::Crypto(
+            "private key does not match the certificate".to_string(),
+        ));
+    }
+    Ok(serial_number_to_hex(tbs.serial_number.as_bytes()))
+}
+
+fn read_to_string(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| io_error(path, e))
+}
+
+#[cfg(feature = "p12")]
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| io_error(path, e))
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Io(std::io::Error::new(
+        e.kind(),
+        format!("{}: {}", path.display(), e),
+    ))
+}
+
+fn crypto_error(e: impl std::fmt::Display) -> Error {
+    Error::Crypto(e.to_string())
+}
+
 /// 生成随机的 none_str
 pub fn generate_none_str(n: usize) -> String {
     // 去掉了符号及容易混淆的字符等，比如 0, o, O, 1, l, i, I。
@@ -152,3 +508,238 @@ impl Debug for MchCredential {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::{jsapi_params, test_credential, test_server};
+    use crate::partner::shou_fu_tong::applyment::settlement;
+    use crate::WechatPayClient;
+    use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+    use std::collections::HashMap;
+
+    const CERT: &str = include_str!("../testdata/platform_cert.pem");
+    /// PKCS#1 格式，与 CERT 匹配。
+    const KEY: &str = include_str!("../testdata/platform_key.pem");
+    const SERIAL_NO: &str = "5157F09EFDC096DE15EBE81A47057A7232F1B8E1";
+    const API_V3_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "wechatpay-{}-{}-{}",
+            name,
+            std::process::id(),
+            generate_none_str(8)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_from_pem() -> Result<()> {
+        let cred = MchCredential::from_pem("1900000001", KEY, CERT, API_V3_KEY)?;
+        assert_eq!(cred.mch_certificate_serial_no, SERIAL_NO);
+        assert_eq!(certificate_serial_no(CERT)?, SERIAL_NO);
+
+        // PKCS#8
+        let pkcs8 = cred
+            .mch_rsa_private_key
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+        let cred = MchCredential::from_pem("1900000001", &pkcs8, CERT, API_V3_KEY)?;
+        assert_eq!(cred.mch_certificate_serial_no, SERIAL_NO);
+
+        // 私钥与证书不匹配
+        let other = include_str!("../testdata/apiclient_key.pem");
+        assert!(MchCredential::from_pem("1900000001", other, CERT, API_V3_KEY).is_err());
+        assert!(MchCredential::from_pem("1900000001", "not a key", CERT, API_V3_KEY).is_err());
+
+        // API v3 密钥须为 32 字节
+        let err = MchCredential::from_key_pem("1900000001", SERIAL_NO, KEY, "short").unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_aes_decrypt_invalid_key() {
+        let mut cred = MchCredential::from_pem("1900000001", KEY, CERT, API_V3_KEY).unwrap();
+        cred.mch_api_v3_key = "short".to_string();
+        assert!(cred.validate().is_err());
+        // 不再 panic
+        assert!(cred.aes_decrypt("AAAA", "", "0123456789ab").is_err());
+    }
+
+    #[cfg(feature = "p12")]
+    #[test]
+    fn test_from_p12() -> Result<()> {
+        use p12_keystore::{
+            Certificate as P12Certificate, EncryptionAlgorithm, KeyStore, KeyStoreEntry,
+            MacAlgorithm, PrivateKeyChain,
+        };
+
+        let private_key = parse_private_key(KEY)?;
+        let der = Certificate::from_pem(CERT.as_bytes())
+            .unwrap()
+            .to_der()
+            .unwrap();
+        let chain = PrivateKeyChain::new(
+            private_key.to_pkcs8_der().unwrap().as_bytes(),
+            [1],
+            [P12Certificate::from_der(&der).unwrap()],
+        );
+        let mut keystore = KeyStore::new();
+        keystore.add_entry("Tenpay Certificate", KeyStoreEntry::PrivateKeyChain(chain));
+        // 与微信支付下发的证书文件一样，使用 3DES 加密，密码为商户号
+        let p12 = keystore
+            .writer("1900000001")
+            .encryption_algorithm(EncryptionAlgorithm::PbeWithShaAnd3KeyTripleDesCbc)
+            .mac_algorithm(MacAlgorithm::HmacSha1)
+            .write()
+            .unwrap();
+
+        let cred = MchCredential::from_p12("1900000001", &p12, "1900000001", API_V3_KEY)?;
+        assert_eq!(cred.mch_certificate_serial_no, SERIAL_NO);
+        assert_eq!(cred.mch_rsa_private_key, private_key);
+        assert!(MchCredential::from_p12("1900000001", &p12, "wrong", API_V3_KEY).is_err());
+
+        let dir = temp_dir("p12");
+        std::fs::write(dir.join(P12_FILE), &p12).unwrap();
+        let cred = MchCredential::from_dir("1900000001", &dir, API_V3_KEY)?;
+        assert_eq!(cred.mch_certificate_serial_no, SERIAL_NO);
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_dir_and_env() -> Result<()> {
+        let dir = temp_dir("dir");
+        assert!(matches!(
+            MchCredential::from_dir("1900000001", &dir, API_V3_KEY),
+            Err(Error::Io(_))
+        ));
+        std::fs::write(dir.join(KEY_FILE), KEY).unwrap();
+        std::fs::write(dir.join(CERT_FILE), CERT).unwrap();
+        let cred = MchCredential::from_dir("1900000001", &dir, API_V3_KEY)?;
+        assert_eq!(cred.mch_certificate_serial_no, SERIAL_NO);
+
+        let mut vars = HashMap::from([
+            ("WECHATPAY_MCH_ID", "1900000001".to_string()),
+            ("WECHATPAY_API_V3_KEY", API_V3_KEY.to_string()),
+        ]);
+        let from_vars =
+            |vars: &HashMap<_, String>| MchCredential::from_vars(|name| vars.get(name).cloned());
+        assert!(from_vars(&vars).is_err());
+
+        vars.insert("WECHATPAY_PRIVATE_KEY", KEY.to_string());
+        vars.insert("WECHATPAY_CERT_SERIAL_NO", "SERIAL".to_string());
+        let cred = from_vars(&vars)?;
+        assert_eq!(cred.mch_id, "1900000001");
+        assert_eq!(cred.mch_certificate_serial_no, "SERIAL");
+
+        vars.remove("WECHATPAY_PRIVATE_KEY");
+        vars.remove("WECHATPAY_CERT_SERIAL_NO");
+        let path = dir.join(KEY_FILE).to_string_lossy().into_owned();
+        vars.insert("WECHATPAY_PRIVATE_KEY_PATH", path);
+        let path = dir.join(CERT_FILE).to_string_lossy().into_owned();
+        vars.insert("WECHATPAY_CERT_PATH", path);
+        assert_eq!(from_vars(&vars)?.mch_certificate_serial_no, SERIAL_NO);
+
+        let vars = HashMap::from([
+            ("WECHATPAY_MCH_ID", "1900000001".to_string()),
+            ("WECHATPAY_API_V3_KEY", API_V3_KEY.to_string()),
+            ("WECHATPAY_CERT_DIR", dir.to_string_lossy().into_owned()),
+        ]);
+        assert_eq!(from_vars(&vars)?.mch_certificate_serial_no, SERIAL_NO);
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    /// 模拟 HSM 的签名器：私钥不对外暴露，记录调用次数。
+    struct StubSigner {
+        serial_no: String,
+        private_key: RsaPrivateKey,
+        calls: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Signer for StubSigner {
+        fn serial_no(&self) -> &str {
+            &self.serial_no
+        }
+
+        fn sign(&self, message: &[u8]) -> crate::Result<Vec<u8>> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let digest = Sha256::digest(message);
+            Ok(self
+                .private_key
+                .sign(rsa::Pkcs1v15Sign::new::<Sha256>(), &digest)
+                .unwrap())
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> crate::Result<Vec<u8>> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.private_key
+                .decrypt(rsa::Oaep::new::<sha1::Sha1>(), ciphertext)
+                .map_err(|e| crate::Error::Decryption(e.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signer() -> anyhow::Result<()> {
+        let server = test_server();
+        let credential = test_credential();
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let signer = StubSigner {
+            serial_no: credential.mch_certificate_serial_no.clone(),
+            private_key: credential.mch_rsa_private_key.clone(),
+            calls: calls.clone(),
+        };
+        let client = WechatPayClient::builder()
+            .signer(credential.mch_id.clone(), API_V3_KEY, signer)
+            .platform_certificate(server.platform_certificate())
+            .transport(server.clone())
+            .build()
+            .await?;
+        assert_eq!(client.get_mch_id(), "1900000001");
+
+        // 请求签名经由签名器完成，模拟服务器验签通过
+        let prepay_id = client.jsapi_create_trade(&jsapi_params("signer_1")).await?;
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let signature = client.sign_jsapi_trade(&prepay_id, "wxd678efh567hg6787")?;
+        let msg = format!(
+            "{}\n{}\n{}\n{}\n",
+            signature.app_id, signature.timestamp, signature.nonce_str, signature.package
+        );
+        let digest = Sha256::digest(msg.as_bytes());
+        credential.mch_rsa_private_key.to_public_key().verify(
+            rsa::Pkcs1v15Sign::new::<Sha256>(),
+            &digest,
+            &BASE64_STANDARD.decode(&signature.pay_sign)?,
+        )?;
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // 敏感信息解密同样经由签名器
+        let data = settlement::SettlementModifyData {
+            modify_mode: "MODIFY_MODE_ASYNC".to_string(),
+            account_type: "ACCOUNT_TYPE_BUSINESS".to_string(),
+            account_bank: "工商银行".to_string(),
+            bank_name: None,
+            bank_branch_id: None,
+            account_number: "6222020000000000078".into(),
+            account_name: Some("深圳腾大有限公司".into()),
+        };
+        let res = settlement::modify_settlement(&client, "1900000109", &data).await?;
+        let res =
+            settlement::query_settlement_modify(&client, "1900000109", &res.application_no).await?;
+        assert_eq!(res.account_name.plaintext(), Some("深圳腾大有限公司"));
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 6);
+
+        // 未设置商户身份时无法构造
+        let res = WechatPayClient::builder()
+            .platform_certificate(server.platform_certificate())
+            .build()
+            .await;
+        assert!(matches!(res, Err(crate::Error::InvalidArgument(_))));
+        Ok(())
+    }
+}
@@ -1,15 +1,243 @@
-use serde::{Deserialize, Serialize};
+//! 错误类型。
 
+use http::StatusCode;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// 本 crate 所有接口返回的错误。
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// 网络请求错误，如连接失败、超时等。
+    #[error("HTTP 请求错误: {0}")]
+    Transport(reqwest::Error),
+    /// 用户添加的中间件返回的错误，如超时、限流等。
+    #[error("中间件错误: {0}")]
+    Service(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// 响应或通知验签失败。
+    #[error("验签失败: {0}")]
+    Verification(String),
+    /// 重放的通知，如重复的 `Wechatpay-Nonce`，或按配置拒绝的重复通知 ID。
+    #[error("重放的通知: {0}")]
+    Replay(String),
+    /// 解密失败，如回调通知、平台证书或敏感信息解密失败。
+    #[error("解密失败: {0}")]
+    Decryption(String),
+    /// 加密、密钥或证书相关的错误。
+    #[error("密钥错误: {0}")]
+    Crypto(String),
+    /// 请求或响应的(反)序列化错误。
+    #[error("序列化错误: {0}")]
+    Serde(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// 微信支付返回的错误。
+    #[error(transparent)]
+    Api(Box<WechatPayApiError>),
+    /// 调用参数或配置错误。
+    #[error("参数错误: {0}")]
+    InvalidArgument(String),
+    /// 调用参数未通过本地校验，包含所有不符合要求的字段。
+    #[error("参数校验失败: {0}")]
+    Validation(ValidationErrors),
+    /// 构建 HTTP 请求或响应时出错。
+    #[error("HTTP 错误: {0}")]
+    Http(#[from] http::Error),
+    /// 读取证书、密钥等文件时出错。
+    #[error("IO 错误: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl Error {
+    /// 若为微信支付返回的错误，返回其错误码。
+    pub fn api_code(&self) -> Option<&ErrorCode> {
+        match self {
+            Error::Api(e) => Some(&e.code),
+            _ => None,
+        }
+    }
+
+    /// 将 tower service 返回的错误还原为具体的错误类型。
+    pub(crate) fn from_boxed(e: Box<dyn std::error::Error + Send + Sync>) -> Error {
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return *e,
+            Err(e) => e,
+        };
+        match e.downcast::<reqwest::Error>() {
+            Ok(e) => (*e).into(),
+            Err(e) => Error::Service(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Error {
+        // 响应 body 反序列化失败时，reqwest 也返回 reqwest::Error。
+        if e.is_decode() {
+            Error::Serde(Box::new(e))
+        } else {
+            Error::Transport(e)
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Serde(Box::new(e))
+    }
+}
+
+impl From<WechatPayApiError> for Error {
+    fn from(e: WechatPayApiError) -> Error {
+        Error::Api(Box::new(e))
+    }
+}
+
+impl From<ValidationErrors> for Error {
+    fn from(e: ValidationErrors) -> Error {
+        Error::Validation(e)
+    }
+}
+
+/// 微信支付返回的错误。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/wechatpay/wechatpay2_0.shtml#part-7>
 #[derive(Debug, Clone, Default, Serialize, Deserialize, thiserror::Error)]
 #[serde(default)]
 #[error("微信支付错误: {message} && {code}")]
 pub struct WechatPayApiError {
+    /// HTTP 状态码
+    #[serde(skip)]
+    pub status: StatusCode,
     /// 错误码
-    code: String,
+    pub code: ErrorCode,
     /// 错误描述
-    message: String,
+    pub message: String,
     /// 错误详情
-    detail: WechatPayErrorDetail,
+    pub detail: WechatPayErrorDetail,
+    /// 响应头中的 `Request-ID`，排查问题时需提供给微信支付。
+    #[serde(skip)]
+    pub request_id: Option<String>,
+}
+
+impl WechatPayApiError {
+    /// 由微信支付返回的错误响应构造。
+    /// 响应 body 不是预期的 JSON 时(如网关返回的 HTML)，以 body 原文作为错误描述。
+    pub(crate) async fn from_response(res: reqwest::Response) -> Error {
+        let status = res.status();
+        let request_id = res
+            .headers()
+            .get("Request-ID")
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+        let body = match res.bytes().await {
+            Ok(body) => body,
+            Err(e) => return e.into(),
+        };
+
+        let mut e = serde_json::from_slice::<WechatPayApiError>(&body).unwrap_or_else(|_| {
+            WechatPayApiError {
+                message: String::from_utf8_lossy(&body).into_owned(),
+                ..Default::default()
+            }
+        });
+        e.status = status;
+        e.request_id = request_id;
+        e.into()
+    }
+}
+
+/// 本地校验参数时发现的错误，按字段顺序排列。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors(Vec<ValidationError>);
+
+impl ValidationErrors {
+    /// 记录一个字段的错误。
+    pub(crate) fn push(&mut self, field: &'static str, reason: ValidationReason) {
+        self.0.push(ValidationError { field, reason });
+    }
+
+    /// 没有错误时返回 `Ok(())`。
+    pub(crate) fn into_result(self) -> Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(self))
+        }
+    }
+
+    /// 是否没有错误。
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 按校验顺序遍历各字段的错误。
+    pub fn iter(&self) -> std::slice::Iter<'_, ValidationError> {
+        self.0.iter()
+    }
+
+    /// 查找字段的错误。
+    pub fn get(&self, field: &str) -> Option<&ValidationReason> {
+        self.0.iter().find(|e| e.field == field).map(|e| &e.reason)
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl IntoIterator for ValidationErrors {
+    type Item = ValidationError;
+    type IntoIter = std::vec::IntoIter<ValidationError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// 一个字段的校验错误。
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("`{field}` {reason}")]
+pub struct ValidationError {
+    /// 字段名，与请求 JSON 中的字段一致，如 `amount.total`。
+    pub field: &'static str,
+    /// 错误原因
+    pub reason: ValidationReason,
+}
+
+/// 字段校验失败的原因。
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationReason {
+    /// 必填字段为空
+    #[error("is required")]
+    Missing,
+    /// 字符数超出范围
+    #[error("must be {min} to {max} characters, got {actual}")]
+    Length {
+        min: usize,
+        max: usize,
+        actual: usize,
+    },
+    /// 字节数超出上限
+    #[error("must be at most {max} bytes, got {actual}")]
+    TooManyBytes { max: usize, actual: usize },
+    /// 包含不允许的字符
+    #[error("contains invalid character {0:?}")]
+    InvalidChar(char),
+    /// 金额不大于 0
+    #[error("must be greater than 0, got {0}")]
+    NotPositive(i64),
+    /// 不是合法的 URL
+    #[error("is not a valid url: {0}")]
+    InvalidUrl(String),
+    /// URL 携带了参数
+    #[error("must not contain a query")]
+    UrlHasQuery,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -24,3 +252,166 @@ pub struct WechatPayErrorDetail {
     /// 出错的位置
     pub location: String,
 }
+
+/// 微信支付错误码。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/wechatpay/wechatpay2_0.shtml#part-7>
+/// 以及各接口文档的错误码列表。未列出的错误码解析为 `Other`。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// 系统错误
+    SystemError,
+    /// 银行系统异常
+    BankError,
+    /// 频率超限
+    FrequencyLimited,
+    /// 签名错误
+    SignError,
+    /// 参数错误
+    ParamError,
+    /// 请求参数符合参数格式，但不符合业务规则
+    InvalidRequest,
+    /// 商户无权限
+    NoAuth,
+    /// 余额不足
+    NotEnough,
+    /// 订单已关闭
+    OrderClosed,
+    /// 订单已支付
+    OrderPaid,
+    /// 订单不存在。查询订单返回 `ORDER_NOT_EXIST`，部分接口返回 `ORDERNOTEXIST`
+    OrderNotExist,
+    /// 商户订单号重复
+    OutTradeNoUsed,
+    /// 商户号不存在
+    MchNotExists,
+    /// AppID 和 mch_id 不匹配
+    AppIdMchIdNotMatch,
+    /// 账号异常
+    AccountError,
+    /// 业务规则限制
+    RuleLimit,
+    /// 交易错误
+    TradeError,
+    /// 资源不存在
+    ResourceNotExists,
+    /// 资源已存在
+    AlreadyExists,
+    /// 用户支付中，需要输入密码
+    UserPaying,
+    /// 请求受阻
+    RequestBlocked,
+    /// 未知的错误码
+    Other(String),
+    /// 响应中没有错误码
+    #[default]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorCode::SystemError => "SYSTEM_ERROR",
+            ErrorCode::BankError => "BANK_ERROR",
+            ErrorCode::FrequencyLimited => "FREQUENCY_LIMITED",
+            ErrorCode::SignError => "SIGN_ERROR",
+            ErrorCode::ParamError => "PARAM_ERROR",
+            ErrorCode::InvalidRequest => "INVALID_REQUEST",
+            ErrorCode::NoAuth => "NO_AUTH",
+            ErrorCode::NotEnough => "NOT_ENOUGH",
+            ErrorCode::OrderClosed => "ORDER_CLOSED",
+            ErrorCode::OrderPaid => "ORDERPAID",
+            ErrorCode::OrderNotExist => "ORDER_NOT_EXIST",
+            ErrorCode::OutTradeNoUsed => "OUT_TRADE_NO_USED",
+            ErrorCode::MchNotExists => "MCH_NOT_EXISTS",
+            ErrorCode::AppIdMchIdNotMatch => "APPID_MCHID_NOT_MATCH",
+            ErrorCode::AccountError => "ACCOUNTERROR",
+            ErrorCode::RuleLimit => "RULE_LIMIT",
+            ErrorCode::TradeError => "TRADE_ERROR",
+            ErrorCode::ResourceNotExists => "RESOURCE_NOT_EXISTS",
+            ErrorCode::AlreadyExists => "ALREADY_EXISTS",
+            ErrorCode::UserPaying => "USERPAYING",
+            ErrorCode::RequestBlocked => "REQUEST_BLOCKED",
+            ErrorCode::Other(s) => s,
+            ErrorCode::Unknown => "",
+        }
+    }
+}
+
+impl From<&str> for ErrorCode {
+    fn from(s: &str) -> ErrorCode {
+        match s {
+            "SYSTEM_ERROR" => ErrorCode::SystemError,
+            "BANK_ERROR" => ErrorCode::BankError,
+            "FREQUENCY_LIMITED" => ErrorCode::FrequencyLimited,
+            "SIGN_ERROR" => ErrorCode::SignError,
+            "PARAM_ERROR" => ErrorCode::ParamError,
+            "INVALID_REQUEST" => ErrorCode::InvalidRequest,
+            "NO_AUTH" => ErrorCode::NoAuth,
+            "NOT_ENOUGH" => ErrorCode::NotEnough,
+            "ORDER_CLOSED" => ErrorCode::OrderClosed,
+            "ORDERPAID" => ErrorCode::OrderPaid,
+            "ORDER_NOT_EXIST" | "ORDERNOTEXIST" => ErrorCode::OrderNotExist,
+            "OUT_TRADE_NO_USED" => ErrorCode::OutTradeNoUsed,
+            "MCH_NOT_EXISTS" => ErrorCode::MchNotExists,
+            "APPID_MCHID_NOT_MATCH" => ErrorCode::AppIdMchIdNotMatch,
+            "ACCOUNTERROR" => ErrorCode::AccountError,
+            "RULE_LIMIT" => ErrorCode::RuleLimit,
+            "TRADE_ERROR" => ErrorCode::TradeError,
+            "RESOURCE_NOT_EXISTS" => ErrorCode::ResourceNotExists,
+            "ALREADY_EXISTS" => ErrorCode::AlreadyExists,
+            "USERPAYING" => ErrorCode::UserPaying,
+            "REQUEST_BLOCKED" => ErrorCode::RequestBlocked,
+            "" => ErrorCode::Unknown,
+            _ => ErrorCode::Other(s.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorCode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(ErrorCode::from(s.as_str()))
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_serde() -> Result<()> {
+        let body = r#"{"code":"PARAM_ERROR","message":"参数错误","detail":{"field":"/amount/total","value":"0","issue":"total 必须大于 0","location":"body"}}"#;
+        let e: WechatPayApiError = serde_json::from_str(body)?;
+        assert_eq!(e.code, ErrorCode::ParamError);
+        assert_eq!(e.detail.field, "/amount/total");
+
+        let e: WechatPayApiError = serde_json::from_str(r#"{"code":"NEW_CODE","message":"x"}"#)?;
+        assert_eq!(e.code, ErrorCode::Other("NEW_CODE".to_string()));
+        assert_eq!(serde_json::to_string(&e.code)?, r#""NEW_CODE""#);
+
+        let e = Error::from(e);
+        assert_eq!(
+            e.api_code(),
+            Some(&ErrorCode::Other("NEW_CODE".to_string()))
+        );
+        Ok(())
+    }
+}
@@ -1,13 +1,31 @@
+#[cfg(feature = "actix-web")]
+pub mod actix_web;
+#[cfg(feature = "axum")]
+pub mod axum;
+pub mod bill;
 pub mod client;
+pub mod codepay;
 pub mod credential;
 pub mod error;
+pub mod middleware;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod notify;
-pub mod platform_certificate;
 pub mod partner;
+pub mod platform_certificate;
+pub mod poller;
+pub mod reconcile;
 pub mod refund;
+pub mod replay;
+pub mod retry;
+pub mod sensitive;
 pub mod trade;
+pub mod transport;
 pub mod util;
 
 pub use client::WechatPayClient;
-pub use credential::MchCredential;
-pub use platform_certificate::PlatformCertificate;
+pub use credential::{MchCredential, Signer};
+pub use error::{Error, ErrorCode, Result, WechatPayApiError};
+pub use platform_certificate::{PlatformCertificate, PlatformKeyring, PlatformPublicKey};
+pub use replay::ReplayProtection;
+pub use retry::RetryPolicy;
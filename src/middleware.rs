@@ -0,0 +1,369 @@
+//! 基于 tower 的请求处理流程。
+//!
+//! WechatPayClient 发送请求时，依次经过以下 service:
+//!
+//! ```text
+//! VerifyLayer -> SignLayer -> 用户添加的 layer -> HTTP 传输
+//! ```
+//!
+//! `SignLayer` 对请求进行签名，`VerifyLayer` 对响应进行验签。
+//! 通过 [`WechatPayClientBuilder::layer`](crate::client::WechatPayClientBuilder::layer)
+//! 添加的 layer (如超时、限流、tracing、metrics 等)位于两者与 HTTP 传输之间，
+//! 看到的是已签名的请求与未验签的响应。
+
+use crate::client::CertificateRefresher;
+use crate::credential::{MchCredential, Merchant};
+use crate::error::{Error, Result, WechatPayApiError};
+use crate::platform_certificate::{self, PlatformKeyring, PlatformPublicKey};
+use crate::replay::ReplayProtection;
+use arc_swap::ArcSwap;
+use reqwest::{Request, Response};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::util::BoxCloneSyncService;
+use tower::{Layer, Service, ServiceExt};
+
+/// 中间件返回的错误。
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 位于签名、验签与 HTTP 传输之间的 service。
+pub type HttpService = BoxCloneSyncService<Request, Response, BoxError>;
+
+/// WechatPayClient 使用的完整 service。
+pub(crate) type WechatPayService = BoxCloneSyncService<Request, Response, Error>;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// 签名所用的 meta，见 [`MchCredential::sign_request`]。
+/// 放入请求的 extensions 中，由 SignService 取出。
+#[derive(Debug, Clone)]
+pub(crate) struct SignMeta(pub(crate) String);
+
+/// 放入请求的 extensions 中时，VerifyService 只检查状态码，不对响应验签。
+/// 账单文件下载接口的响应没有签名，由调用方通过文件摘要校验。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SkipVerify;
+
+/// 读写请求的 extensions。
+/// reqwest::Request 未公开 extensions，因此先转换为 http::Request 再访问。
+pub(crate) fn with_extensions<R>(
+    req: Request,
+    f: impl FnOnce(&mut http::Extensions) -> R,
+) -> Result<(Request, R)> {
+    let has_body = req.body().is_some();
+    let mut http_req = http::Request::try_from(req)?;
+    let r = f(http_req.extensions_mut());
+    let mut req = Request::try_from(http_req)?;
+    // 转换后没有 body 的请求会带上空 body，还原以免影响签名及发送。
+    if !has_body {
+        *req.body_mut() = None;
+    }
+    Ok((req, r))
+}
+
+/// 对请求进行签名的 layer。
+#[derive(Debug, Clone)]
+pub struct SignLayer {
+    merchant: Arc<Merchant>,
+}
+
+impl SignLayer {
+    pub fn new(mch_credential: MchCredential) -> SignLayer {
+        SignLayer::with_merchant(Merchant::from(mch_credential))
+    }
+
+    pub(crate) fn with_merchant(merchant: Merchant) -> SignLayer {
+        SignLayer {
+            merchant: Arc::new(merchant),
+        }
+    }
+}
+
+impl<S> Layer<S> for SignLayer {
+    type Service = SignService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SignService {
+            inner,
+            merchant: self.merchant.clone(),
+        }
+    }
+}
+
+/// 对请求进行签名的 service。每次调用都使用新的时间戳与随机串。
+#[derive(Debug, Clone)]
+pub struct SignService<S> {
+    inner: S,
+    merchant: Arc<Merchant>,
+}
+
+impl<S> Service<Request> for SignService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let signed = with_extensions(req, |ext| ext.get::<SignMeta>().map(|m| m.0.clone()))
+            .and_then(|(req, meta)| self.merchant.sign_request(req, meta));
+        match signed {
+            Ok(req) => {
+                let fut = self.inner.call(req);
+                Box::pin(async move { fut.await.map_err(Into::into) })
+            }
+            Err(e) => Box::pin(async move { Err(e.into()) }),
+        }
+    }
+}
+
+/// 对响应进行验签的 layer。
+/// 非 2xx 响应转换为 [`Error::Api`]；其余响应验签通过后返回。
+#[derive(Debug, Clone)]
+pub struct VerifyLayer {
+    verifier: Verifier,
+}
+
+impl VerifyLayer {
+    /// 使用给定的平台证书及公钥验签。
+    pub fn new(platform_keyring: PlatformKeyring) -> VerifyLayer {
+        VerifyLayer {
+            verifier: Verifier::new(
+                Arc::new(ArcSwap::from_pointee(platform_keyring)),
+                None,
+                ReplayProtection::default(),
+            ),
+        }
+    }
+
+    pub(crate) fn with_verifier(verifier: Verifier) -> VerifyLayer {
+        VerifyLayer { verifier }
+    }
+}
+
+impl<S> Layer<S> for VerifyLayer {
+    type Service = VerifyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifyService {
+            inner,
+            verifier: self.verifier.clone(),
+        }
+    }
+}
+
+/// 对响应进行验签的 service。
+#[derive(Debug, Clone)]
+pub struct VerifyService<S> {
+    inner: S,
+    verifier: Verifier,
+}
+
+impl<S> Service<Request> for VerifyService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = BoxFuture<Result<Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner
+            .poll_ready(cx)
+            .map_err(|e| Error::from_boxed(e.into()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let (req, verify) = match with_extensions(req, |ext| ext.get::<SkipVerify>().is_none()) {
+            Ok(r) => r,
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let fut = self.inner.call(req);
+        let verifier = self.verifier.clone();
+        Box::pin(async move {
+            let res = fut.await.map_err(|e| Error::from_boxed(e.into()))?;
+            // 请求出错时，响应中可能不存在验签相关的字段。因此直接返回 error。
+            if !res.status().is_success() {
+                return Err(WechatPayApiError::from_response(res).await);
+            }
+            if !verify {
+                return Ok(res);
+            }
+            verifier.verify_response(res).await
+        })
+    }
+}
+
+/// 根据 `Wechatpay-Serial` 选择平台证书或公钥进行验签，并检查时间戳。
+#[derive(Clone)]
+pub(crate) struct Verifier {
+    platform_keyring: Arc<ArcSwap<PlatformKeyring>>,
+    /// 平台证书自动更新。未开启时为 None。
+    certificate_refresher: Option<Arc<CertificateRefresher>>,
+    pub(crate) replay_protection: ReplayProtection,
+}
+
+impl Verifier {
+    pub(crate) fn new(
+        platform_keyring: Arc<ArcSwap<PlatformKeyring>>,
+        certificate_refresher: Option<Arc<CertificateRefresher>>,
+        replay_protection: ReplayProtection,
+    ) -> Verifier {
+        Verifier {
+            platform_keyring,
+            certificate_refresher,
+            replay_protection,
+        }
+    }
+
+    /// 对响应进行数字签名验证，并检查 `Wechatpay-Timestamp` 与本地时间的偏差。
+    /// 若开启了平台证书自动更新，遇到未知的序列号时会先更新平台证书再验签。
+    pub(crate) async fn verify_response(&self, res: Response) -> Result<Response> {
+        let (head, body) = platform_certificate::split_response(res).await?;
+
+        if let Some(refresher) = &self.certificate_refresher {
+            let serial = head
+                .headers()
+                .get("Wechatpay-Serial")
+                .and_then(|v| v.to_str().ok());
+            // 微信支付公钥不会通过证书下载接口更新，因此只对平台证书序列号按需更新。
+            if let Some(serial) = serial.filter(|s| !PlatformPublicKey::is_key_id(s)) {
+                if !self.platform_keyring.load().contains(serial) {
+                    refresher.refresh_on_demand(serial).await?;
+                }
+            }
+        }
+
+        self.platform_keyring.load().verify(head.headers(), &body)?;
+        self.replay_protection.check_timestamp(head.headers())?;
+        Ok(platform_certificate::join_response(head, body))
+    }
+}
+
+impl fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Verifier")
+            .field("auto_refresh", &self.certificate_refresher.is_some())
+            .field("replay_protection", &self.replay_protection)
+            .finish_non_exhaustive()
+    }
+}
+
+type BoxLayer = Box<dyn FnOnce(HttpService) -> HttpService + Send + Sync>;
+
+/// 用户添加的 layer。
+#[derive(Default)]
+pub(crate) struct LayerStack {
+    layers: Vec<BoxLayer>,
+}
+
+impl LayerStack {
+    pub(crate) fn push<L>(&mut self, layer: L)
+    where
+        L: Layer<HttpService> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Response> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers.push(Box::new(move |inner| {
+            BoxCloneSyncService::new(layer.layer(inner).map_err(Into::into))
+        }));
+    }
+
+    /// 与 `tower::ServiceBuilder` 一致，先添加的 layer 位于外层。
+    pub(crate) fn apply(self, transport: HttpService) -> HttpService {
+        self.layers
+            .into_iter()
+            .rev()
+            .fold(transport, |service, layer| layer(service))
+    }
+}
+
+impl fmt::Debug for LayerStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayerStack")
+            .field("len", &self.layers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::Signer;
+    use std::sync::Mutex;
+
+    /// 记录待签名内容的签名器。
+    #[derive(Default)]
+    struct RecordingSigner {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl Signer for RecordingSigner {
+        fn serial_no(&self) -> &str {
+            "SERIAL"
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+            let message = String::from_utf8(message.to_vec()).unwrap();
+            self.messages.lock().unwrap().push(message);
+            Ok(Vec::new())
+        }
+
+        fn decrypt(&self, _ciphertext: &[u8]) -> Result<Vec<u8>> {
+            Err(Error::Decryption("unsupported".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_meta() -> Result<()> {
+        let signer = Arc::new(RecordingSigner::default());
+        let merchant = Merchant::new("1900000001".to_string(), String::new(), signer.clone());
+        let transport = tower::service_fn(|req: Request| async move {
+            // 读写 extensions 不改变请求的 body
+            assert_eq!(req.method() == reqwest::Method::GET, req.body().is_none());
+            let body = req.body().and_then(|b| b.as_bytes()).map(<[u8]>::to_vec);
+            Ok::<_, BoxError>(Response::from(http::Response::new(
+                body.unwrap_or_default(),
+            )))
+        });
+        let mut service = SignLayer::with_merchant(merchant).layer(transport);
+        let url = "https://api.mch.weixin.qq.com/v3/merchant/media/upload";
+
+        // 有 meta 时签名 meta，而非 body
+        let mut req = Request::new(reqwest::Method::POST, url.parse().unwrap());
+        *req.body_mut() = Some("file".into());
+        let (req, _) = with_extensions(req, |ext| {
+            ext.insert(SignMeta(r#"{"filename":"a.jpg"}"#.to_string()))
+        })?;
+        let res = service.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(res.bytes().await?.as_ref(), b"file");
+        let message = signer.messages.lock().unwrap().pop().unwrap();
+        assert!(
+            message.ends_with("\n{\"filename\":\"a.jpg\"}\n"),
+            "{}",
+            message
+        );
+
+        // 没有 meta 时签名 body
+        let req = Request::new(reqwest::Method::GET, url.parse().unwrap());
+        let res = service.ready().await.unwrap().call(req).await.unwrap();
+        assert!(res.bytes().await?.is_empty());
+        let message = signer.messages.lock().unwrap().pop().unwrap();
+        assert!(message.starts_with("GET\n/v3/merchant/media/upload\n"));
+        assert!(message.ends_with("\n\n"), "{}", message);
+        Ok(())
+    }
+}
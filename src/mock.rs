@@ -0,0 +1,1031 @@
+//! 内存中的微信支付模拟服务，用于测试。需要开启 `mock` feature。
+//!
+//! [`MockServer`] 实现了 JSAPI/APP/H5/Native 下单、查询、关单、退款，合单支付，
+//! 以及收付通的分账、退款、余额、提现、进件等接口。订单状态保存在内存中，
+//! 响应使用平台私钥签名，可以通过 WechatPayClient 的验签。
+//! 默认使用 `testdata` 中的测试平台证书及私钥。
+//!
+//! 既可以作为 [`Transport`] 在进程内使用，也可以通过 [`MockServer::start`] 监听本地端口，
+//! 并通过 [`MockServer::notify`] 向回调地址发送已签名、已加密的通知。
+//!
+//! ```ignore
+//! let server = MockServer::new(&credential.mch_api_v3_key);
+//! let client = WechatPayClient::builder()
+//!     .mch_credential(credential)
+//!     .platform_certificate(server.platform_certificate())
+//!     .transport(server.clone())
+//!     .build()
+//!     .await?;
+//!
+//! let prepay_id = client.jsapi_create_trade(&params).await?;
+//! // 模拟用户完成支付，并向 notify_url 发送支付通知
+//! server.pay(&params.out_trade_no)?;
+//! server.notify(server.trade_notification(&params.out_trade_no)?).await?;
+//! ```
+
+#[cfg(test)]
+pub(crate) mod fixtures;
+mod state;
+
+use crate::credential::generate_none_str;
+use crate::error::{Error, Result};
+use crate::platform_certificate::PlatformCertificate;
+use crate::refund::RefundStatus;
+use crate::trade::TradeState;
+use crate::transport::{sign_headers, Transport};
+use crate::util::DATETIME_FORMAT;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use async_trait::async_trait;
+use base64::prelude::*;
+use bytes::Bytes;
+use chrono::Local;
+use http::header::{AUTHORIZATION, CONTENT_TYPE, HOST};
+use http::{Method, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use rand::Rng;
+use reqwest::{Request, Response};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs1v15::{Signature, SigningKey, VerifyingKey};
+use rsa::sha2::Sha256;
+use rsa::signature::Verifier;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde_json::{json, Value};
+use state::{Failure, MockState, Reply};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::net::TcpListener;
+use tokio::task::{JoinHandle, JoinSet};
+
+const PLATFORM_KEY: &str = include_str!("../testdata/platform_key.pem");
+const PLATFORM_CERT: &str = include_str!("../testdata/platform_cert.pem");
+
+/// 内存中的微信支付模拟服务。clone 后共享同一份状态。
+#[derive(Clone)]
+pub struct MockServer {
+    state: Arc<Mutex<MockState>>,
+    requests: Arc<Mutex<Vec<http::Request<Bytes>>>>,
+    platform: Arc<MockPlatform>,
+    /// 设置后，校验请求的签名。
+    mch_public_key: Option<Arc<VerifyingKey<Sha256>>>,
+}
+
+/// 平台证书、私钥，以及商户的 API v3 密钥。
+struct MockPlatform {
+    api_v3_key: String,
+    certificate_pem: String,
+    certificate: PlatformCertificate,
+    signing_key: SigningKey<Sha256>,
+}
+
+impl MockServer {
+    /// 使用测试平台证书及私钥。`api_v3_key` 用于加密通知及平台证书下载接口的响应。
+    pub fn new(api_v3_key: impl Into<String>) -> MockServer {
+        let private_key = RsaPrivateKey::from_pkcs1_pem(PLATFORM_KEY).unwrap();
+        MockServer::with_platform_certificate(api_v3_key, PLATFORM_CERT, private_key).unwrap()
+    }
+
+    /// 使用给定的平台证书(PEM 格式)及其私钥。
+    pub fn with_platform_certificate(
+        api_v3_key: impl Into<String>,
+        certificate_pem: &str,
+        private_key: RsaPrivateKey,
+    ) -> Result<MockServer> {
+        let certificate = PlatformCertificate::from_pem(certificate_pem)?;
+        Ok(MockServer {
+            state: Arc::new(Mutex::new(MockState::new(private_key.clone()))),
+            requests: Default::default(),
+            platform: Arc::new(MockPlatform {
+                api_v3_key: api_v3_key.into(),
+                certificate_pem: certificate_pem.to_string(),
+                certificate,
+                signing_key: SigningKey::new(private_key),
+            }),
+            mch_public_key: None,
+        })
+    }
+
+    /// 使用商户公钥校验请求的签名。未设置时只检查 Authorization 头部是否存在。
+    /// 应答中的敏感信息字段也使用此公钥加密，未设置时以明文返回。
+    pub fn verify_requests(mut self, mch_public_key: RsaPublicKey) -> Self {
+        self.state.lock().unwrap().mch_public_key = Some(mch_public_key.clone());
+        self.mch_public_key = Some(Arc::new(VerifyingKey::new(mch_public_key)));
+        self
+    }
+
+    /// 平台证书。WechatPayClient 使用它对模拟服务的响应验签。
+    pub fn platform_certificate(&self) -> PlatformCertificate {
+        self.platform.certificate.clone()
+    }
+
+    /// 监听 `127.0.0.1` 的随机端口。返回的 handle 被 drop 时停止服务。
+    pub async fn start(&self) -> Result<MockServerHandle> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = self.clone();
+        let task = tokio::spawn(async move {
+            // 连接任务归属于监听任务，停止服务时一并断开。
+            let mut connections = JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                while connections.try_join_next().is_some() {}
+                let server = server.clone();
+                connections.spawn(async move {
+                    let service = service_fn(move |req: http::Request<hyper::body::Incoming>| {
+                        let server = server.clone();
+                        async move {
+                            let (parts, body) = req.into_parts();
+                            let body = body.collect().await?.to_bytes();
+                            let res = server.handle(http::Request::from_parts(parts, body));
+                            Ok::<_, hyper::Error>(res.map(Full::new))
+                        }
+                    });
+                    let io = TokioIo::new(stream);
+                    if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
+                        log::debug!("mock server connection error: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(MockServerHandle {
+            base_url: format!("http://{}", addr),
+            task,
+        })
+    }
+
+    /// 处理一个请求。
+    pub fn handle(&self, req: http::Request<Bytes>) -> http::Response<Bytes> {
+        self.requests.lock().unwrap().push(req.clone());
+        let (status, body) = match self.dispatch(&req) {
+            Ok(Reply::Json(v)) => (StatusCode::OK, Bytes::from(v.to_string())),
+            Ok(Reply::Accepted(v)) => (StatusCode::ACCEPTED, Bytes::from(v.to_string())),
+            Ok(Reply::NoContent) => (StatusCode::NO_CONTENT, Bytes::new()),
+            Ok(Reply::File(file)) => {
+                return http::Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .header("Request-ID", request_id())
+                    .body(Bytes::from(file))
+                    .unwrap();
+            }
+            Err(failure) => {
+                return http::Response::builder()
+                    .status(failure.status)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header("Request-ID", request_id())
+                    .body(Bytes::from(failure.body().to_string()))
+                    .unwrap();
+            }
+        };
+        let mut res = http::Response::builder()
+            .status(status)
+            .header("Request-ID", request_id());
+        if !body.is_empty() {
+            res = res.header(CONTENT_TYPE, "application/json");
+        }
+        let mut res = res.body(body.clone()).unwrap();
+        let platform = &self.platform;
+        sign_headers(
+            res.headers_mut(),
+            &platform.certificate.public_id,
+            &platform.signing_key,
+            &body,
+        );
+        res
+    }
+
+    fn dispatch(&self, req: &http::Request<Bytes>) -> Result<Reply, Failure> {
+        let mch_id = self.authorize(req)?;
+        if let Some(failure) = self.state().failures.pop_front() {
+            return Err(failure);
+        }
+
+        let path_and_query = req.uri().path_and_query().map_or("/", |p| p.as_str());
+        let url = reqwest::Url::parse(&format!("http://localhost{}", path_and_query))
+            .map_err(|e| Failure::new(StatusCode::BAD_REQUEST, "PARAM_ERROR", e.to_string()))?;
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        if req.method() == Method::GET && url.path() == "/v3/certificates" {
+            return Ok(Reply::Json(self.certificates()));
+        }
+        self.state().handle(
+            &mch_id,
+            &origin(req),
+            req.method(),
+            url.path(),
+            &query,
+            req.body(),
+        )
+    }
+
+    /// 检查请求的 Authorization 头部，返回其中的商户号。
+    fn authorize(&self, req: &http::Request<Bytes>) -> Result<String, Failure> {
+        let sign_error = || Failure::new(StatusCode::UNAUTHORIZED, "SIGN_ERROR", "签名错误");
+        let authorization = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+        let params = authorization
+            .and_then(|s| s.strip_prefix("WECHATPAY2-SHA256-RSA2048 "))
+            .ok_or_else(sign_error)?;
+        let params: HashMap<&str, &str> = params
+            .split(',')
+            .filter_map(|kv| kv.split_once('='))
+            .map(|(k, v)| (k.trim(), v.trim_matches('"')))
+            .collect();
+        let mch_id = params.get("mchid").ok_or_else(sign_error)?;
+
+        // multipart 请求的签名内容为 meta，而非 body，此时不校验签名。
+        let is_multipart = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("multipart/"));
+        if let (Some(verifying_key), false) = (&self.mch_public_key, is_multipart) {
+            let path_and_query = req.uri().path_and_query().map_or("/", |p| p.as_str());
+            let mut msg = format!(
+                "{}\n{}\n{}\n{}\n",
+                req.method(),
+                path_and_query,
+                params.get("timestamp").ok_or_else(sign_error)?,
+                params.get("nonce_str").ok_or_else(sign_error)?,
+            )
+            .into_bytes();
+            msg.extend_from_slice(req.body());
+            msg.push(b'\n');
+            let signature = params.get("signature").ok_or_else(sign_error)?;
+            let signature = BASE64_STANDARD
+                .decode(signature)
+                .map_err(|_| sign_error())?;
+            let signature = Signature::try_from(signature.as_slice()).map_err(|_| sign_error())?;
+            verifying_key
+                .verify(&msg, &signature)
+                .map_err(|_| sign_error())?;
+        }
+        Ok(mch_id.to_string())
+    }
+
+    /// 平台证书下载接口的响应。
+    fn certificates(&self) -> Value {
+        let platform = &self.platform;
+        let (ciphertext, nonce) =
+            self.aes_encrypt(platform.certificate_pem.as_bytes(), "certificate");
+        let certificate = &platform.certificate;
+        let format_time =
+            |t: Option<chrono::DateTime<Local>>| t.map(|t| t.format(DATETIME_FORMAT).to_string());
+        json!({
+            "data": [{
+                "serial_no": certificate.public_id,
+                "effective_time": format_time(certificate.effective_time)
+                    .unwrap_or_else(|| "2000-01-01T00:00:00+08:00".to_string()),
+                "expire_time": format_time(certificate.expire_time)
+                    .unwrap_or_else(|| "2099-01-01T00:00:00+08:00".to_string()),
+                "encrypt_certificate": {
+                    "algorithm": "AEAD_AES_256_GCM",
+                    "nonce": nonce,
+                    "associated_data": "certificate",
+                    "ciphertext": ciphertext,
+                },
+            }],
+        })
+    }
+
+    /// 使用 API v3 密钥加密，返回 base64 编码的密文及随机串。
+    fn aes_encrypt(&self, plain: &[u8], associated_data: &str) -> (String, String) {
+        let nonce = generate_none_str(12);
+        let cipher = Aes256Gcm::new(self.platform.api_v3_key.as_bytes().into());
+        let payload = Payload {
+            msg: plain,
+            aad: associated_data.as_bytes(),
+        };
+        let ciphertext = cipher
+            .encrypt(nonce.as_bytes().into(), payload)
+            .expect("invalid api v3 key");
+        (BASE64_STANDARD.encode(ciphertext), nonce)
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// 已收到的请求。
+    pub fn requests(&self) -> Vec<http::Request<Bytes>> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// 下一个请求返回给定的错误，用于测试错误处理及重试。可多次调用，按顺序返回。
+    pub fn fail_next(&self, status: u16, code: &str, message: &str) {
+        let status = StatusCode::from_u16(status).expect("invalid status code");
+        self.state()
+            .failures
+            .push_back(Failure::new(status, code, message));
+    }
+
+    /// 模拟用户完成支付。订单未支付时置为支付成功，并分配微信支付订单号。
+    pub fn pay(&self, out_trade_no: &str) -> Result<()> {
+        self.state()
+            .pay(out_trade_no)
+            .map(|_| ())
+            .ok_or_else(|| trade_not_found(out_trade_no))
+    }
+
+    /// 模拟用户完成合单支付，所有子单置为支付成功。
+    pub fn pay_combine(&self, combine_out_trade_no: &str) -> Result<()> {
+        let mut state = self.state();
+        let combine = state.combine_trades.get(combine_out_trade_no);
+        let combine = combine.ok_or_else(|| trade_not_found(combine_out_trade_no))?;
+        for out_trade_no in combine.sub_orders.clone() {
+            state.pay(&out_trade_no);
+        }
+        Ok(())
+    }
+
+    /// 直接设置订单状态，如 `USERPAYING`、`PAYERROR`。
+    pub fn set_trade_state(&self, out_trade_no: &str, trade_state: TradeState) -> Result<()> {
+        if trade_state == TradeState::Success {
+            return self.pay(out_trade_no);
+        }
+        let mut state = self.state();
+        let trade = state.trades.get_mut(out_trade_no);
+        trade
+            .ok_or_else(|| trade_not_found(out_trade_no))?
+            .trade_state = trade_state;
+        Ok(())
+    }
+
+    /// 将处理中的退款单置为给定状态。退款单创建时为 `PROCESSING`。
+    pub fn complete_refund(&self, out_refund_no: &str, status: RefundStatus) -> Result<()> {
+        self.state()
+            .complete_refund(out_refund_no, status)
+            .map(|_| ())
+            .ok_or_else(|| Error::InvalidArgument(format!("refund not found: {}", out_refund_no)))
+    }
+
+    /// 设置二级商户的可用余额，单位为分。
+    pub fn set_sub_mch_balance(&self, sub_mchid: &str, amount: i64) {
+        self.state().set_balance(Some(sub_mchid), "", amount);
+    }
+
+    /// 设置平台账户的可用余额，单位为分。`account_type` 如 `BASIC`、`OPERATION`、`FEES`。
+    pub fn set_platform_balance(&self, account_type: &str, amount: i64) {
+        self.state().set_balance(None, account_type, amount);
+    }
+
+    /// 设置进件申请单的状态。申请单创建时为 `CHECKING`；置为 `FINISH` 时分配二级商户号。
+    pub fn set_applyment_state(&self, out_request_no: &str, applyment_state: &str) -> Result<()> {
+        if self
+            .state()
+            .set_applyment_state(out_request_no, applyment_state)
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidArgument(format!(
+                "applyment not found: {}",
+                out_request_no
+            )))
+        }
+    }
+
+    /// 构造发往 `notify_url` 的通知：资源使用 API v3 密钥加密，请求使用平台私钥签名。
+    pub fn notification(
+        &self,
+        notify_url: &str,
+        event_type: &str,
+        original_type: &str,
+        summary: &str,
+        resource: &Value,
+    ) -> Result<http::Request<Bytes>> {
+        let (ciphertext, nonce) = self.aes_encrypt(resource.to_string().as_bytes(), original_type);
+        let body = json!({
+            "id": notification_id(),
+            "create_time": Local::now().format(DATETIME_FORMAT).to_string(),
+            "event_type": event_type,
+            "resource_type": "encrypt-resource",
+            "resource": {
+                "algorithm": "AEAD_AES_256_GCM",
+                "ciphertext": ciphertext,
+                "associated_data": original_type,
+                "original_type": original_type,
+                "nonce": nonce,
+            },
+            "summary": summary,
+        });
+        let body = Bytes::from(body.to_string());
+
+        let mut req = http::Request::builder()
+            .method(Method::POST)
+            .uri(notify_url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)?;
+        let platform = &self.platform;
+        let body = req.body().clone();
+        sign_headers(
+            req.headers_mut(),
+            &platform.certificate.public_id,
+            &platform.signing_key,
+            &body,
+        );
+        Ok(req)
+    }
+
+    /// 重发通知：通知内容(包括通知 ID)不变，使用新的时间戳与随机串重新签名，
+    /// 与微信支付未收到成功应答时的重发一致。
+    pub fn redeliver(&self, notification: &http::Request<Bytes>) -> Result<http::Request<Bytes>> {
+        let mut req = http::Request::builder()
+            .method(notification.method())
+            .uri(notification.uri())
+            .header(CONTENT_TYPE, "application/json")
+            .body(notification.body().clone())?;
+        let platform = &self.platform;
+        let body = req.body().clone();
+        sign_headers(
+            req.headers_mut(),
+            &platform.certificate.public_id,
+            &platform.signing_key,
+            &body,
+        );
+        Ok(req)
+    }
+
+    /// 支付成功通知，发往下单时的 `notify_url`。订单须已支付。
+    pub fn trade_notification(&self, out_trade_no: &str) -> Result<http::Request<Bytes>> {
+        let (notify_url, resource) = {
+            let state = self.state();
+            let trade = state.trades.get(out_trade_no);
+            let trade = trade.ok_or_else(|| trade_not_found(out_trade_no))?;
+            if trade.transaction_id.is_none() {
+                return Err(Error::InvalidArgument(format!(
+                    "trade not paid: {}",
+                    out_trade_no
+                )));
+            }
+            (trade.notify_url.clone(), trade.to_json())
+        };
+        self.notification(
+            &notify_url,
+            "TRANSACTION.SUCCESS",
+            "transaction",
+            "支付成功",
+            &resource,
+        )
+    }
+
+    /// 合单支付成功通知。所有子单须已支付。
+    pub fn combine_trade_notification(
+        &self,
+        combine_out_trade_no: &str,
+    ) -> Result<http::Request<Bytes>> {
+        let (notify_url, resource) = {
+            let state = self.state();
+            let combine = state.combine_trades.get(combine_out_trade_no);
+            let combine = combine.ok_or_else(|| trade_not_found(combine_out_trade_no))?;
+            let paid = combine
+                .sub_orders
+                .iter()
+                .all(|no| state.trades[no].transaction_id.is_some());
+            if !paid {
+                return Err(Error::InvalidArgument(format!(
+                    "trade not paid: {}",
+                    combine_out_trade_no
+                )));
+            }
+            (
+                combine.notify_url.clone(),
+                state.combine_trade_json(combine),
+            )
+        };
+        self.notification(
+            &notify_url,
+            "TRANSACTION.SUCCESS",
+            "transaction",
+            "支付成功",
+            &resource,
+        )
+    }
+
+    /// 退款结果通知，发往申请退款时的 `notify_url`。退款单须已完成，见 [`MockServer::complete_refund`]。
+    pub fn refund_notification(&self, out_refund_no: &str) -> Result<http::Request<Bytes>> {
+        let not_found = || Error::InvalidArgument(format!("refund not found: {}", out_refund_no));
+        let (notify_url, status, resource) = {
+            let state = self.state();
+            let refund = state.refunds.get(out_refund_no).ok_or_else(not_found)?;
+            let notify_url = refund.notify_url.clone().ok_or_else(|| {
+                Error::InvalidArgument(format!("refund has no notify_url: {}", out_refund_no))
+            })?;
+            (notify_url, refund.status, refund.to_notify_json())
+        };
+        let (event_type, summary) = match status {
+            RefundStatus::Success => ("REFUND.SUCCESS", "退款成功"),
+            RefundStatus::Closed => ("REFUND.CLOSED", "退款关闭"),
+            RefundStatus::Abnormal => ("REFUND.ABNORMAL", "退款异常"),
+            RefundStatus::Processing => {
+                return Err(Error::InvalidArgument(format!(
+                    "refund is processing: {}",
+                    out_refund_no
+                )))
+            }
+        };
+        self.notification(&notify_url, event_type, "refund", summary, &resource)
+    }
+
+    /// 分账动账通知，每个接收方一个通知。分账回调地址在商户平台配置，因此需要传入。
+    pub fn profit_sharing_notifications(
+        &self,
+        notify_url: &str,
+        out_order_no: &str,
+    ) -> Result<Vec<http::Request<Bytes>>> {
+        let sharing = self.state().profit_sharings.get(out_order_no).cloned();
+        let sharing = sharing.ok_or_else(|| {
+            Error::InvalidArgument(format!("profit sharing not found: {}", out_order_no))
+        })?;
+        sharing
+            .receivers
+            .iter()
+            .map(|receiver| {
+                let resource = json!({
+                    "sp_mchid": sharing.sp_mch_id,
+                    "sub_mchid": sharing.sub_mch_id,
+                    "transaction_id": sharing.transaction_id,
+                    "order_id": sharing.order_id,
+                    "out_order_no": sharing.out_order_no,
+                    "receiver": {
+                        "type": receiver["type"],
+                        "account": receiver["receiver_account"],
+                        "amount": receiver["amount"],
+                        "description": receiver["description"],
+                    },
+                    "success_time": receiver["finish_time"],
+                });
+                self.notification(
+                    notify_url,
+                    "PROFITSHARING.SUCCESS",
+                    "profitsharing",
+                    "分账成功",
+                    &resource,
+                )
+            })
+            .collect()
+    }
+
+    /// 将通知发送到其 uri，返回商户的应答。
+    pub async fn notify(&self, req: http::Request<Bytes>) -> Result<http::Response<Bytes>> {
+        let (parts, body) = req.into_parts();
+        let res = reqwest::Client::new()
+            .request(parts.method, parts.uri.to_string())
+            .headers(parts.headers)
+            .body(body)
+            .send()
+            .await?;
+        let mut builder = http::Response::builder().status(res.status());
+        for (name, value) in res.headers() {
+            builder = builder.header(name, value);
+        }
+        Ok(builder.body(res.bytes().await?)?)
+    }
+}
+
+#[async_trait]
+impl Transport for MockServer {
+    async fn send(&self, req: Request) -> Result<Response> {
+        let mut http_req = http::Request::builder()
+            .method(req.method().clone())
+            .uri(req.url().as_str())
+            .body(Bytes::copy_from_slice(
+                req.body().and_then(|b| b.as_bytes()).unwrap_or_default(),
+            ))?;
+        *http_req.headers_mut() = req.headers().clone();
+        Ok(self.handle(http_req).into())
+    }
+}
+
+impl fmt::Debug for MockServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockServer")
+            .field("platform_serial", &self.platform.certificate.public_id)
+            .field("verify_requests", &self.mch_public_key.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// 监听本地端口的模拟服务。drop 时停止服务。
+#[derive(Debug)]
+pub struct MockServerHandle {
+    base_url: String,
+    task: JoinHandle<()>,
+}
+
+impl MockServerHandle {
+    /// 服务的根地址，如 `http://127.0.0.1:34567`。可传给
+    /// [`WechatPayClientBuilder::base_url`](crate::client::WechatPayClientBuilder::base_url)。
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+impl Drop for MockServerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn trade_not_found(out_trade_no: &str) -> Error {
+    Error::InvalidArgument(format!("trade not found: {}", out_trade_no))
+}
+
+fn request_id() -> String {
+    format!("08{}", generate_none_str(16).to_ascii_uppercase())
+}
+
+/// 请求的根地址，用于生成账单下载地址。
+/// 通过 Transport 调用时取请求 URL，监听本地端口时取 `Host` 头部。
+fn origin(req: &http::Request<Bytes>) -> String {
+    let uri = req.uri();
+    if let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) {
+        return format!("{}://{}", scheme, authority);
+    }
+    let host = req.headers().get(HOST).and_then(|v| v.to_str().ok());
+    format!("http://{}", host.unwrap_or("localhost"))
+}
+
+/// 形如 UUID 的通知 ID。
+fn notification_id() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::*;
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::notify::{EventType, NotificationEvent, WechatPayNotification};
+    use crate::partner::shou_fu_tong::{self, ShouFuTong};
+    use crate::replay::Redelivery;
+    use crate::WechatPayClient;
+
+    #[tokio::test]
+    async fn test_trade_lifecycle() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+
+        let params = jsapi_params("1217752501201407033233368018");
+        let prepay_id = client.jsapi_create_trade(&params).await?;
+        assert!(prepay_id.starts_with("wx"));
+        assert_eq!(client.jsapi_create_trade(&params).await?, prepay_id);
+
+        let trade = client
+            .query_trade_by_out_trade_no("1217752501201407033233368018")
+            .await?;
+        assert_eq!(trade.trade_state, TradeState::NotPay);
+        assert!(trade.transaction_id.is_none());
+
+        server.pay("1217752501201407033233368018")?;
+        let trade = client
+            .query_trade_by_out_trade_no("1217752501201407033233368018")
+            .await?;
+        assert_eq!(trade.trade_state, TradeState::Success);
+        assert_eq!(trade.amount.unwrap().payer_total, Some(100));
+        let transaction_id = trade.transaction_id.unwrap();
+        let trade = client
+            .query_trade_by_transaction_id(&transaction_id)
+            .await?;
+        assert_eq!(trade.attach.as_deref(), Some("自定义数据"));
+
+        let err = client
+            .close_trade("1217752501201407033233368018")
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::OrderPaid));
+
+        let params = refund_params(
+            "1217752501201407033233368018",
+            "1217752501201407033233368019",
+            30,
+        );
+        let refund = client.apply_refund(&params).await?;
+        assert_eq!(refund.status, RefundStatus::Processing);
+        assert_eq!(refund.transaction_id, transaction_id);
+        let params = refund_params(
+            "1217752501201407033233368018",
+            "1217752501201407033233368020",
+            80,
+        );
+        let err = client.apply_refund(&params).await.unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::InvalidRequest));
+
+        server.complete_refund("1217752501201407033233368019", RefundStatus::Success)?;
+        let refund = client.query_refund("1217752501201407033233368019").await?;
+        assert_eq!(refund.status, RefundStatus::Success);
+        assert!(refund.success_time.is_some());
+        let trade = client
+            .query_trade_by_transaction_id(&transaction_id)
+            .await?;
+        assert_eq!(trade.trade_state, TradeState::Refund);
+
+        client
+            .native_create_trade(&native_params("1217752501201407033233368021"))
+            .await?;
+        client.close_trade("1217752501201407033233368021").await?;
+        let trade = client
+            .query_trade_by_out_trade_no("1217752501201407033233368021")
+            .await?;
+        assert_eq!(trade.trade_state, TradeState::Closed);
+
+        let err = client
+            .query_trade_by_out_trade_no("not-exist")
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::OrderNotExist));
+
+        // 参数不合法时不发送请求
+        let sent = server.requests().len();
+        let mut params = native_params("1217752501201407033233368022");
+        params.amount.total = 0;
+        let err = client.native_create_trade(&params).await.unwrap_err();
+        assert!(matches!(err, crate::Error::Validation(_)));
+        assert_eq!(server.requests().len(), sent);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fail_next_and_signature() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+
+        server.fail_next(500, "SYSTEM_ERROR", "系统错误");
+        let err = client
+            .native_create_trade(&native_params("1217752501201407033233368018"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::SystemError));
+        assert!(err.is_retryable());
+
+        let code_url = client
+            .native_create_trade(&native_params("1217752501201407033233368018"))
+            .await?;
+        assert!(code_url.starts_with("weixin://wxpay/bizpayurl?pr="));
+
+        // 未签名的请求
+        let res = server.handle(
+            http::Request::get("/v3/pay/transactions/out-trade-no/1217752501201407033233368018")
+                .body(Bytes::new())?,
+        );
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // 签名与商户公钥不匹配
+        let mut other = test_credential();
+        other.mch_rsa_private_key = other_mch_private_key();
+        let other = WechatPayClient::builder()
+            .mch_credential(other)
+            .platform_certificate(server.platform_certificate())
+            .transport(server.clone())
+            .build()
+            .await?;
+        let err = other
+            .query_trade_by_out_trade_no("1217752501201407033233368018")
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::SignError));
+        assert_eq!(server.requests().len(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notifications() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+
+        client
+            .jsapi_create_trade(&jsapi_params("1217752501201407033233368018"))
+            .await?;
+        assert!(server
+            .trade_notification("1217752501201407033233368018")
+            .is_err());
+        server.pay("1217752501201407033233368018")?;
+
+        let req = server.trade_notification("1217752501201407033233368018")?;
+        assert_eq!(req.uri(), NOTIFY_URL);
+        let req = client.verify_notification(req).await?;
+        let notification: WechatPayNotification = serde_json::from_slice(req.body())?;
+        assert_eq!(notification.event_type, EventType::TransactionSuccess);
+        match client.decrypt_notification(&notification)? {
+            NotificationEvent::Trade(trade) => {
+                assert_eq!(trade.out_trade_no, "1217752501201407033233368018");
+                assert_eq!(trade.trade_state, TradeState::Success);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        let params = refund_params(
+            "1217752501201407033233368018",
+            "1217752501201407033233368019",
+            100,
+        );
+        client.apply_refund(&params).await?;
+        assert!(server
+            .refund_notification("1217752501201407033233368019")
+            .is_err());
+        server.complete_refund("1217752501201407033233368019", RefundStatus::Success)?;
+        let req = server.refund_notification("1217752501201407033233368019")?;
+        let req = client.verify_notification(req).await?;
+        let notification: WechatPayNotification = serde_json::from_slice(req.body())?;
+        assert_eq!(notification.event_type, EventType::RefundSuccess);
+        match client.decrypt_notification(&notification)? {
+            NotificationEvent::Refund(refund) => {
+                assert_eq!(refund.out_refund_no, "1217752501201407033233368019");
+                assert_eq!(refund.refund_status, RefundStatus::Success);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        // 原样重放的通知被拒绝；重发的通知被标记
+        let req = server.trade_notification("1217752501201407033233368018")?;
+        let redelivered = server.redeliver(&req)?;
+        let verified = client.verify_notification(req.clone()).await?;
+        assert!(verified.extensions().get::<Redelivery>().is_none());
+        let notification: WechatPayNotification = serde_json::from_slice(verified.body())?;
+        client.commit_notification(&notification.id).await?;
+        assert!(matches!(
+            client.verify_notification(req).await,
+            Err(Error::Replay(_))
+        ));
+        let verified = client.verify_notification(redelivered).await?;
+        assert!(verified.extensions().get::<Redelivery>().is_some());
+
+        // 篡改后的通知无法通过验签
+        let req = server.trade_notification("1217752501201407033233368018")?;
+        let (parts, _) = req.into_parts();
+        let req = http::Request::from_parts(parts, Bytes::from_static(b"{}"));
+        assert!(client.verify_notification(req).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_over_http() -> anyhow::Result<()> {
+        let server = test_server();
+        let handle = server.start().await?;
+        let client = WechatPayClient::builder()
+            .mch_credential(test_credential())
+            .base_url(handle.base_url())
+            .fetch_platform_certificates()
+            .build()
+            .await?;
+        assert!(client
+            .platform_keyring()
+            .contains(&server.platform_certificate().public_id));
+
+        let mut params = jsapi_params("1217752501201407033233368018");
+        // 商户的回调地址：验签、解密后应答
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        params.notify_url = format!("http://{}/notify", listener.local_addr()?);
+        let receiver = client.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(move |req: http::Request<hyper::body::Incoming>| {
+                let client = receiver.clone();
+                async move {
+                    let (parts, body) = req.into_parts();
+                    let body = body.collect().await?.to_bytes();
+                    let req = http::Request::from_parts(parts, body);
+                    let verified = client.verify_notification(req).await.is_ok();
+                    let status = if verified { 200 } else { 401 };
+                    let res = http::Response::builder()
+                        .status(status)
+                        .body(Full::new(Bytes::new()))
+                        .unwrap();
+                    Ok::<_, hyper::Error>(res)
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        client.jsapi_create_trade(&params).await?;
+        server.pay("1217752501201407033233368018")?;
+        let res = server
+            .notify(server.trade_notification("1217752501201407033233368018")?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        drop(handle);
+        assert!(client
+            .query_trade_by_out_trade_no("1217752501201407033233368018")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ecommerce() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+
+        let combine = json!({
+            "combine_appid": "wxd678efh567hg6787",
+            "combine_mchid": "1900000001",
+            "combine_out_trade_no": "P20150806125346",
+            "sub_orders": [{
+                "mchid": "1900000001",
+                "sub_mchid": "1900000109",
+                "attach": "深圳分店",
+                "amount": { "total_amount": 100, "currency": "CNY" },
+                "out_trade_no": "20150806125346",
+                "description": "腾讯充值中心-QQ会员充值",
+            }],
+            "combine_payer_info": { "openid": "oUpF8uMuAJO_M2pxb1Q9zNjWeS6o" },
+            "notify_url": NOTIFY_URL,
+        });
+        let url = format!("{}/combine-transactions/jsapi", client.v3_url());
+        let req = client.client.post(url).json(&combine).build()?;
+        client.execute(req, None).await?;
+
+        let order = client.query_combine_order("P20150806125346").await?;
+        assert_eq!(order.sub_orders.as_ref().unwrap()[0].trade_state, "NOTPAY");
+        server.pay_combine("P20150806125346")?;
+        let order = client.query_combine_order("P20150806125346").await?;
+        let sub_order = &order.sub_orders.unwrap()[0];
+        assert_eq!(sub_order.trade_state, "SUCCESS");
+        let transaction_id = sub_order.transaction_id.clone().unwrap();
+
+        let req = client.verify_notification(server.combine_trade_notification("P20150806125346")?);
+        let req = req.await?;
+        let notification: WechatPayNotification = serde_json::from_slice(req.body())?;
+        match client.decrypt_shou_fu_tong_notification(&notification)? {
+            shou_fu_tong::NotificationEvent::Trade(data) => {
+                assert_eq!(data.sub_orders[0].transaction_id, transaction_id)
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        let share = shou_fu_tong::profit_sharing::share_apply::ShareRequestBody {
+            appid: "wxd678efh567hg6787".to_string(),
+            sub_mchid: "1900000109".to_string(),
+            transaction_id: transaction_id.clone(),
+            out_order_no: "P20150806125346".to_string(),
+            receivers: vec![shou_fu_tong::profit_sharing::share_apply::Receiver {
+                receiver_type: "MERCHANT_ID".to_string(),
+                receiver_account: "1900000110".to_string(),
+                amount: 30,
+                description: "分给商户1900000110".to_string(),
+                receiver_name: None,
+            }],
+            finish: false,
+        };
+        assert_eq!(client.share_request(&share).await?.status, "FINISHED");
+        let remainder = shou_fu_tong::profit_sharing::share_remainder::share_remainder_query(
+            &client,
+            &transaction_id,
+        )
+        .await?;
+        assert_eq!(remainder.unsplit_amount, 70);
+        let notifications = server.profit_sharing_notifications(NOTIFY_URL, "P20150806125346")?;
+        assert_eq!(notifications.len(), 1);
+
+        server.set_sub_mch_balance("1900000109", 1000);
+        let balance =
+            shou_fu_tong::fund_balance::query_sub_mch_balance(&client, "1900000109", "BASIC")
+                .await?;
+        assert_eq!(balance.available_amount, 1000);
+        let withdraw = shou_fu_tong::fund_withdraw::SubMchWithdrawalRequest {
+            sub_mchid: "1900000109".to_string(),
+            out_request_no: "20190611222222222200000000012122".to_string(),
+            amount: 2000,
+            remark: None,
+            bank_memo: None,
+            account_type: None,
+            notify_url: None,
+        };
+        let err = shou_fu_tong::fund_withdraw::sub_mch_withdraw(&client, &withdraw)
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::NotEnough));
+
+        let url = format!("{}/ecommerce/applyments/", client.v3_url());
+        let req = client
+            .client
+            .post(url)
+            .json(&json!({ "out_request_no": "APPLYMENT_00000000001" }))
+            .build()?;
+        client.execute(req, None).await?;
+        server.set_applyment_state("APPLYMENT_00000000001", "FINISH")?;
+        let applyment = shou_fu_tong::applyment::apply_query::query_applyment_by_out_request_no(
+            &client,
+            "APPLYMENT_00000000001",
+        )
+        .await?;
+        assert_eq!(applyment.applyment_state, "FINISH");
+        assert!(applyment.sub_mchid.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! 测试共用的商户证书、客户端及下单参数。
+
+use super::MockServer;
+use crate::platform_certificate::PlatformCertificate;
+use crate::refund::{RefundApplyingAmount, RefundParams, TradeId};
+use crate::trade::{Amount, JsApiCreateTradeParams, NativeCreateTradeParams};
+use crate::{MchCredential, WechatPayClient};
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::RsaPrivateKey;
+
+pub(crate) const MCH_ID: &str = "1900000001";
+pub(crate) const MCH_SERIAL_NO: &str = "444F4E544C4F4F4B4154544845534552494E414C";
+pub(crate) const API_V3_KEY: &str = "0123456789abcdef0123456789abcdef";
+pub(crate) const NOTIFY_URL: &str = "https://www.weixin.qq.com/wxpay/pay.php";
+/// `testdata/platform_cert.pem` 的序列号
+pub(crate) const PLATFORM_SERIAL: &str = "5157F09EFDC096DE15EBE81A47057A7232F1B8E1";
+
+/// 测试商户的证书及密钥，私钥为 `testdata/apiclient_key.pem`。
+pub(crate) fn test_credential() -> MchCredential {
+    MchCredential {
+        mch_id: MCH_ID.to_string(),
+        mch_certificate_serial_no: MCH_SERIAL_NO.to_string(),
+        mch_rsa_private_key: RsaPrivateKey::from_pkcs8_pem(include_str!(
+            "../../testdata/apiclient_key.pem"
+        ))
+        .unwrap(),
+        mch_api_v3_key: API_V3_KEY.to_string(),
+    }
+}
+
+/// 另一个商户私钥 `testdata/other_apiclient_key.pem`，用于测试签名与商户公钥不匹配。
+pub(crate) fn other_mch_private_key() -> RsaPrivateKey {
+    RsaPrivateKey::from_pkcs8_pem(include_str!("../../testdata/other_apiclient_key.pem")).unwrap()
+}
+
+/// `testdata/platform_cert.pem` 中的测试平台证书。
+pub(crate) fn test_platform_certificate() -> PlatformCertificate {
+    PlatformCertificate::from_pem(include_str!("../../testdata/platform_cert.pem")).unwrap()
+}
+
+/// 校验请求签名的模拟服务。
+pub(crate) fn test_server() -> MockServer {
+    let mch_public_key = test_credential().mch_rsa_private_key.to_public_key();
+    MockServer::new(API_V3_KEY).verify_requests(mch_public_key)
+}
+
+/// 使用测试商户证书、以模拟服务为传输的客户端。
+pub(crate) async fn test_client(server: &MockServer) -> WechatPayClient {
+    WechatPayClient::builder()
+        .mch_credential(test_credential())
+        .platform_certificate(server.platform_certificate())
+        .transport(server.clone())
+        .build()
+        .await
+        .unwrap()
+}
+
+pub(crate) fn jsapi_params(out_trade_no: &str) -> JsApiCreateTradeParams {
+    JsApiCreateTradeParams::new(
+        "wxd678efh567hg6787".to_string(),
+        MCH_ID.to_string(),
+        "Image形象店-深圳腾大-QQ公仔".to_string(),
+        out_trade_no.to_string(),
+        None,
+        Some("自定义数据".to_string()),
+        NOTIFY_URL.to_string(),
+        Amount {
+            total: 100,
+            currency: "CNY".to_string(),
+        },
+        "oUpF8uMuAJO_M2pxb1Q9zNjWeS6o".to_string(),
+    )
+}
+
+pub(crate) fn native_params(out_trade_no: &str) -> NativeCreateTradeParams {
+    NativeCreateTradeParams {
+        app_id: "wxd678efh567hg6787".to_string(),
+        mch_id: MCH_ID.to_string(),
+        description: "Image形象店-深圳腾大-QQ公仔".to_string(),
+        out_trade_no: out_trade_no.to_string(),
+        time_expire: None,
+        attach: None,
+        notify_url: NOTIFY_URL.to_string(),
+        goods_tag: None,
+        support_fapiao: None,
+        amount: Amount {
+            total: 100,
+            currency: "CNY".to_string(),
+        },
+        detail: None,
+        scene_info: None,
+        settle_info: None,
+    }
+}
+
+pub(crate) fn refund_params(out_trade_no: &str, out_refund_no: &str, refund: i32) -> RefundParams {
+    RefundParams {
+        trade_id: TradeId::OutTradeNo(out_trade_no.to_string()),
+        out_refund_no: out_refund_no.to_string(),
+        reason: None,
+        notify_url: Some(NOTIFY_URL.to_string()),
+        funds_account: None,
+        amount: RefundApplyingAmount {
+            total: 100,
+            refund,
+            currency: "CNY".to_string(),
+            from: Vec::new(),
+        },
+        goods_detail: Vec::new(),
+    }
+}
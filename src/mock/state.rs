@@ -0,0 +1,1764 @@
+//! 模拟服务的内存状态，以及各接口的处理逻辑。
+//!
+//! 请求与响应均以 `serde_json::Value` 处理，与接口的请求、响应结构体解耦，
+//! 字段名以微信支付文档为准。
+
+use crate::credential::generate_none_str;
+use crate::platform_certificate::encrypt;
+use crate::refund::RefundStatus;
+use crate::trade::{TradeState, TradeType};
+use crate::util::DATETIME_FORMAT;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use base64::prelude::*;
+use chrono::{Local, NaiveDate};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use http::{Method, StatusCode};
+use rsa::{Oaep, RsaPrivateKey, RsaPublicKey};
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Write;
+
+/// 处理成功时的响应。
+pub(super) enum Reply {
+    Json(Value),
+    /// 已受理但尚未完成，如付款码支付时用户支付中。
+    Accepted(Value),
+    NoContent,
+    /// 账单文件。与微信支付一致，响应没有签名。
+    File(Vec<u8>),
+}
+
+/// 处理失败时的响应，即微信支付的错误响应。
+#[derive(Debug, Clone)]
+pub(super) struct Failure {
+    pub(super) status: StatusCode,
+    pub(super) code: String,
+    pub(super) message: String,
+}
+
+impl Failure {
+    pub(super) fn new(status: StatusCode, code: &str, message: impl Into<String>) -> Failure {
+        Failure {
+            status,
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn param(message: impl Into<String>) -> Failure {
+        Failure::new(StatusCode::BAD_REQUEST, "PARAM_ERROR", message)
+    }
+
+    fn invalid(message: impl Into<String>) -> Failure {
+        Failure::new(StatusCode::BAD_REQUEST, "INVALID_REQUEST", message)
+    }
+
+    fn order_not_exist() -> Failure {
+        Failure::new(StatusCode::NOT_FOUND, "ORDER_NOT_EXIST", "订单不存在")
+    }
+
+    fn not_exists(message: impl Into<String>) -> Failure {
+        Failure::new(StatusCode::NOT_FOUND, "RESOURCE_NOT_EXISTS", message)
+    }
+
+    pub(super) fn body(&self) -> Value {
+        json!({ "code": self.code, "message": self.message })
+    }
+}
+
+type Handled = Result<Reply, Failure>;
+
+/// 订单。合单支付的子单也以订单保存。
+#[derive(Debug, Clone)]
+pub(super) struct Trade {
+    pub(super) app_id: String,
+    pub(super) mch_id: String,
+    pub(super) sub_mch_id: Option<String>,
+    pub(super) sub_app_id: Option<String>,
+    /// 服务商模式下单的订单
+    pub(super) partner: bool,
+    pub(super) out_trade_no: String,
+    pub(super) transaction_id: Option<String>,
+    pub(super) trade_type: TradeType,
+    pub(super) trade_state: TradeState,
+    pub(super) prepay_id: String,
+    pub(super) description: String,
+    pub(super) attach: Option<String>,
+    pub(super) notify_url: String,
+    pub(super) total: i64,
+    pub(super) currency: String,
+    pub(super) openid: Option<String>,
+    /// 服务商模式下，用户在子商户 sub_appid 下的 openid
+    pub(super) sub_openid: Option<String>,
+    pub(super) success_time: Option<String>,
+    /// 已退款金额
+    refunded: i64,
+    /// 已分账金额
+    shared: i64,
+    /// 已完结分账
+    sharing_finished: bool,
+}
+
+impl Trade {
+    fn is_paid(&self) -> bool {
+        matches!(self.trade_state, TradeState::Success | TradeState::Refund)
+    }
+
+    fn unsplit_amount(&self) -> i64 {
+        if self.sharing_finished || !self.is_paid() {
+            0
+        } else {
+            self.total - self.refunded - self.shared
+        }
+    }
+
+    /// 订单查询接口及支付通知中的订单数据。服务商模式的订单使用 sp_/sub_ 字段。
+    pub(super) fn to_json(&self) -> Value {
+        let mut v = json!({
+            "out_trade_no": self.out_trade_no,
+            "trade_type": self.trade_type,
+            "trade_state": self.trade_state,
+            "trade_state_desc": trade_state_desc(self.trade_state),
+        });
+        let m = v.as_object_mut().unwrap();
+        if self.partner {
+            m.insert("sp_appid".into(), json!(self.app_id));
+            m.insert("sp_mchid".into(), json!(self.mch_id));
+            insert_some(m, "sub_appid", &self.sub_app_id);
+            insert_some(m, "sub_mchid", &self.sub_mch_id);
+            let mut payer = Map::new();
+            insert_some(&mut payer, "sp_openid", &self.openid);
+            insert_some(&mut payer, "sub_openid", &self.sub_openid);
+            if !payer.is_empty() {
+                m.insert("payer".into(), Value::Object(payer));
+            }
+        } else {
+            m.insert("appid".into(), json!(self.app_id));
+            m.insert("mchid".into(), json!(self.mch_id));
+            if let Some(openid) = &self.openid {
+                m.insert("payer".into(), json!({ "openid": openid }));
+            }
+        }
+        insert_some(m, "transaction_id", &self.transaction_id);
+        insert_some(m, "attach", &self.attach);
+        if self.is_paid() {
+            m.insert("bank_type".into(), json!("OTHERS"));
+            insert_some(m, "success_time", &self.success_time);
+            m.insert(
+                "amount".into(),
+                json!({
+                    "total": self.total,
+                    "currency": self.currency,
+                    "payer_total": self.total,
+                    "payer_currency": self.currency,
+                }),
+            );
+        }
+        v
+    }
+
+    /// 合单查询接口及合单支付通知中的子单数据。
+    pub(super) fn to_sub_order_json(&self) -> Value {
+        let mut v = json!({
+            "mchid": self.mch_id,
+            "trade_type": self.trade_type,
+            "trade_state": self.trade_state,
+            "attach": self.attach.clone().unwrap_or_default(),
+            "out_trade_no": self.out_trade_no,
+            "amount": {
+                "total_amount": self.total,
+                "payer_amount": if self.is_paid() { self.total } else { 0 },
+                "currency": self.currency,
+                "payer_currency": self.currency,
+            },
+        });
+        let m = v.as_object_mut().unwrap();
+        insert_some(m, "sub_mchid", &self.sub_mch_id);
+        insert_some(m, "transaction_id", &self.transaction_id);
+        insert_some(m, "success_time", &self.success_time);
+        if self.is_paid() {
+            m.insert("bank_type".into(), json!("OTHERS"));
+        }
+        v
+    }
+}
+
+fn trade_state_desc(state: TradeState) -> &'static str {
+    match state {
+        TradeState::Success => "支付成功",
+        TradeState::Refund => "转入退款",
+        TradeState::NotPay => "未支付",
+        TradeState::Closed => "已关闭",
+        TradeState::Revoked => "已撤销",
+        TradeState::UserPaying => "用户支付中",
+        TradeState::PayError => "支付失败",
+    }
+}
+
+/// 合单支付订单。
+#[derive(Debug, Clone)]
+pub(super) struct CombineTrade {
+    pub(super) combine_app_id: String,
+    pub(super) combine_mch_id: String,
+    pub(super) combine_out_trade_no: String,
+    pub(super) prepay_id: String,
+    pub(super) notify_url: String,
+    pub(super) openid: Option<String>,
+    /// 子单的商户订单号
+    pub(super) sub_orders: Vec<String>,
+}
+
+/// 退款单。
+#[derive(Debug, Clone)]
+pub(super) struct Refund {
+    pub(super) refund_id: String,
+    pub(super) out_refund_no: String,
+    pub(super) transaction_id: String,
+    pub(super) out_trade_no: String,
+    /// 发起退款的商户号。收付通退款时为平台商户号。
+    pub(super) sp_mch_id: String,
+    pub(super) mch_id: String,
+    pub(super) sub_mch_id: Option<String>,
+    pub(super) status: RefundStatus,
+    pub(super) total: i64,
+    pub(super) refund: i64,
+    pub(super) currency: String,
+    pub(super) create_time: String,
+    pub(super) success_time: Option<String>,
+    pub(super) notify_url: Option<String>,
+}
+
+impl Refund {
+    /// 退款查询接口中的退款单数据。
+    fn to_json(&self) -> Value {
+        let mut v = json!({
+            "refund_id": self.refund_id,
+            "out_refund_no": self.out_refund_no,
+            "transaction_id": self.transaction_id,
+            "out_trade_no": self.out_trade_no,
+            "channel": "ORIGINAL",
+            "user_received_account": "支付用户零钱",
+            "create_time": self.create_time,
+            "status": self.status,
+            "amount": {
+                "total": self.total,
+                "refund": self.refund,
+                "payer_total": self.total,
+                "payer_refund": self.refund,
+                "settlement_total": self.total,
+                "settlement_refund": self.refund,
+                "discount_refund": 0,
+                "currency": self.currency,
+                "refund_fee": 0,
+            },
+        });
+        insert_some(
+            v.as_object_mut().unwrap(),
+            "success_time",
+            &self.success_time,
+        );
+        v
+    }
+
+    /// 收付通退款查询接口中的退款单数据。
+    fn to_ecommerce_json(&self) -> Value {
+        let mut v = json!({
+            "refund_id": self.refund_id,
+            "out_refund_no": self.out_refund_no,
+            "transaction_id": self.transaction_id,
+            "out_trade_no": self.out_trade_no,
+            "channel": "ORIGINAL",
+            "user_received_account": "支付用户零钱",
+            "create_time": self.create_time,
+            "status": self.status,
+            "amount": {
+                "refund": self.refund,
+                "payer_refund": self.refund,
+                "discount_refund": 0,
+                "currency": self.currency,
+            },
+        });
+        insert_some(
+            v.as_object_mut().unwrap(),
+            "success_time",
+            &self.success_time,
+        );
+        v
+    }
+
+    /// 退款通知中的退款单数据。
+    pub(super) fn to_notify_json(&self) -> Value {
+        let mut v = json!({
+            "mchid": self.mch_id,
+            "out_trade_no": self.out_trade_no,
+            "transaction_id": self.transaction_id,
+            "out_refund_no": self.out_refund_no,
+            "refund_id": self.refund_id,
+            "refund_status": self.status,
+            "user_received_account": "支付用户零钱",
+            "amount": {
+                "total": self.total,
+                "refund": self.refund,
+                "payer_total": self.total,
+                "payer_refund": self.refund,
+            },
+        });
+        let m = v.as_object_mut().unwrap();
+        insert_some(m, "success_time", &self.success_time);
+        if let Some(sub_mch_id) = &self.sub_mch_id {
+            m.insert("sp_mchid".into(), json!(self.sp_mch_id));
+            m.insert("sub_mchid".into(), json!(sub_mch_id));
+        }
+        v
+    }
+}
+
+/// 收付通分账单。
+#[derive(Debug, Clone)]
+pub(super) struct ProfitSharing {
+    pub(super) order_id: String,
+    pub(super) sp_mch_id: String,
+    pub(super) sub_mch_id: String,
+    pub(super) transaction_id: String,
+    pub(super) out_order_no: String,
+    pub(super) receivers: Vec<Value>,
+    pub(super) finish_amount: Option<i64>,
+    pub(super) finish_description: Option<String>,
+}
+
+impl ProfitSharing {
+    fn to_json(&self) -> Value {
+        let mut v = json!({
+            "sub_mchid": self.sub_mch_id,
+            "transaction_id": self.transaction_id,
+            "out_order_no": self.out_order_no,
+            "order_id": self.order_id,
+            "status": "FINISHED",
+            "receivers": self.receivers,
+        });
+        let m = v.as_object_mut().unwrap();
+        insert_some(m, "finish_amount", &self.finish_amount);
+        insert_some(m, "finish_description", &self.finish_description);
+        v
+    }
+}
+
+/// 提现单。`sub_mch_id` 为 None 时为平台提现。
+#[derive(Debug, Clone)]
+struct Withdraw {
+    withdraw_id: String,
+    out_request_no: String,
+    sp_mch_id: String,
+    sub_mch_id: Option<String>,
+    amount: i64,
+    account_type: String,
+    remark: String,
+    bank_memo: String,
+    create_time: String,
+}
+
+impl Withdraw {
+    fn to_json(&self) -> Value {
+        let mut v = json!({
+            "status": "SUCCESS",
+            "withdraw_id": self.withdraw_id,
+            "out_request_no": self.out_request_no,
+            "amount": self.amount,
+            "create_time": self.create_time,
+            "update_time": self.create_time,
+            "reason": "",
+            "remark": self.remark,
+            "bank_memo": self.bank_memo,
+            "account_type": self.account_type,
+            "solution": "",
+            "account_number": "1234",
+            "account_bank": "工商银行",
+        });
+        let m = v.as_object_mut().unwrap();
+        if let Some(sub_mch_id) = &self.sub_mch_id {
+            m.insert("sp_mchid".into(), json!(self.sp_mch_id));
+            m.insert("sub_mchid".into(), json!(sub_mch_id));
+        }
+        v
+    }
+}
+
+/// 进件申请单。
+#[derive(Debug, Clone)]
+struct Applyment {
+    applyment_id: u64,
+    out_request_no: String,
+    state: String,
+    sub_mch_id: Option<String>,
+}
+
+impl Applyment {
+    fn to_json(&self) -> Value {
+        let mut v = json!({
+            "applyment_state": self.state,
+            "applyment_state_desc": applyment_state_desc(&self.state),
+            "out_request_no": self.out_request_no,
+            "applyment_id": self.applyment_id,
+        });
+        let m = v.as_object_mut().unwrap();
+        insert_some(m, "sub_mchid", &self.sub_mch_id);
+        if self.state == "NEED_SIGN" {
+            m.insert(
+                "sign_url".into(),
+                json!(format!(
+                    "https://pay.weixin.qq.com/public/apply4ec_sign/s?applymentId={}",
+                    self.applyment_id
+                )),
+            );
+            m.insert("sign_state".into(), json!("UNSIGNED"));
+        }
+        v
+    }
+}
+
+fn applyment_state_desc(state: &str) -> &'static str {
+    match state {
+        "CHECKING" => "资料校验中",
+        "ACCOUNT_NEED_VERIFY" => "待账户验证",
+        "AUDITING" => "审核中",
+        "REJECTED" => "已驳回",
+        "NEED_SIGN" => "待签约",
+        "FINISH" => "完成",
+        "FROZEN" => "已冻结",
+        "CANCELED" => "已作废",
+        _ => "",
+    }
+}
+
+/// 结算账户修改申请。
+#[derive(Debug, Clone)]
+struct SettlementApplication {
+    sub_mch_id: String,
+    account_type: String,
+    account_bank: String,
+    bank_name: Option<String>,
+    bank_branch_id: Option<String>,
+    account_name: String,
+    account_number: String,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct MockState {
+    seq: u64,
+    pub(super) trades: BTreeMap<String, Trade>,
+    pub(super) combine_trades: BTreeMap<String, CombineTrade>,
+    pub(super) refunds: BTreeMap<String, Refund>,
+    pub(super) profit_sharings: BTreeMap<String, ProfitSharing>,
+    withdraws: BTreeMap<String, Withdraw>,
+    applyments: BTreeMap<String, Applyment>,
+    settlement_applications: BTreeMap<String, SettlementApplication>,
+    sub_mch_balances: HashMap<String, i64>,
+    platform_balances: HashMap<String, i64>,
+    /// 账单文件，以下载地址中的 token 为 key。
+    bill_files: HashMap<String, Vec<u8>>,
+    pub(super) failures: VecDeque<Failure>,
+    /// 商户公钥，用于加密应答中的敏感信息字段。
+    pub(super) mch_public_key: Option<RsaPublicKey>,
+    /// 平台私钥，用于解密请求中的敏感信息字段。
+    platform_private_key: Option<RsaPrivateKey>,
+}
+
+impl MockState {
+    pub(super) fn new(platform_private_key: RsaPrivateKey) -> MockState {
+        MockState {
+            platform_private_key: Some(platform_private_key),
+            ..Default::default()
+        }
+    }
+
+    /// 生成单号，形如 `{prefix}20240101120000000001`。
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.seq += 1;
+        format!(
+            "{}{}{:06}",
+            prefix,
+            Local::now().format("%Y%m%d%H%M%S"),
+            self.seq
+        )
+    }
+
+    /// 处理请求。`mch_id` 为请求签名中的商户号，`origin` 为生成账单下载地址所用的根地址。
+    pub(super) fn handle(
+        &mut self,
+        mch_id: &str,
+        origin: &str,
+        method: &Method,
+        path: &str,
+        query: &HashMap<String, String>,
+        body: &[u8],
+    ) -> Handled {
+        let path = path.trim_start_matches("/v3/").trim_end_matches('/');
+        let segments: Vec<&str> = path.split('/').collect();
+        let query_param = |name: &str| query.get(name).cloned().unwrap_or_default();
+        match (method, segments.as_slice()) {
+            (&Method::POST, ["pay", "transactions", "codepay"]) => {
+                self.create_codepay_trade(&parse_json(body)?)
+            }
+            (&Method::POST, ["pay", "transactions", kind]) => {
+                let trade_type = match *kind {
+                    "jsapi" => TradeType::JsApi,
+                    "app" => TradeType::App,
+                    "h5" => TradeType::Mweb,
+                    "native" => TradeType::Native,
+                    _ => return Err(Failure::not_exists("接口不存在")),
+                };
+                self.create_trade(trade_type, false, &parse_json(body)?)
+            }
+            (&Method::GET, ["pay", "transactions", "id", transaction_id]) => {
+                let trade = self.trade_by_transaction_id(transaction_id)?;
+                Ok(Reply::Json(trade.to_json()))
+            }
+            (&Method::GET, ["pay", "transactions", "out-trade-no", out_trade_no]) => {
+                let trade = self.trades.get(*out_trade_no);
+                let trade = trade.ok_or_else(Failure::order_not_exist)?;
+                Ok(Reply::Json(trade.to_json()))
+            }
+            (&Method::POST, ["pay", "transactions", "out-trade-no", out_trade_no, "close"]) => {
+                self.close_trades(&[out_trade_no.to_string()])
+            }
+            (&Method::POST, ["pay", "transactions", "out-trade-no", out_trade_no, "reverse"]) => {
+                let trade = self.trades.get_mut(*out_trade_no);
+                let trade = trade.ok_or_else(Failure::order_not_exist)?;
+                if trade.trade_type != TradeType::Micropay {
+                    return Err(Failure::invalid("仅付款码支付的订单可以撤销"));
+                }
+                if trade.refunded > 0 {
+                    return Err(Failure::invalid("订单已发生退款，不能撤销"));
+                }
+                trade.trade_state = TradeState::Revoked;
+                Ok(Reply::NoContent)
+            }
+            (&Method::POST, ["pay", "partner", "transactions", kind]) => {
+                let trade_type = match *kind {
+                    "jsapi" => TradeType::JsApi,
+                    "app" => TradeType::App,
+                    "h5" => TradeType::Mweb,
+                    "native" => TradeType::Native,
+                    _ => return Err(Failure::not_exists("接口不存在")),
+                };
+                self.create_trade(trade_type, true, &parse_json(body)?)
+            }
+            (&Method::GET, ["pay", "partner", "transactions", "id", transaction_id]) => {
+                let trade = self.trade_by_transaction_id(transaction_id)?;
+                let trade = check_sub_mch(trade, &query_param("sub_mchid"))?;
+                Ok(Reply::Json(trade.to_json()))
+            }
+            (&Method::GET, ["pay", "partner", "transactions", "out-trade-no", out_trade_no]) => {
+                let trade = self.trades.get(*out_trade_no);
+                let trade = trade.ok_or_else(Failure::order_not_exist)?;
+                let trade = check_sub_mch(trade, &query_param("sub_mchid"))?;
+                Ok(Reply::Json(trade.to_json()))
+            }
+            (
+                &Method::POST,
+                ["pay", "partner", "transactions", "out-trade-no", out_trade_no, "close"],
+            ) => {
+                let trade = self.trades.get(*out_trade_no);
+                let trade = trade.ok_or_else(Failure::order_not_exist)?;
+                check_sub_mch(trade, &str_field(&parse_json(body)?, "sub_mchid")?)?;
+                self.close_trades(&[out_trade_no.to_string()])
+            }
+            (&Method::POST, ["refund", "domestic", "refunds"]) => {
+                let req = parse_json(body)?;
+                let sub_mch_id = req["sub_mchid"].as_str().map(ToString::to_string);
+                let refund = self.create_refund(mch_id, sub_mch_id, &req)?;
+                Ok(Reply::Json(refund.to_json()))
+            }
+            (&Method::GET, ["refund", "domestic", "refunds", out_refund_no]) => {
+                let refund = self.refunds.get(*out_refund_no);
+                let refund = refund.ok_or_else(|| Failure::not_exists("退款单不存在"))?;
+                Ok(Reply::Json(refund.to_json()))
+            }
+            (&Method::POST, ["combine-transactions", "jsapi"]) => {
+                self.create_combine_trade(&parse_json(body)?)
+            }
+            (&Method::GET, ["combine-transactions", "out-trade-no", combine_out_trade_no]) => {
+                self.query_combine_trade(combine_out_trade_no)
+            }
+            (
+                &Method::POST,
+                ["combine-transactions", "out-trade-no", combine_out_trade_no, "close"],
+            ) => {
+                let combine = self.combine_trades.get(*combine_out_trade_no);
+                let combine = combine.ok_or_else(Failure::order_not_exist)?;
+                let sub_orders = combine.sub_orders.clone();
+                self.close_trades(&sub_orders)
+            }
+            (&Method::POST, ["ecommerce", "profitsharing", "orders"]) => {
+                self.create_profit_sharing(mch_id, &parse_json(body)?)
+            }
+            (&Method::GET, ["ecommerce", "profitsharing", "orders"]) => {
+                let sharing = self
+                    .profit_sharings
+                    .get(&query_param("out_order_no"))
+                    .filter(|s| s.transaction_id == query_param("transaction_id"));
+                let sharing = sharing.ok_or_else(|| Failure::not_exists("分账单不存在"))?;
+                Ok(Reply::Json(sharing.to_json()))
+            }
+            (&Method::POST, ["ecommerce", "profitsharing", "finish-order"]) => {
+                self.finish_profit_sharing(mch_id, &parse_json(body)?)
+            }
+            (&Method::GET, ["ecommerce", "profitsharing", "orders", transaction_id, "amounts"]) => {
+                let trade = self.trade_by_transaction_id(transaction_id)?;
+                Ok(Reply::Json(json!({
+                    "transaction_id": transaction_id,
+                    "unsplit_amount": trade.unsplit_amount(),
+                })))
+            }
+            (&Method::POST, ["ecommerce", "refunds", "apply"]) => {
+                let req = parse_json(body)?;
+                let sub_mch_id = str_field(&req, "sub_mchid")?;
+                let refund = self.create_refund(mch_id, Some(sub_mch_id), &req)?;
+                Ok(Reply::Json(json!({
+                    "refund_id": refund.refund_id,
+                    "out_refund_no": refund.out_refund_no,
+                    "create_time": refund.create_time,
+                    "amount": {
+                        "refund": refund.refund,
+                        "payer_refund": refund.refund,
+                        "discount_refund": 0,
+                        "currency": refund.currency,
+                    },
+                })))
+            }
+            (&Method::GET, ["ecommerce", "refunds", "id", refund_id]) => {
+                let refund = self.refunds.values().find(|r| r.refund_id == *refund_id);
+                let refund = refund.ok_or_else(|| Failure::not_exists("退款单不存在"))?;
+                Ok(Reply::Json(refund.to_ecommerce_json()))
+            }
+            (&Method::GET, ["ecommerce", "refunds", "out-refund-no", out_refund_no]) => {
+                let refund = self.refunds.get(*out_refund_no);
+                let refund = refund.ok_or_else(|| Failure::not_exists("退款单不存在"))?;
+                Ok(Reply::Json(refund.to_ecommerce_json()))
+            }
+            (&Method::GET, ["ecommerce", "fund", "balance" | "enddaybalance", sub_mch_id]) => {
+                let available = self.sub_mch_balances.get(*sub_mch_id).copied();
+                let account_type = query.get("account_type").map_or("BASIC", |s| s.as_str());
+                Ok(Reply::Json(json!({
+                    "sub_mchid": sub_mch_id,
+                    "available_amount": available.unwrap_or_default(),
+                    "pending_amount": 0,
+                    "account_type": account_type,
+                })))
+            }
+            (&Method::GET, ["merchant", "fund", "balance" | "dayendbalance", account_type]) => {
+                let available = self.platform_balances.get(*account_type).copied();
+                Ok(Reply::Json(json!({
+                    "available_amount": available.unwrap_or_default(),
+                    "pending_amount": 0,
+                })))
+            }
+            (&Method::POST, ["ecommerce", "fund", "withdraw"]) => {
+                let req = parse_json(body)?;
+                let sub_mch_id = str_field(&req, "sub_mchid")?;
+                let withdraw = self.create_withdraw(mch_id, Some(sub_mch_id), &req)?;
+                Ok(Reply::Json(json!({
+                    "sub_mchid": withdraw.sub_mch_id,
+                    "withdraw_id": withdraw.withdraw_id,
+                    "out_request_no": withdraw.out_request_no,
+                })))
+            }
+            (&Method::GET, ["ecommerce", "fund", "withdraw", "out-request-no", out_request_no]) => {
+                let sub_mch_id = query_param("sub_mchid");
+                let withdraw = self
+                    .withdraws
+                    .get(*out_request_no)
+                    .filter(|w| w.sub_mch_id.as_deref() == Some(&sub_mch_id));
+                self.withdraw_reply(withdraw)
+            }
+            (&Method::GET, ["ecommerce", "fund", "withdraw", withdraw_id]) => {
+                let sub_mch_id = query_param("sub_mchid");
+                let withdraw = self.withdraws.values().find(|w| {
+                    w.withdraw_id == *withdraw_id && w.sub_mch_id.as_deref() == Some(&sub_mch_id)
+                });
+                self.withdraw_reply(withdraw)
+            }
+            (&Method::POST, ["merchant", "fund", "withdraw"]) => {
+                let withdraw = self.create_withdraw(mch_id, None, &parse_json(body)?)?;
+                Ok(Reply::Json(json!({
+                    "withdraw_id": withdraw.withdraw_id,
+                    "out_request_no": withdraw.out_request_no,
+                })))
+            }
+            (&Method::GET, ["merchant", "fund", "withdraw", "out-request-no", out_request_no]) => {
+                let withdraw = self
+                    .withdraws
+                    .get(*out_request_no)
+                    .filter(|w| w.sub_mch_id.is_none());
+                self.withdraw_reply(withdraw)
+            }
+            (&Method::GET, ["merchant", "fund", "withdraw", "withdraw-id", withdraw_id]) => {
+                let withdraw = self
+                    .withdraws
+                    .values()
+                    .find(|w| w.withdraw_id == *withdraw_id && w.sub_mch_id.is_none());
+                self.withdraw_reply(withdraw)
+            }
+            (&Method::POST, ["ecommerce", "applyments"]) => {
+                let req = parse_json(body)?;
+                let out_request_no = str_field(&req, "out_request_no")?;
+                let applyment_id = self.seq + 2_000_000_000;
+                let applyment = self
+                    .applyments
+                    .entry(out_request_no.clone())
+                    .or_insert_with(|| Applyment {
+                        applyment_id,
+                        out_request_no,
+                        state: "CHECKING".to_string(),
+                        sub_mch_id: None,
+                    });
+                let reply = json!({
+                    "applyment_id": applyment.applyment_id,
+                    "out_request_no": applyment.out_request_no,
+                });
+                self.seq += 1;
+                Ok(Reply::Json(reply))
+            }
+            (&Method::GET, ["ecommerce", "applyments", "out-request-no", out_request_no]) => {
+                let applyment = self.applyments.get(*out_request_no);
+                let applyment = applyment.ok_or_else(|| Failure::not_exists("申请单不存在"))?;
+                Ok(Reply::Json(applyment.to_json()))
+            }
+            (&Method::GET, ["ecommerce", "applyments", applyment_id]) => {
+                let applyment = self
+                    .applyments
+                    .values()
+                    .find(|a| a.applyment_id.to_string() == *applyment_id);
+                let applyment = applyment.ok_or_else(|| Failure::not_exists("申请单不存在"))?;
+                Ok(Reply::Json(applyment.to_json()))
+            }
+            (&Method::GET, ["apply4sub", "sub_merchants", _sub_mch_id, "settlement"]) => {
+                Ok(Reply::Json(json!({
+                    "account_type": "ACCOUNT_TYPE_BUSINESS",
+                    "account_bank": "工商银行",
+                    "bank_name": "中国工商银行股份有限公司深圳南山支行",
+                    "account_number": "62*************78",
+                    "verify_result": "VERIFY_SUCCESS",
+                })))
+            }
+            (&Method::POST, ["apply4sub", "sub_merchants", sub_mch_id, "modify-settlement"]) => {
+                let req = parse_json(body)?;
+                let application = SettlementApplication {
+                    sub_mch_id: sub_mch_id.to_string(),
+                    account_type: str_field(&req, "account_type")?,
+                    account_bank: str_field(&req, "account_bank")?,
+                    bank_name: req["bank_name"].as_str().map(ToString::to_string),
+                    bank_branch_id: req["bank_branch_id"].as_str().map(ToString::to_string),
+                    account_name: match req["account_name"].as_str() {
+                        Some(account_name) => self.decrypt(account_name)?,
+                        None => "*公司".to_string(),
+                    },
+                    account_number: self.decrypt(&str_field(&req, "account_number")?)?,
+                };
+                let application_no = self.next_id("");
+                self.settlement_applications
+                    .insert(application_no.clone(), application);
+                Ok(Reply::Json(json!({ "application_no": application_no })))
+            }
+            (
+                &Method::GET,
+                ["apply4sub", "sub_merchants", sub_mch_id, "application", application_no],
+            ) => {
+                let application = self
+                    .settlement_applications
+                    .get(*application_no)
+                    .filter(|a| a.sub_mch_id == *sub_mch_id);
+                let application = application.ok_or_else(|| Failure::not_exists("申请单不存在"))?;
+                let mut v = json!({
+                    "account_name": self.sensitive(&application.account_name)?,
+                    "account_type": application.account_type,
+                    "account_bank": application.account_bank,
+                    "account_number": self.sensitive(&application.account_number)?,
+                    "verify_result": "AUDIT_SUCCESS",
+                    "verify_finish_time": Local::now().format(DATETIME_FORMAT).to_string(),
+                });
+                let m = v.as_object_mut().unwrap();
+                insert_some(m, "bank_name", &application.bank_name);
+                insert_some(m, "bank_branch_id", &application.bank_branch_id);
+                Ok(Reply::Json(v))
+            }
+            (&Method::GET, ["bill", "tradebill"]) => {
+                let sub_mch_id = query.get("sub_mchid").map(String::as_str);
+                let bill_date = bill_date(query)?;
+                let bill_type = query.get("bill_type").map_or("ALL", String::as_str);
+                let content = self.trade_bill(mch_id, sub_mch_id, &bill_date, bill_type)?;
+                let info = self.save_bill(origin, &content, query.get("tar_type"))?;
+                Ok(Reply::Json(info))
+            }
+            (&Method::GET, ["bill", "fundflowbill"]) => {
+                let bill_date = bill_date(query)?;
+                let account_type = query.get("account_type").map_or("BASIC", String::as_str);
+                let content = self.fund_flow_bill(mch_id, None, &bill_date, account_type)?;
+                let info = self.save_bill(origin, &content, query.get("tar_type"))?;
+                Ok(Reply::Json(info))
+            }
+            (&Method::GET, ["ecommerce", "bill", "fundflowbill"]) => {
+                let sub_mch_id = query_param("sub_mchid");
+                if sub_mch_id.is_empty() {
+                    return Err(Failure::param("缺少参数 sub_mchid"));
+                }
+                if query_param("algorithm") != "AEAD_AES_256_GCM" {
+                    return Err(Failure::param("algorithm 无效"));
+                }
+                let bill_date = bill_date(query)?;
+                let account_type = query.get("account_type").map_or("BASIC", String::as_str);
+                let content =
+                    self.fund_flow_bill(mch_id, Some(&sub_mch_id), &bill_date, account_type)?;
+                let info = self.save_encrypted_bill(origin, &content, query.get("tar_type"))?;
+                Ok(Reply::Json(json!({
+                    "download_bill_count": 1,
+                    "download_bill_list": [info],
+                })))
+            }
+            (&Method::GET, ["billdownload", "file"]) => {
+                let file = self.bill_files.get(&query_param("token"));
+                let file = file.ok_or_else(|| Failure::not_exists("账单文件不存在"))?;
+                Ok(Reply::File(file.clone()))
+            }
+            (&Method::POST, ["merchant", "media", "upload"]) => {
+                let media_id = self.next_id("mock-media-");
+                Ok(Reply::Json(json!({ "media_id": media_id })))
+            }
+            _ => Err(Failure::not_exists(format!(
+                "接口不存在: {} /v3/{}",
+                method, path
+            ))),
+        }
+    }
+
+    /// 使用商户公钥加密敏感信息字段。
+    fn sensitive(&self, plaintext: &str) -> Result<String, Failure> {
+        match &self.mch_public_key {
+            Some(public_key) => encrypt(public_key, plaintext.as_bytes()).map_err(|e| {
+                Failure::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "SYSTEM_ERROR",
+                    e.to_string(),
+                )
+            }),
+            None => Ok(plaintext.to_string()),
+        }
+    }
+
+    /// 使用平台私钥解密敏感信息字段。
+    fn decrypt(&self, ciphertext: &str) -> Result<String, Failure> {
+        let private_key = self.platform_private_key.as_ref();
+        BASE64_STANDARD
+            .decode(ciphertext)
+            .ok()
+            .zip(private_key)
+            .and_then(|(c, key)| key.decrypt(Oaep::new::<Sha1>(), &c).ok())
+            .and_then(|p| String::from_utf8(p).ok())
+            .ok_or_else(|| Failure::param("敏感信息解密失败，请使用平台证书加密"))
+    }
+
+    fn trade_by_transaction_id(&self, transaction_id: &str) -> Result<&Trade, Failure> {
+        self.trades
+            .values()
+            .find(|t| t.transaction_id.as_deref() == Some(transaction_id))
+            .ok_or_else(Failure::order_not_exist)
+    }
+
+    /// 下单。`partner` 为服务商模式下单。
+    fn create_trade(&mut self, trade_type: TradeType, partner: bool, req: &Value) -> Handled {
+        let out_trade_no = str_field(req, "out_trade_no")?;
+        let total = int_field(req, "/amount/total")?;
+        if total <= 0 {
+            return Err(Failure::param("total 必须大于 0"));
+        }
+        let (openid, sub_openid) = if partner {
+            let payer = |name: &str| req["payer"][name].as_str().map(ToString::to_string);
+            (payer("sp_openid"), payer("sub_openid"))
+        } else {
+            let openid = req.pointer("/payer/openid").and_then(Value::as_str);
+            (openid.map(ToString::to_string), None)
+        };
+        if trade_type == TradeType::JsApi && openid.is_none() && sub_openid.is_none() {
+            return Err(Failure::param("缺少参数 payer"));
+        }
+        let sub_app_id = req["sub_appid"].as_str().map(ToString::to_string);
+        if sub_openid.is_some() && sub_app_id.is_none() {
+            return Err(Failure::param("传入 sub_openid 时 sub_appid 必填"));
+        }
+        let description = str_field(req, "description")?;
+
+        let prepay_id = match self.trades.get(&out_trade_no) {
+            Some(trade)
+                if trade.trade_type != trade_type
+                    || trade.total != total
+                    || trade.partner != partner =>
+            {
+                return Err(Failure::new(
+                    StatusCode::BAD_REQUEST,
+                    "OUT_TRADE_NO_USED",
+                    "商户订单号重复",
+                ))
+            }
+            Some(trade) => {
+                check_not_paid(trade)?;
+                trade.prepay_id.clone()
+            }
+            None => {
+                let prepay_id = self.next_id("wx");
+                let (app_id, mch_id, sub_mch_id) = if partner {
+                    let sub_mch_id = str_field(req, "sub_mchid")?;
+                    (
+                        str_field(req, "sp_appid")?,
+                        str_field(req, "sp_mchid")?,
+                        Some(sub_mch_id),
+                    )
+                } else {
+                    (str_field(req, "appid")?, str_field(req, "mchid")?, None)
+                };
+                let trade = Trade {
+                    app_id,
+                    mch_id,
+                    sub_mch_id,
+                    sub_app_id,
+                    partner,
+                    out_trade_no: out_trade_no.clone(),
+                    transaction_id: None,
+                    trade_type,
+                    trade_state: TradeState::NotPay,
+                    prepay_id: prepay_id.clone(),
+                    description,
+                    attach: req["attach"].as_str().map(ToString::to_string),
+                    notify_url: str_field(req, "notify_url")?,
+                    total,
+                    currency: currency(req.pointer("/amount/currency")),
+                    openid,
+                    sub_openid,
+                    success_time: None,
+                    refunded: 0,
+                    shared: 0,
+                    sharing_finished: false,
+                };
+                self.trades.insert(out_trade_no, trade);
+                prepay_id
+            }
+        };
+
+        let reply = match trade_type {
+            TradeType::Mweb => json!({
+                "h5_url": format!(
+                    "https://wx.tenpay.com/cgi-bin/mmpayweb-bin/checkmweb?prepay_id={}",
+                    prepay_id
+                ),
+            }),
+            TradeType::Native => json!({
+                "code_url": format!("weixin://wxpay/bizpayurl?pr={}", prepay_id),
+            }),
+            _ => json!({ "prepay_id": prepay_id }),
+        };
+        Ok(Reply::Json(reply))
+    }
+
+    /// 付款码支付。订单创建后为用户支付中，并返回 `USERPAYING`，
+    /// 通过 [`MockState::pay`] 等模拟用户输入密码后的结果。
+    fn create_codepay_trade(&mut self, req: &Value) -> Handled {
+        let out_trade_no = str_field(req, "out_trade_no")?;
+        let total = int_field(req, "/amount/total")?;
+        if total <= 0 {
+            return Err(Failure::param("total 必须大于 0"));
+        }
+        let auth_code = req.pointer("/payer/auth_code").and_then(Value::as_str);
+        if auth_code.is_none_or(|c| c.len() != 18 || !c.starts_with('1')) {
+            return Err(Failure::param("付款码无效，请重新扫码"));
+        }
+        let description = str_field(req, "description")?;
+
+        let trade = match self.trades.get(&out_trade_no) {
+            Some(trade) if trade.trade_type != TradeType::Micropay || trade.total != total => {
+                return Err(Failure::new(
+                    StatusCode::BAD_REQUEST,
+                    "OUT_TRADE_NO_USED",
+                    "商户订单号重复",
+                ))
+            }
+            Some(trade) => trade,
+            None => {
+                let trade = Trade {
+                    app_id: str_field(req, "appid")?,
+                    mch_id: str_field(req, "mchid")?,
+                    sub_mch_id: None,
+                    sub_app_id: None,
+                    partner: false,
+                    out_trade_no: out_trade_no.clone(),
+                    transaction_id: None,
+                    trade_type: TradeType::Micropay,
+                    trade_state: TradeState::UserPaying,
+                    prepay_id: String::new(),
+                    description,
+                    attach: req["attach"].as_str().map(ToString::to_string),
+                    notify_url: String::new(),
+                    total,
+                    currency: currency(req.pointer("/amount/currency")),
+                    openid: None,
+                    sub_openid: None,
+                    success_time: None,
+                    refunded: 0,
+                    shared: 0,
+                    sharing_finished: false,
+                };
+                self.trades.entry(out_trade_no).or_insert(trade)
+            }
+        };
+        match trade.trade_state {
+            // 与微信支付一致，以 202 Accepted 返回
+            TradeState::UserPaying => Ok(Reply::Accepted(json!({
+                "code": "USERPAYING",
+                "message": "用户支付中，需要输入密码",
+            }))),
+            TradeState::Success => Ok(Reply::Json(trade.to_json())),
+            _ => Err(Failure::invalid(trade_state_desc(trade.trade_state))),
+        }
+    }
+
+    /// 关闭订单。任一订单已支付时不关闭任何订单。
+    fn close_trades(&mut self, out_trade_nos: &[String]) -> Handled {
+        for out_trade_no in out_trade_nos {
+            let trade = self.trades.get(out_trade_no);
+            check_not_paid(trade.ok_or_else(Failure::order_not_exist)?)?;
+        }
+        for out_trade_no in out_trade_nos {
+            if let Some(trade) = self.trades.get_mut(out_trade_no) {
+                trade.trade_state = TradeState::Closed;
+            }
+        }
+        Ok(Reply::NoContent)
+    }
+
+    /// 将订单置为支付成功。
+    pub(super) fn pay(&mut self, out_trade_no: &str) -> Option<&Trade> {
+        let transaction_id = self.next_id("42000");
+        let trade = self.trades.get_mut(out_trade_no)?;
+        if trade.trade_state == TradeState::NotPay || trade.trade_state == TradeState::UserPaying {
+            trade.trade_state = TradeState::Success;
+            trade.transaction_id = Some(transaction_id);
+            trade.success_time = Some(Local::now().format(DATETIME_FORMAT).to_string());
+        }
+        Some(trade)
+    }
+
+    fn create_refund(
+        &mut self,
+        mch_id: &str,
+        sub_mch_id: Option<String>,
+        req: &Value,
+    ) -> Result<Refund, Failure> {
+        let out_refund_no = str_field(req, "out_refund_no")?;
+        if let Some(refund) = self.refunds.get(&out_refund_no) {
+            return Ok(refund.clone());
+        }
+
+        let refund = int_field(req, "/amount/refund")?;
+        let total = int_field(req, "/amount/total")?;
+        let trade = if let Some(transaction_id) = req["transaction_id"].as_str() {
+            self.trade_by_transaction_id(transaction_id)?
+        } else {
+            let out_trade_no = str_field(req, "out_trade_no")?;
+            self.trades
+                .get(&out_trade_no)
+                .ok_or_else(Failure::order_not_exist)?
+        };
+        if !trade.is_paid() {
+            return Err(Failure::invalid("订单未支付"));
+        }
+        if total != trade.total {
+            return Err(Failure::param("订单金额与原订单不一致"));
+        }
+        if refund <= 0 || refund > trade.total - trade.refunded {
+            return Err(Failure::invalid("退款金额超过订单可退金额"));
+        }
+
+        let transaction_id = trade.transaction_id.clone().unwrap_or_default();
+        let out_trade_no = trade.out_trade_no.clone();
+        let refund = Refund {
+            refund_id: self.next_id("50300"),
+            out_refund_no: out_refund_no.clone(),
+            transaction_id,
+            out_trade_no,
+            sp_mch_id: mch_id.to_string(),
+            mch_id: sub_mch_id
+                .as_ref()
+                .map_or_else(|| mch_id.to_string(), Clone::clone),
+            sub_mch_id,
+            status: RefundStatus::Processing,
+            total,
+            refund,
+            currency: currency(req.pointer("/amount/currency")),
+            create_time: Local::now().format(DATETIME_FORMAT).to_string(),
+            success_time: None,
+            notify_url: req["notify_url"].as_str().map(ToString::to_string),
+        };
+        let trade = self.trades.get_mut(&refund.out_trade_no).unwrap();
+        trade.refunded += refund.refund;
+        trade.trade_state = TradeState::Refund;
+        self.refunds.insert(out_refund_no, refund.clone());
+        Ok(refund)
+    }
+
+    /// 将退款单置为给定状态。退款关闭或异常时，退回订单的已退款金额。
+    pub(super) fn complete_refund(
+        &mut self,
+        out_refund_no: &str,
+        status: RefundStatus,
+    ) -> Option<&Refund> {
+        let refund = self.refunds.get_mut(out_refund_no)?;
+        if refund.status == RefundStatus::Processing {
+            refund.status = status;
+            match status {
+                RefundStatus::Success => {
+                    refund.success_time = Some(Local::now().format(DATETIME_FORMAT).to_string())
+                }
+                RefundStatus::Closed | RefundStatus::Abnormal => {
+                    if let Some(trade) = self.trades.get_mut(&refund.out_trade_no) {
+                        trade.refunded -= refund.refund;
+                        if trade.refunded == 0 {
+                            trade.trade_state = TradeState::Success;
+                        }
+                    }
+                }
+                RefundStatus::Processing => {}
+            }
+        }
+        Some(refund)
+    }
+
+    fn create_combine_trade(&mut self, req: &Value) -> Handled {
+        let combine_out_trade_no = str_field(req, "combine_out_trade_no")?;
+        if let Some(combine) = self.combine_trades.get(&combine_out_trade_no) {
+            for out_trade_no in &combine.sub_orders {
+                check_not_paid(&self.trades[out_trade_no])?;
+            }
+            return Ok(Reply::Json(json!({ "prepay_id": combine.prepay_id })));
+        }
+
+        let combine_app_id = str_field(req, "combine_appid")?;
+        let openid = req
+            .pointer("/combine_payer_info/openid")
+            .and_then(Value::as_str)
+            .map(ToString::to_string);
+        let sub_orders = req["sub_orders"].as_array();
+        let sub_orders = sub_orders.ok_or_else(|| Failure::param("缺少参数 sub_orders"))?;
+        let mut trades = Vec::with_capacity(sub_orders.len());
+        for sub_order in sub_orders {
+            let out_trade_no = str_field(sub_order, "out_trade_no")?;
+            if self.trades.contains_key(&out_trade_no) {
+                return Err(Failure::new(
+                    StatusCode::BAD_REQUEST,
+                    "OUT_TRADE_NO_USED",
+                    "商户订单号重复",
+                ));
+            }
+            let total = int_field(sub_order, "/amount/total_amount")?;
+            if total <= 0 {
+                return Err(Failure::param("total_amount 必须大于 0"));
+            }
+            trades.push(Trade {
+                app_id: combine_app_id.clone(),
+                mch_id: str_field(sub_order, "mchid")?,
+                sub_mch_id: sub_order["sub_mchid"].as_str().map(ToString::to_string),
+                sub_app_id: sub_order["sub_appid"].as_str().map(ToString::to_string),
+                partner: false,
+                out_trade_no,
+                transaction_id: None,
+                trade_type: TradeType::JsApi,
+                trade_state: TradeState::NotPay,
+                prepay_id: String::new(),
+                description: sub_order["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                attach: sub_order["attach"].as_str().map(ToString::to_string),
+                notify_url: String::new(),
+                total,
+                currency: currency(sub_order.pointer("/amount/currency")),
+                openid: openid.clone(),
+                sub_openid: None,
+                success_time: None,
+                refunded: 0,
+                shared: 0,
+                sharing_finished: false,
+            });
+        }
+
+        let prepay_id = self.next_id("wx");
+        let notify_url = str_field(req, "notify_url")?;
+        let combine = CombineTrade {
+            combine_app_id,
+            combine_mch_id: str_field(req, "combine_mchid")?,
+            combine_out_trade_no: combine_out_trade_no.clone(),
+            prepay_id: prepay_id.clone(),
+            notify_url: notify_url.clone(),
+            openid,
+            sub_orders: trades.iter().map(|t| t.out_trade_no.clone()).collect(),
+        };
+        for mut trade in trades {
+            trade.prepay_id = prepay_id.clone();
+            trade.notify_url = notify_url.clone();
+            self.trades.insert(trade.out_trade_no.clone(), trade);
+        }
+        self.combine_trades.insert(combine_out_trade_no, combine);
+        Ok(Reply::Json(json!({ "prepay_id": prepay_id })))
+    }
+
+    fn query_combine_trade(&self, combine_out_trade_no: &str) -> Handled {
+        let combine = self.combine_trades.get(combine_out_trade_no);
+        let combine = combine.ok_or_else(Failure::order_not_exist)?;
+        Ok(Reply::Json(self.combine_trade_json(combine)))
+    }
+
+    /// 合单查询接口及合单支付通知中的合单数据。
+    pub(super) fn combine_trade_json(&self, combine: &CombineTrade) -> Value {
+        let sub_orders: Vec<Value> = combine
+            .sub_orders
+            .iter()
+            .map(|no| self.trades[no].to_sub_order_json())
+            .collect();
+        json!({
+            "combine_appid": combine.combine_app_id,
+            "combine_mchid": combine.combine_mch_id,
+            "combine_out_trade_no": combine.combine_out_trade_no,
+            "combine_payer_info": { "openid": combine.openid },
+            "sub_orders": sub_orders,
+        })
+    }
+
+    fn create_profit_sharing(&mut self, mch_id: &str, req: &Value) -> Handled {
+        let out_order_no = str_field(req, "out_order_no")?;
+        if let Some(sharing) = self.profit_sharings.get(&out_order_no) {
+            return Ok(Reply::Json(sharing.to_json()));
+        }
+
+        let sub_mch_id = str_field(req, "sub_mchid")?;
+        let transaction_id = str_field(req, "transaction_id")?;
+        let trade = self.trade_by_transaction_id(&transaction_id)?;
+        let unsplit_amount = trade.unsplit_amount();
+        let out_trade_no = trade.out_trade_no.clone();
+
+        let receivers = req["receivers"].as_array();
+        let receivers = receivers.ok_or_else(|| Failure::param("缺少参数 receivers"))?;
+        let amount: i64 = receivers.iter().filter_map(|r| r["amount"].as_i64()).sum();
+        if amount > unsplit_amount {
+            return Err(Failure::new(
+                StatusCode::FORBIDDEN,
+                "NOT_ENOUGH",
+                "分账金额超过订单待分账金额",
+            ));
+        }
+
+        let finish_time = Local::now().format(DATETIME_FORMAT).to_string();
+        let mut receivers_json = Vec::with_capacity(receivers.len());
+        for receiver in receivers {
+            let account = str_field(receiver, "receiver_account")?;
+            receivers_json.push(json!({
+                "receiver_mchid": account,
+                "amount": int_field(receiver, "/amount")?,
+                "description": receiver["description"].as_str().unwrap_or_default(),
+                "result": "SUCCESS",
+                "finish_time": finish_time,
+                "type": str_field(receiver, "type")?,
+                "receiver_account": account,
+                "detail_id": self.next_id("36011"),
+            }));
+        }
+        let sharing = ProfitSharing {
+            order_id: self.next_id("30000"),
+            sp_mch_id: mch_id.to_string(),
+            sub_mch_id,
+            transaction_id,
+            out_order_no: out_order_no.clone(),
+            receivers: receivers_json,
+            finish_amount: None,
+            finish_description: None,
+        };
+
+        let trade = self.trades.get_mut(&out_trade_no).unwrap();
+        trade.shared += amount;
+        if req["finish"].as_bool().unwrap_or_default() {
+            trade.sharing_finished = true;
+        }
+        let reply = sharing.to_json();
+        self.profit_sharings.insert(out_order_no, sharing);
+        Ok(Reply::Json(reply))
+    }
+
+    fn finish_profit_sharing(&mut self, mch_id: &str, req: &Value) -> Handled {
+        let out_order_no = str_field(req, "out_order_no")?;
+        if self.profit_sharings.contains_key(&out_order_no) {
+            return Err(Failure::new(
+                StatusCode::BAD_REQUEST,
+                "ALREADY_EXISTS",
+                "商户分账单号重复",
+            ));
+        }
+        let transaction_id = str_field(req, "transaction_id")?;
+        let trade = self.trade_by_transaction_id(&transaction_id)?;
+        let finish_amount = trade.unsplit_amount();
+        let out_trade_no = trade.out_trade_no.clone();
+
+        let sharing = ProfitSharing {
+            order_id: self.next_id("30000"),
+            sp_mch_id: mch_id.to_string(),
+            sub_mch_id: str_field(req, "sub_mchid")?,
+            transaction_id,
+            out_order_no: out_order_no.clone(),
+            receivers: Vec::new(),
+            finish_amount: Some(finish_amount),
+            finish_description: req["description"].as_str().map(ToString::to_string),
+        };
+        self.trades.get_mut(&out_trade_no).unwrap().sharing_finished = true;
+        let reply = json!({
+            "sub_mchid": sharing.sub_mch_id,
+            "transaction_id": sharing.transaction_id,
+            "out_order_no": sharing.out_order_no,
+            "order_id": sharing.order_id,
+        });
+        self.profit_sharings.insert(out_order_no, sharing);
+        Ok(Reply::Json(reply))
+    }
+
+    /// 设置二级商户或平台账户的可用余额。`sub_mch_id` 为 None 时设置平台账户。
+    pub(super) fn set_balance(&mut self, sub_mch_id: Option<&str>, account: &str, amount: i64) {
+        match sub_mch_id {
+            Some(sub_mch_id) => self.sub_mch_balances.insert(sub_mch_id.to_string(), amount),
+            None => self.platform_balances.insert(account.to_string(), amount),
+        };
+    }
+
+    fn create_withdraw(
+        &mut self,
+        mch_id: &str,
+        sub_mch_id: Option<String>,
+        req: &Value,
+    ) -> Result<Withdraw, Failure> {
+        let out_request_no = str_field(req, "out_request_no")?;
+        if let Some(withdraw) = self.withdraws.get(&out_request_no) {
+            return Ok(withdraw.clone());
+        }
+
+        let amount = int_field(req, "/amount")?;
+        let account_type = req["account_type"].as_str().unwrap_or("BASIC").to_string();
+        let balance = match &sub_mch_id {
+            Some(sub_mch_id) => self.sub_mch_balances.entry(sub_mch_id.clone()),
+            None => self.platform_balances.entry(account_type.clone()),
+        }
+        .or_default();
+        if amount <= 0 || amount > *balance {
+            return Err(Failure::new(
+                StatusCode::FORBIDDEN,
+                "NOT_ENOUGH",
+                "余额不足",
+            ));
+        }
+        *balance -= amount;
+
+        let withdraw = Withdraw {
+            withdraw_id: self.next_id("10000"),
+            out_request_no: out_request_no.clone(),
+            sp_mch_id: mch_id.to_string(),
+            sub_mch_id,
+            amount,
+            account_type,
+            remark: req["remark"].as_str().unwrap_or_default().to_string(),
+            bank_memo: req["bank_memo"].as_str().unwrap_or_default().to_string(),
+            create_time: Local::now().format(DATETIME_FORMAT).to_string(),
+        };
+        self.withdraws.insert(out_request_no, withdraw.clone());
+        Ok(withdraw)
+    }
+
+    fn withdraw_reply(&self, withdraw: Option<&Withdraw>) -> Handled {
+        let withdraw = withdraw.ok_or_else(|| Failure::not_exists("提现单不存在"))?;
+        Ok(Reply::Json(withdraw.to_json()))
+    }
+
+    /// 设置进件申请单的状态。状态为 `FINISH` 时分配二级商户号。申请单不存在时返回 false。
+    /// 交易账单原文。订单按支付时间、退款按申请时间计入账单。
+    /// 各类型账单的列与 ALL 类型一致。
+    fn trade_bill(
+        &self,
+        mch_id: &str,
+        sub_mch_id: Option<&str>,
+        bill_date: &str,
+        bill_type: &str,
+    ) -> Result<String, Failure> {
+        let (payments, refunds) = match bill_type {
+            "ALL" => (true, true),
+            "SUCCESS" => (true, false),
+            "REFUND" => (false, true),
+            _ => return Err(Failure::param("bill_type 无效")),
+        };
+        let mut rows = Vec::new();
+        let (mut settlement_total, mut refund_total, mut fee_total) = (0, 0, 0);
+        for trade in self.bill_trades(mch_id, sub_mch_id) {
+            let success_time = trade.success_time.as_deref().unwrap_or_default();
+            if payments && trade.is_paid() && success_time.starts_with(bill_date) {
+                settlement_total += trade.total;
+                fee_total += trade.total * BILL_FEE_RATE;
+                rows.push(trade_bill_row(trade, None));
+            }
+            let trade_refunds = self.refunds.values().filter(|r| {
+                r.out_trade_no == trade.out_trade_no && r.create_time.starts_with(bill_date)
+            });
+            for refund in trade_refunds.filter(|_| refunds) {
+                refund_total += refund.refund;
+                fee_total -= refund.refund * BILL_FEE_RATE;
+                rows.push(trade_bill_row(trade, Some(refund)));
+            }
+        }
+        rows.sort();
+
+        let mut bill = String::from(
+            "交易时间,公众账号ID,商户号,特约商户号,设备号,微信订单号,商户订单号,用户标识,\
+             交易类型,交易状态,付款银行,货币种类,应结订单金额,代金券金额,微信退款单号,商户退款单号,\
+             退款金额,充值券退款金额,退款类型,退款状态,商品名称,商户数据包,手续费,费率,订单金额,\
+             申请退款金额,费率备注\n",
+        );
+        for (_, row) in &rows {
+            bill.push_str(row);
+        }
+        bill.push_str(
+            "总交易单数,应结订单总金额,退款总金额,充值券退款总金额,手续费总金额,订单总金额,申请退款总金额\n",
+        );
+        bill.push_str(&bill_line(&[
+            rows.len().to_string(),
+            yuan(settlement_total),
+            yuan(refund_total),
+            yuan(0),
+            fee_yuan(fee_total),
+            yuan(settlement_total),
+            yuan(refund_total),
+        ]));
+        Ok(bill)
+    }
+
+    /// 资金账单原文。只有基本账户有资金流水：支付成功记为收入，退款成功记为支出。
+    /// `sub_mch_id` 不为 None 时为二级商户的资金账单。
+    fn fund_flow_bill(
+        &self,
+        mch_id: &str,
+        sub_mch_id: Option<&str>,
+        bill_date: &str,
+        account_type: &str,
+    ) -> Result<String, Failure> {
+        if !["BASIC", "OPERATION", "FEES", "ALL"].contains(&account_type) {
+            return Err(Failure::param("account_type 无效"));
+        }
+        // (记账时间, 业务单号, 业务名称, 收支类型, 金额, 业务凭证号)
+        let mut flows = Vec::new();
+        let trades = self.bill_trades(mch_id, sub_mch_id);
+        for trade in trades.filter(|_| matches!(account_type, "BASIC" | "ALL")) {
+            let success_time = trade.success_time.as_deref().unwrap_or_default();
+            let transaction_id = trade.transaction_id.as_deref().unwrap_or_default();
+            if trade.is_paid() && success_time.starts_with(bill_date) {
+                let flow = (success_time, transaction_id, "交易", "收入", trade.total);
+                flows.push((flow, &trade.out_trade_no));
+            }
+            let trade_refunds = self.refunds.values().filter(|r| {
+                r.out_trade_no == trade.out_trade_no
+                    && r.status == RefundStatus::Success
+                    && r.success_time
+                        .as_deref()
+                        .is_some_and(|t| t.starts_with(bill_date))
+            });
+            for refund in trade_refunds {
+                let success_time = refund.success_time.as_deref().unwrap_or_default();
+                let flow = (
+                    success_time,
+                    refund.refund_id.as_str(),
+                    "退款",
+                    "支出",
+                    refund.refund,
+                );
+                flows.push((flow, &refund.out_refund_no));
+            }
+        }
+        flows.sort();
+
+        let mut bill = String::from(
+            "记账时间,微信支付业务单号,资金流水单号,业务名称,业务类型,收支类型,收支金额(元),\
+             账户结余(元),资金变更提交申请人,备注,业务凭证号\n",
+        );
+        let (mut balance, mut income, mut expense) = (0, Vec::new(), Vec::new());
+        for ((time, biz_id, biz_name, flow_type, amount), voucher_no) in &flows {
+            if *flow_type == "收入" {
+                balance += amount;
+                income.push(amount);
+            } else {
+                balance -= amount;
+                expense.push(amount);
+            }
+            bill.push_str(&bill_line(&[
+                bill_time(time),
+                biz_id.to_string(),
+                biz_id.to_string(),
+                biz_name.to_string(),
+                biz_name.to_string(),
+                flow_type.to_string(),
+                yuan(*amount),
+                yuan(balance),
+                "system".to_string(),
+                String::new(),
+                voucher_no.to_string(),
+            ]));
+        }
+        bill.push_str("资金流水总笔数,收入笔数,收入金额,支出笔数,支出金额\n");
+        bill.push_str(&bill_line(&[
+            flows.len().to_string(),
+            income.len().to_string(),
+            yuan(income.into_iter().sum()),
+            expense.len().to_string(),
+            yuan(expense.into_iter().sum()),
+        ]));
+        Ok(bill)
+    }
+
+    /// 商户(及子商户)的订单。
+    fn bill_trades<'a>(
+        &'a self,
+        mch_id: &'a str,
+        sub_mch_id: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a Trade> + 'a {
+        self.trades.values().filter(move |t| {
+            t.mch_id == mch_id && sub_mch_id.is_none_or(|s| t.sub_mch_id.as_deref() == Some(s))
+        })
+    }
+
+    /// 保存账单文件，返回申请账单接口的响应。
+    fn save_bill(
+        &mut self,
+        origin: &str,
+        content: &str,
+        tar_type: Option<&String>,
+    ) -> Result<Value, Failure> {
+        let file = compress_bill(content, tar_type)?;
+        let token = self.next_id("mock-bill-");
+        self.bill_files.insert(token.clone(), file);
+        Ok(json!({
+            "hash_type": "SHA1",
+            "hash_value": sha1_hex(content.as_bytes()),
+            "download_url": format!("{}/v3/billdownload/file?token={}", origin, token),
+        }))
+    }
+
+    /// 保存加密的账单文件。文件密钥使用商户公钥加密，因此需要设置商户公钥。
+    fn save_encrypted_bill(
+        &mut self,
+        origin: &str,
+        content: &str,
+        tar_type: Option<&String>,
+    ) -> Result<Value, Failure> {
+        let public_key = self
+            .mch_public_key
+            .as_ref()
+            .ok_or_else(|| Failure::invalid("模拟服务未设置商户公钥，无法加密账单文件"))?;
+        let key = generate_none_str(32);
+        let nonce = generate_none_str(12);
+        let encrypt_key = encrypt(public_key, key.as_bytes()).map_err(|e| {
+            Failure::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "SYSTEM_ERROR",
+                e.to_string(),
+            )
+        })?;
+        let file = compress_bill(content, tar_type)?;
+        let payload = Payload {
+            msg: &file,
+            aad: b"",
+        };
+        let file = Aes256Gcm::new(key.as_bytes().into())
+            .encrypt(nonce.as_bytes().into(), payload)
+            .expect("invalid bill key");
+        let token = self.next_id("mock-bill-");
+        self.bill_files.insert(token.clone(), file);
+        Ok(json!({
+            "bill_sequence": 1,
+            "download_url": format!("{}/v3/billdownload/file?token={}", origin, token),
+            "encrypt_key": encrypt_key,
+            "hash_type": "SHA1",
+            "hash_value": sha1_hex(content.as_bytes()),
+            "nonce": nonce,
+        }))
+    }
+
+    pub(super) fn set_applyment_state(&mut self, out_request_no: &str, state: &str) -> bool {
+        if !self.applyments.contains_key(out_request_no) {
+            return false;
+        }
+        self.seq += 1;
+        let sub_mch_id = format!("1900{:06}", self.seq);
+        let applyment = self.applyments.get_mut(out_request_no).unwrap();
+        applyment.state = state.to_string();
+        if state == "FINISH" && applyment.sub_mch_id.is_none() {
+            applyment.sub_mch_id = Some(sub_mch_id);
+        }
+        true
+    }
+}
+
+/// 服务商模式的订单须属于请求的子商户。
+fn check_sub_mch<'a>(trade: &'a Trade, sub_mch_id: &str) -> Result<&'a Trade, Failure> {
+    if trade.partner && trade.sub_mch_id.as_deref() == Some(sub_mch_id) {
+        Ok(trade)
+    } else {
+        Err(Failure::order_not_exist())
+    }
+}
+
+fn check_not_paid(trade: &Trade) -> Result<(), Failure> {
+    match trade.trade_state {
+        TradeState::Success | TradeState::Refund => Err(Failure::new(
+            StatusCode::BAD_REQUEST,
+            "ORDERPAID",
+            "订单已支付",
+        )),
+        TradeState::Closed | TradeState::Revoked => Err(Failure::new(
+            StatusCode::BAD_REQUEST,
+            "ORDER_CLOSED",
+            "订单已关闭",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 模拟的手续费率，0.6%。手续费以 0.00001 元为单位，即每分收取 6 个单位。
+const BILL_FEE_RATE: i64 = 6;
+
+/// 交易账单中的一行：支付记录，或 `refund` 的退款记录。以交易时间排序。
+fn trade_bill_row(trade: &Trade, refund: Option<&Refund>) -> (String, String) {
+    let (time, trade_state, settlement_total, fee, total) = match refund {
+        None => (
+            trade.success_time.as_deref().unwrap_or_default(),
+            "SUCCESS",
+            trade.total,
+            trade.total * BILL_FEE_RATE,
+            trade.total,
+        ),
+        Some(refund) => (
+            refund.create_time.as_str(),
+            "REFUND",
+            0,
+            -refund.refund * BILL_FEE_RATE,
+            0,
+        ),
+    };
+    let refund_status = refund.map(|r| json!(r.status).as_str().unwrap_or_default().to_string());
+    let row = bill_line(&[
+        bill_time(time),
+        trade.app_id.clone(),
+        trade.mch_id.clone(),
+        trade.sub_mch_id.clone().unwrap_or_else(|| "0".to_string()),
+        String::new(),
+        trade.transaction_id.clone().unwrap_or_default(),
+        trade.out_trade_no.clone(),
+        trade.openid.clone().unwrap_or_default(),
+        trade.trade_type.as_str().to_string(),
+        trade_state.to_string(),
+        "OTHERS".to_string(),
+        trade.currency.clone(),
+        yuan(settlement_total),
+        yuan(0),
+        refund.map_or("0".to_string(), |r| r.refund_id.clone()),
+        refund.map_or("0".to_string(), |r| r.out_refund_no.clone()),
+        yuan(refund.map_or(0, |r| r.refund)),
+        yuan(0),
+        refund.map_or(String::new(), |_| "ORIGINAL".to_string()),
+        refund_status.unwrap_or_default(),
+        trade.description.clone(),
+        trade.attach.clone().unwrap_or_default(),
+        fee_yuan(fee),
+        "0.60%".to_string(),
+        yuan(total),
+        yuan(refund.map_or(0, |r| r.refund)),
+        String::new(),
+    ]);
+    (bill_time(time), row)
+}
+
+/// 账单中的一行，每个字段以 ` 开头。
+fn bill_line(fields: &[String]) -> String {
+    let fields: Vec<String> = fields.iter().map(|f| format!("`{}", f)).collect();
+    format!("{}\n", fields.join(","))
+}
+
+/// 将接口中的时间，如 `2024-01-01T12:00:00+08:00`，转换为账单中的格式。
+fn bill_time(time: &str) -> String {
+    time.get(..19).unwrap_or(time).replace('T', " ")
+}
+
+/// 以元为单位的金额，保留两位小数。
+fn yuan(fen: i64) -> String {
+    let sign = if fen < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, fen.abs() / 100, fen.abs() % 100)
+}
+
+/// 以元为单位的手续费，保留五位小数。
+fn fee_yuan(fee: i64) -> String {
+    let sign = if fee < 0 { "-" } else { "" };
+    format!("{}{}.{:05}", sign, fee.abs() / 100_000, fee.abs() % 100_000)
+}
+
+fn bill_date(query: &HashMap<String, String>) -> Result<String, Failure> {
+    let bill_date = query.get("bill_date").map_or("", String::as_str);
+    NaiveDate::parse_from_str(bill_date, "%Y-%m-%d")
+        .map(|_| bill_date.to_string())
+        .map_err(|_| Failure::param("bill_date 格式错误"))
+}
+
+fn compress_bill(content: &str, tar_type: Option<&String>) -> Result<Vec<u8>, Failure> {
+    match tar_type.map(String::as_str) {
+        None => Ok(content.as_bytes().to_vec()),
+        Some("GZIP") => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content.as_bytes()).unwrap();
+            Ok(encoder.finish().unwrap())
+        }
+        Some(_) => Err(Failure::param("tar_type 无效")),
+    }
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn parse_json(body: &[u8]) -> Result<Value, Failure> {
+    serde_json::from_slice(body)
+        .map_err(|e| Failure::param(format!("请求体不是合法的 JSON: {}", e)))
+}
+
+fn str_field(v: &Value, name: &str) -> Result<String, Failure> {
+    v[name]
+        .as_str()
+        .map(ToString::to_string)
+        .ok_or_else(|| Failure::param(format!("缺少参数 {}", name)))
+}
+
+/// 按 JSON pointer 读取整数字段，如 `/amount/total`。
+fn int_field(v: &Value, pointer: &str) -> Result<i64, Failure> {
+    v.pointer(pointer)
+        .and_then(Value::as_i64)
+        .ok_or_else(|| Failure::param(format!("缺少参数 {}", &pointer[1..].replace('/', "."))))
+}
+
+fn currency(v: Option<&Value>) -> String {
+    v.and_then(Value::as_str).unwrap_or("CNY").to_string()
+}
+
+fn insert_some<T: serde::Serialize>(m: &mut Map<String, Value>, key: &str, value: &Option<T>) {
+    if let Some(value) = value {
+        m.insert(key.to_string(), json!(value));
+    }
+}
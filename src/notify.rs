@@ -1,14 +1,16 @@
 //! 微信支付通知。包括支付结果与退款结果的通知。
 
+use crate::error::{Error, Result};
 use crate::refund::RefundStatus;
+use crate::replay::{Redelivery, ReplayProtection};
 use crate::util::datetime_fmt;
 use crate::util::option_datetime_fmt;
 use crate::{client::WechatPayClient, trade::TradeQueryResponse};
-use anyhow::Result;
 use chrono::{DateTime, Local};
 use http::{StatusCode, Version};
 use hyper::body::Bytes;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::future::Future;
 
 /// 微信支付通知。
 /// 包括支付结果与退款结果。
@@ -20,11 +22,7 @@ pub struct WechatPayNotification {
     #[serde(with = "datetime_fmt")]
     pub create_time: DateTime<Local>,
     /// 通知类型。不超过 32 字符。
-    /// TRANSACTION.SUCCESS：支付成功通知。
-    /// REFUND.SUCCESS：退款成功通知
-    /// REFUND.ABNORMAL：退款异常通知
-    /// REFUND.CLOSED：退款关闭通知
-    pub event_type: String,
+    pub event_type: EventType,
     /// 通知的资源数据类型，不超过 32 字符。支付成功通知为 encrypt-resource。
     pub resource_type: String,
     /// 通知资源数据。
@@ -33,6 +31,130 @@ pub struct WechatPayNotification {
     pub summary: String,
 }
 
+/// 通知类型。未列出的类型解析为 [`EventType::Other`]。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EventType {
+    /// 支付成功通知。TRANSACTION.SUCCESS
+    TransactionSuccess,
+    /// 退款成功通知。REFUND.SUCCESS
+    RefundSuccess,
+    /// 退款异常通知。REFUND.ABNORMAL
+    RefundAbnormal,
+    /// 退款关闭通知。REFUND.CLOSED
+    RefundClosed,
+    /// 分账动账通知。PROFITSHARING.SUCCESS
+    ProfitSharingSuccess,
+    /// 分账回退动账通知。PROFITSHARING.RETURN
+    ProfitSharingReturn,
+    /// 新投诉通知。COMPLAINT.CREATE
+    ComplaintCreate,
+    /// 投诉状态变更通知。COMPLAINT.STATE_CHANGE
+    ComplaintStateChange,
+    /// 商家转账单据终态通知。MCHTRANSFER.BILL.FINISHED
+    TransferBillFinished,
+    /// 商家转账批次完成通知。MCHTRANSFER.BATCH.FINISHED
+    TransferBatchFinished,
+    /// 商家转账批次关闭通知。MCHTRANSFER.BATCH.CLOSED
+    TransferBatchClosed,
+    /// 代金券核销通知。COUPON.USE
+    CouponUse,
+    /// 二级商户进件申请单状态变更通知。APPLYMENT_STATE.CHANGE
+    ApplymentStateChange,
+    /// 提现成功通知。WITHDRAW.SUCCESS
+    WithdrawSuccess,
+    /// 提现失败通知。WITHDRAW.FAIL
+    WithdrawFail,
+    /// 未列出的通知类型，保留原始字符串。
+    Other(String),
+}
+
+impl EventType {
+    /// 微信支付定义的通知类型字符串，如 `TRANSACTION.SUCCESS`。
+    pub fn as_str(&self) -> &str {
+        match self {
+            EventType::TransactionSuccess => "TRANSACTION.SUCCESS",
+            EventType::RefundSuccess => "REFUND.SUCCESS",
+            EventType::RefundAbnormal => "REFUND.ABNORMAL",
+            EventType::RefundClosed => "REFUND.CLOSED",
+            EventType::ProfitSharingSuccess => "PROFITSHARING.SUCCESS",
+            EventType::ProfitSharingReturn => "PROFITSHARING.RETURN",
+            EventType::ComplaintCreate => "COMPLAINT.CREATE",
+            EventType::ComplaintStateChange => "COMPLAINT.STATE_CHANGE",
+            EventType::TransferBillFinished => "MCHTRANSFER.BILL.FINISHED",
+            EventType::TransferBatchFinished => "MCHTRANSFER.BATCH.FINISHED",
+            EventType::TransferBatchClosed => "MCHTRANSFER.BATCH.CLOSED",
+            EventType::CouponUse => "COUPON.USE",
+            EventType::ApplymentStateChange => "APPLYMENT_STATE.CHANGE",
+            EventType::WithdrawSuccess => "WITHDRAW.SUCCESS",
+            EventType::WithdrawFail => "WITHDRAW.FAIL",
+            EventType::Other(s) => s,
+        }
+    }
+
+    /// 是否为退款通知(REFUND.*)。
+    pub fn is_refund(&self) -> bool {
+        self.as_str().starts_with("REFUND.")
+    }
+
+    /// 是否为分账通知(PROFITSHARING.*)。
+    pub fn is_profit_sharing(&self) -> bool {
+        self.as_str().starts_with("PROFITSHARING.")
+    }
+
+    /// 是否为提现通知(WITHDRAW.*)。
+    pub fn is_withdraw(&self) -> bool {
+        self.as_str().starts_with("WITHDRAW.")
+    }
+}
+
+impl From<&str> for EventType {
+    fn from(s: &str) -> EventType {
+        match s {
+            "TRANSACTION.SUCCESS" => EventType::TransactionSuccess,
+            "REFUND.SUCCESS" => EventType::RefundSuccess,
+            "REFUND.ABNORMAL" => EventType::RefundAbnormal,
+            "REFUND.CLOSED" => EventType::RefundClosed,
+            "PROFITSHARING.SUCCESS" => EventType::ProfitSharingSuccess,
+            "PROFITSHARING.RETURN" => EventType::ProfitSharingReturn,
+            "COMPLAINT.CREATE" => EventType::ComplaintCreate,
+            "COMPLAINT.STATE_CHANGE" => EventType::ComplaintStateChange,
+            "MCHTRANSFER.BILL.FINISHED" => EventType::TransferBillFinished,
+            "MCHTRANSFER.BATCH.FINISHED" => EventType::TransferBatchFinished,
+            "MCHTRANSFER.BATCH.CLOSED" => EventType::TransferBatchClosed,
+            "COUPON.USE" => EventType::CouponUse,
+            "APPLYMENT_STATE.CHANGE" => EventType::ApplymentStateChange,
+            "WITHDRAW.SUCCESS" => EventType::WithdrawSuccess,
+            "WITHDRAW.FAIL" => EventType::WithdrawFail,
+            _ => EventType::Other(s.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for EventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventType {
+    fn deserialize<D>(deserializer: D) -> Result<EventType, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(EventType::from(s.as_str()))
+    }
+}
+
+impl Serialize for EventType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 /// 通知资源数据。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationResourse {
@@ -83,23 +205,180 @@ pub struct RefundNotifyResponse {
     /// 金额详细信息
     pub amount: RefundNotifyAmount,
 }
+/// 退款通知中的金额信息。单位为分。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RefundNotifyAmount {
-    total: i32,
-    refund: i32,
-    payer_total: i32,
-    payer_refund: i32,
+    /// 订单总金额
+    pub total: i32,
+    /// 退款金额
+    pub refund: i32,
+    /// 用户实际支付金额
+    pub payer_total: i32,
+    /// 用户退款金额
+    pub payer_refund: i32,
 }
 
+/// 解密后的通知资源。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NotificationEvent {
+    /// 支付成功通知。
     Trade(TradeQueryResponse),
+    /// 退款成功、异常或关闭通知。
     Refund(RefundNotifyResponse),
+    /// 其他通知，资源解密后不做解析。
+    Other(serde_json::Value),
+}
+
+/// 经验签、解密的通知。由 [`WechatPayClient::handle_notification`] 返回。
+/// 服务商模式的通知见 [`ServiceProvider::handle_partner_notification`](crate::partner::service_provider::ServiceProvider::handle_partner_notification)。
+#[derive(Debug, Clone)]
+pub struct Notification<E = NotificationEvent> {
+    /// 通知的唯一 ID。微信支付重发的通知 ID 不变。
+    pub id: String,
+    /// 通知创建的时间。
+    pub create_time: DateTime<Local>,
+    /// 通知类型。
+    pub event_type: EventType,
+    /// 回调摘要。
+    pub summary: String,
+    /// 是否为微信支付重发的通知，即此前已成功应答过相同的通知 ID。
+    /// 参见 [`DuplicatePolicy`](crate::replay::DuplicatePolicy)。
+    pub redelivery: bool,
+    /// 解密后的通知资源。
+    pub event: E,
+    /// 用于在成功应答后记录通知 ID。
+    replay: ReplayProtection,
+}
+
+impl<E> Notification<E> {
+    /// 由业务处理的结果构造应答。处理成功时记录通知 ID，此后相同 ID 的通知标记为重发；
+    /// 处理失败时不记录，微信支付重发的通知仍按新通知处理。
+    ///
+    /// ```ignore
+    /// async fn notify(notification: Notification) -> NotificationAck {
+    ///     let result = process(&notification.event).await;
+    ///     notification.ack(result).await
+    /// }
+    /// ```
+    pub async fn ack<T: std::fmt::Display>(
+        &self,
+        result: std::result::Result<(), T>,
+    ) -> NotificationAck {
+        if result.is_ok() {
+            commit(&self.replay, &self.id).await;
+        }
+        NotificationAck::from(result)
+    }
+}
+
+/// 业务处理成功后记录通知 ID。记录失败只影响此后的重发标记，因此仍应答成功。
+async fn commit(replay: &ReplayProtection, id: &str) {
+    if let Err(e) = replay.commit_notification(id).await {
+        log::warn!("failed to commit wechatpay notification {}: {}", id, e);
+    }
+}
+
+/// 商户对通知的应答。
+/// 接收成功时返回 HTTP 204，无需应答报文；失败时返回 4XX 或 5XX，
+/// 应答报文为 `{"code":"FAIL","message":"失败"}`，微信支付会按策略重发通知。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_1_5.shtml>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationAck {
+    /// 接收成功。
+    Success,
+    /// 接收失败。
+    Fail {
+        /// HTTP 状态码，须为 4XX 或 5XX。
+        status: StatusCode,
+        /// 失败原因。
+        message: String,
+    },
+}
+
+impl NotificationAck {
+    /// 接收成功。
+    pub fn success() -> NotificationAck {
+        NotificationAck::Success
+    }
+
+    /// 接收失败，状态码为 500。
+    pub fn fail(message: impl Into<String>) -> NotificationAck {
+        NotificationAck::Fail {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.into(),
+        }
+    }
+
+    /// 应答的 HTTP 状态码。
+    pub fn status(&self) -> StatusCode {
+        match self {
+            NotificationAck::Success => StatusCode::NO_CONTENT,
+            NotificationAck::Fail { status, .. } => *status,
+        }
+    }
+
+    /// 应答报文。接收成功时为空。
+    pub fn body(&self) -> Bytes {
+        match self {
+            NotificationAck::Success => Bytes::new(),
+            NotificationAck::Fail { message, .. } => {
+                let body = serde_json::json!({ "code": "FAIL", "message": message });
+                Bytes::from(body.to_string())
+            }
+        }
+    }
+
+    /// 转换为 HTTP 应答。
+    pub fn into_response(self) -> http::Response<Bytes> {
+        let mut builder = http::Response::builder().status(self.status());
+        if let NotificationAck::Fail { .. } = self {
+            builder = builder.header(http::header::CONTENT_TYPE, "application/json");
+        }
+        builder.body(self.body()).unwrap()
+    }
+}
+
+impl<E: std::fmt::Display> From<std::result::Result<(), E>> for NotificationAck {
+    /// 由业务处理的结果构造应答。
+    fn from(result: std::result::Result<(), E>) -> NotificationAck {
+        match result {
+            Ok(()) => NotificationAck::success(),
+            Err(e) => NotificationAck::fail(e.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for NotificationAck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationAck::Success => f.write_str("SUCCESS"),
+            NotificationAck::Fail { status, message } => write!(f, "FAIL({}): {}", status, message),
+        }
+    }
+}
+
+impl From<&Error> for NotificationAck {
+    /// 验签、防重放、解析或解密失败的通知不是有效的通知，返回 400；其余错误返回 500。
+    fn from(e: &Error) -> NotificationAck {
+        let status = match e {
+            Error::Verification(_) | Error::Replay(_) | Error::Serde(_) | Error::Decryption(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        NotificationAck::Fail {
+            status,
+            message: e.to_string(),
+        }
+    }
 }
 
 impl WechatPayClient {
-    /// 对微信支付结果通知进行验签。
-    /// 为避免对于具体 web 框架的依赖，这里的参数为 `http::Request<hyper::Body>`。
+    /// 对微信支付结果通知进行验签，并检查时间戳、随机串及通知 ID 以防重放。
+    /// 为避免对于具体 web 框架的依赖，这里的参数为 `http::Request<Bytes>`。
+    /// 随机串重复时返回 [`Error::Replay`]；通知 ID 已成功应答过(即微信支付重发的通知)时，
+    /// 默认在返回请求的 extensions 中加入 [`Redelivery`]，参见 [`ReplayProtection`](crate::ReplayProtection)。
+    /// 业务处理成功后，须调用 [`commit_notification`](Self::commit_notification) 记录通知 ID。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_1_5.shtml>
     pub async fn verify_notification(
         &self,
@@ -128,28 +407,310 @@ impl WechatPayClient {
             req_builder = req_builder.header(key, value);
         }
         let body = res.bytes().await?;
-        let req: http::Request<Bytes> = req_builder.body(body)?;
+        let mut req: http::Request<Bytes> = req_builder.body(body)?;
+
+        let redelivery = self
+            .verifier
+            .replay_protection
+            .check_notification(req.headers(), req.body())
+            .await?;
+        if redelivery {
+            req.extensions_mut().insert(Redelivery);
+        }
         Ok(req)
     }
 
-    /// 解密微信支付结果通知，解密结果为 TradeQueryResponse
+    /// 记录已成功应答的通知 ID，此后相同 ID 的通知视为重发。
+    /// 业务处理失败时不应调用，以便微信支付重发的通知按新通知处理。
+    pub async fn commit_notification(&self, id: &str) -> Result<()> {
+        self.verifier
+            .replay_protection
+            .commit_notification(id)
+            .await
+    }
+
+    /// 解密微信支付通知，按通知类型解析通知资源。
     pub fn decrypt_notification(&self, noti: &WechatPayNotification) -> Result<NotificationEvent> {
-        let plain = self.mch_credential.aes_decrypt(
+        let plain = self.merchant.aes_decrypt(
             &noti.resource.ciphertext,
             &noti.resource.associated_data,
             &noti.resource.nonce,
         )?;
 
-        let event = match noti.resource.original_type.as_str() {
-            "transaction" => NotificationEvent::Trade(serde_json::from_slice(&plain)?),
-            "refund" => NotificationEvent::Refund(serde_json::from_slice(&plain)?),
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "unknown notification type: {}",
-                    noti.resource.original_type
-                ));
+        let original_type = noti.resource.original_type.as_str();
+        let event =
+            if noti.event_type == EventType::TransactionSuccess || original_type == "transaction" {
+                NotificationEvent::Trade(serde_json::from_slice(&plain)?)
+            } else if noti.event_type.is_refund() || original_type == "refund" {
+                NotificationEvent::Refund(serde_json::from_slice(&plain)?)
+            } else {
+                // 新增的通知类型不应导致回调处理失败。
+                NotificationEvent::Other(serde_json::from_slice(&plain)?)
+            };
+        Ok(event)
+    }
+
+    /// 处理微信支付通知：验签、防重放检查、解析并解密。
+    /// 处理完成后，以 [`NotificationAck`] 应答微信支付。
+    /// [`Notification::ack`] 在处理成功时记录通知 ID。
+    ///
+    /// ```ignore
+    /// let ack = match wechatpay_client.handle_notification(req).await {
+    ///     Ok(notification) => {
+    ///         let result = process(&notification).await;
+    ///         notification.ack(result).await
+    ///     }
+    ///     Err(e) => NotificationAck::from(&e),
+    /// };
+    /// ack.into_response()
+    /// ```
+    pub async fn handle_notification(&self, req: http::Request<Bytes>) -> Result<Notification> {
+        self.handle_notification_with(req, WechatPayClient::decrypt_notification)
+            .await
+    }
+
+    /// 验签、防重放检查并解析通知，使用 `decrypt` 解密通知资源。
+    pub(crate) async fn handle_notification_with<E>(
+        &self,
+        req: http::Request<Bytes>,
+        decrypt: impl FnOnce(&WechatPayClient, &WechatPayNotification) -> Result<E>,
+    ) -> Result<Notification<E>> {
+        let req = self.verify_notification(req).await?;
+        let redelivery = req.extensions().get::<Redelivery>().is_some();
+        let notification: WechatPayNotification = serde_json::from_slice(req.body())?;
+        let event = decrypt(self, &notification)?;
+        Ok(Notification {
+            id: notification.id,
+            create_time: notification.create_time,
+            event_type: notification.event_type,
+            summary: notification.summary,
+            redelivery,
+            event,
+            replay: self.verifier.replay_protection.clone(),
+        })
+    }
+
+    /// 处理微信支付通知，并将业务处理的结果转换为应答。可直接用于 hyper、tower 等基于
+    /// `http` 的服务；axum 与 actix-web 可开启同名 feature，使用 [`Notification`] 提取器。
+    /// 通知无效时不调用 `handler`；`handler` 成功时记录通知 ID。
+    pub async fn serve_notification<F, Fut, E>(
+        &self,
+        req: http::Request<Bytes>,
+        handler: F,
+    ) -> http::Response<Bytes>
+    where
+        F: FnOnce(Notification) -> Fut,
+        Fut: Future<Output = std::result::Result<(), E>>,
+        E: std::fmt::Display,
+    {
+        let ack = match self.handle_notification(req).await {
+            Ok(notification) => {
+                let id = notification.id.clone();
+                let result = handler(notification).await;
+                if result.is_ok() {
+                    commit(&self.verifier.replay_protection, &id).await;
+                }
+                NotificationAck::from(result)
+            }
+            Err(e) => {
+                log::warn!("invalid wechatpay notification: {}", e);
+                NotificationAck::from(&e)
             }
         };
-        Ok(event)
+        ack.into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::{jsapi_params, test_client, test_server, NOTIFY_URL};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn test_handle_notification() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+
+        client.jsapi_create_trade(&jsapi_params("handle_1")).await?;
+        server.pay("handle_1")?;
+        let req = server.trade_notification("handle_1")?;
+        let redelivered = server.redeliver(&req)?;
+
+        let notification = client.handle_notification(req.clone()).await?;
+        assert_eq!(notification.event_type, EventType::TransactionSuccess);
+        assert!(!notification.redelivery);
+        match &notification.event {
+            NotificationEvent::Trade(trade) => assert_eq!(trade.out_trade_no, "handle_1"),
+            event => panic!("unexpected event: {:?}", event),
+        }
+        let res = notification.ack(Ok::<(), String>(())).await.into_response();
+        assert_eq!(res.status(), http::StatusCode::NO_CONTENT);
+        assert!(res.body().is_empty());
+
+        let redelivered = client.handle_notification(redelivered).await?;
+        assert_eq!(redelivered.id, notification.id);
+        assert!(redelivered.redelivery);
+
+        // 重放的通知应答失败
+        let err = client.handle_notification(req).await.unwrap_err();
+        let res = NotificationAck::from(&err).into_response();
+        assert_eq!(res.status(), http::StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_slice(res.body())?;
+        assert_eq!(body["code"], "FAIL");
+        assert!(body["message"].as_str().unwrap().contains("nonce"));
+
+        // 未解析的通知类型保留原始资源
+        let resource = json!({ "complaint_id": "200201820200101080076610000", "action_type": "CREATE_COMPLAINT" });
+        let req = server.notification(
+            NOTIFY_URL,
+            "COMPLAINT.CREATE",
+            "complaint",
+            "产生新投诉",
+            &resource,
+        )?;
+        let notification = client.handle_notification(req).await?;
+        assert_eq!(notification.event_type, EventType::ComplaintCreate);
+        match notification.event {
+            NotificationEvent::Other(value) => assert_eq!(value, resource),
+            event => panic!("unexpected event: {:?}", event),
+        }
+        let req = server.notification(
+            NOTIFY_URL,
+            "SOMETHING.NEW",
+            "something",
+            "新通知",
+            &resource,
+        )?;
+        let notification = client.handle_notification(req).await?;
+        assert_eq!(
+            notification.event_type,
+            EventType::Other("SOMETHING.NEW".to_string())
+        );
+        assert_eq!(
+            serde_json::to_value(&notification.event_type)?,
+            json!("SOMETHING.NEW")
+        );
+        assert!(matches!(notification.event, NotificationEvent::Other(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_type_serde() -> serde_json::Result<()> {
+        let known = [
+            EventType::TransactionSuccess,
+            EventType::RefundSuccess,
+            EventType::RefundAbnormal,
+            EventType::RefundClosed,
+            EventType::ProfitSharingSuccess,
+            EventType::ProfitSharingReturn,
+            EventType::ComplaintCreate,
+            EventType::ComplaintStateChange,
+            EventType::TransferBillFinished,
+            EventType::TransferBatchFinished,
+            EventType::TransferBatchClosed,
+            EventType::CouponUse,
+            EventType::ApplymentStateChange,
+            EventType::WithdrawSuccess,
+            EventType::WithdrawFail,
+        ];
+        for event_type in known {
+            let value = serde_json::to_value(&event_type)?;
+            assert_eq!(value, json!(event_type.as_str()));
+            assert_eq!(serde_json::from_value::<EventType>(value)?, event_type);
+        }
+        assert!(EventType::WithdrawSuccess.is_withdraw());
+        assert!(!EventType::RefundSuccess.is_withdraw());
+        let other: EventType = serde_json::from_value(json!("SOMETHING.NEW"))?;
+        assert_eq!(other, EventType::Other("SOMETHING.NEW".to_string()));
+        assert_eq!(serde_json::to_value(&other)?, json!("SOMETHING.NEW"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_serve_notification() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+        client.jsapi_create_trade(&jsapi_params("serve_1")).await?;
+        client.jsapi_create_trade(&jsapi_params("serve_2")).await?;
+        server.pay("serve_1")?;
+        server.pay("serve_2")?;
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        let handler = |notification: Notification| {
+            calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                match notification.event {
+                    NotificationEvent::Trade(trade) if trade.out_trade_no == "serve_2" => {
+                        Err("database unavailable")
+                    }
+                    _ => Ok(()),
+                }
+            }
+        };
+
+        // 处理成功时应答 204，无应答报文
+        let req = server.trade_notification("serve_1")?;
+        let res = client.serve_notification(req, handler).await;
+        assert_eq!(res.status(), http::StatusCode::NO_CONTENT);
+        assert!(res.body().is_empty());
+
+        // 处理失败时应答 FAIL
+        let req = server.trade_notification("serve_2")?;
+        let res = client.serve_notification(req, handler).await;
+        assert_eq!(res.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            res.headers()[http::header::CONTENT_TYPE],
+            "application/json"
+        );
+        let body: Value = serde_json::from_slice(res.body())?;
+        assert_eq!(
+            body,
+            json!({ "code": "FAIL", "message": "database unavailable" })
+        );
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // 签名无效时不调用 handler
+        let mut req = server.trade_notification("serve_1")?;
+        req.headers_mut()
+            .insert("Wechatpay-Signature", "aW52YWxpZA==".parse()?);
+        let res = client.serve_notification(req, handler).await;
+        assert_eq!(res.status(), http::StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_slice(res.body())?;
+        assert_eq!(body["code"], "FAIL");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_redelivery_after_failure() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+        client.jsapi_create_trade(&jsapi_params("retry_1")).await?;
+        server.pay("retry_1")?;
+        let req = server.trade_notification("retry_1")?;
+        let redelivered = server.redeliver(&req)?;
+        let redelivered_again = server.redeliver(&req)?;
+
+        // 业务处理失败时不记录通知 ID
+        let res = client
+            .serve_notification(req, |_| async { Err("database unavailable") })
+            .await;
+        assert_eq!(res.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        // 重发的通知仍按新通知处理
+        let mut received = None;
+        let res = client
+            .serve_notification(redelivered, |notification| {
+                received = Some(notification.redelivery);
+                async { Ok::<(), String>(()) }
+            })
+            .await;
+        assert_eq!(res.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(received, Some(false));
+
+        // 成功应答后再次重发的通知被标记
+        let notification = client.handle_notification(redelivered_again).await?;
+        assert!(notification.redelivery);
+        Ok(())
     }
 }
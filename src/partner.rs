@@ -1 +1,2 @@
+pub mod service_provider;
 pub mod shou_fu_tong;
@@ -0,0 +1,353 @@
+//! 服务商(普通服务商)模式。
+//! 服务商为子商户下单，请求中使用 `sp_appid`、`sp_mchid` 标识服务商，
+//! `sub_appid`、`sub_mchid` 标识子商户。客户端使用服务商的商户号及证书。
+
+pub mod notify;
+pub mod refund;
+pub mod trade;
+
+use crate::error::Result;
+use crate::notify::{Notification, WechatPayNotification};
+use crate::refund::RefundQueryResponse;
+use crate::WechatPayClient;
+use async_trait::async_trait;
+use hyper::body::Bytes;
+use notify::PartnerNotificationEvent;
+use refund::PartnerRefundParams;
+use trade::{
+    PartnerAppCreateTradeParams, PartnerH5CreateTradeParams, PartnerJsApiCreateTradeParams,
+    PartnerNativeCreateTradeParams, PartnerTradeQueryResponse,
+};
+
+#[async_trait]
+pub trait ServiceProvider {
+    /// JSAPI 下单，返回 prepay_id。
+    async fn partner_jsapi_create_trade(
+        &self,
+        params: &PartnerJsApiCreateTradeParams,
+    ) -> Result<String>;
+
+    /// APP 下单，返回 prepay_id。
+    async fn partner_app_create_trade(
+        &self,
+        params: &PartnerAppCreateTradeParams,
+    ) -> Result<String>;
+
+    /// H5 下单，返回 h5_url。
+    async fn partner_h5_create_trade(&self, params: &PartnerH5CreateTradeParams) -> Result<String>;
+
+    /// Native 下单，返回二维码 url (code_url)。
+    async fn partner_native_create_trade(
+        &self,
+        params: &PartnerNativeCreateTradeParams,
+    ) -> Result<String>;
+
+    /// 通过微信支付订单号查询子商户的订单。
+    async fn partner_query_trade_by_transaction_id(
+        &self,
+        sub_mchid: &str,
+        transaction_id: &str,
+    ) -> Result<PartnerTradeQueryResponse>;
+
+    /// 通过商户订单号查询子商户的订单。
+    async fn partner_query_trade_by_out_trade_no(
+        &self,
+        sub_mchid: &str,
+        out_trade_no: &str,
+    ) -> Result<PartnerTradeQueryResponse>;
+
+    /// 关闭子商户的订单。
+    async fn partner_close_trade(&self, sub_mchid: &str, out_trade_no: &str) -> Result<()>;
+
+    /// 为子商户申请退款。
+    async fn partner_apply_refund(
+        &self,
+        params: &PartnerRefundParams,
+    ) -> Result<RefundQueryResponse>;
+
+    /// 查询子商户的退款。
+    async fn partner_query_refund(
+        &self,
+        sub_mchid: &str,
+        out_refund_no: &str,
+    ) -> Result<RefundQueryResponse>;
+
+    /// 解密服务商模式的支付、退款通知。
+    fn decrypt_partner_notification(
+        &self,
+        notify: &WechatPayNotification,
+    ) -> Result<PartnerNotificationEvent>;
+
+    /// 处理服务商模式的通知：验签、防重放检查、解析并解密。
+    /// 参见 [`WechatPayClient::handle_notification`]。
+    async fn handle_partner_notification(
+        &self,
+        req: http::Request<Bytes>,
+    ) -> Result<Notification<PartnerNotificationEvent>>;
+}
+
+#[async_trait]
+impl ServiceProvider for WechatPayClient {
+    async fn partner_jsapi_create_trade(
+        &self,
+        params: &PartnerJsApiCreateTradeParams,
+    ) -> Result<String> {
+        trade::jsapi_create_trade(self, params).await
+    }
+
+    async fn partner_app_create_trade(
+        &self,
+        params: &PartnerAppCreateTradeParams,
+    ) -> Result<String> {
+        trade::app_create_trade(self, params).await
+    }
+
+    async fn partner_h5_create_trade(&self, params: &PartnerH5CreateTradeParams) -> Result<String> {
+        trade::h5_create_trade(self, params).await
+    }
+
+    async fn partner_native_create_trade(
+        &self,
+        params: &PartnerNativeCreateTradeParams,
+    ) -> Result<String> {
+        trade::native_create_trade(self, params).await
+    }
+
+    async fn partner_query_trade_by_transaction_id(
+        &self,
+        sub_mchid: &str,
+        transaction_id: &str,
+    ) -> Result<PartnerTradeQueryResponse> {
+        trade::query_trade_by_transaction_id(self, sub_mchid, transaction_id).await
+    }
+
+    async fn partner_query_trade_by_out_trade_no(
+        &self,
+        sub_mchid: &str,
+        out_trade_no: &str,
+    ) -> Result<PartnerTradeQueryResponse> {
+        trade::query_trade_by_out_trade_no(self, sub_mchid, out_trade_no).await
+    }
+
+    async fn partner_close_trade(&self, sub_mchid: &str, out_trade_no: &str) -> Result<()> {
+        trade::close_trade(self, sub_mchid, out_trade_no).await
+    }
+
+    async fn partner_apply_refund(
+        &self,
+        params: &PartnerRefundParams,
+    ) -> Result<RefundQueryResponse> {
+        refund::apply_refund(self, params).await
+    }
+
+    async fn partner_query_refund(
+        &self,
+        sub_mchid: &str,
+        out_refund_no: &str,
+    ) -> Result<RefundQueryResponse> {
+        refund::query_refund(self, sub_mchid, out_refund_no).await
+    }
+
+    fn decrypt_partner_notification(
+        &self,
+        notify: &WechatPayNotification,
+    ) -> Result<PartnerNotificationEvent> {
+        notify::decrypt_notification(self, notify)
+    }
+
+    async fn handle_partner_notification(
+        &self,
+        req: http::Request<Bytes>,
+    ) -> Result<Notification<PartnerNotificationEvent>> {
+        self.handle_notification_with(req, notify::decrypt_notification)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Error, ErrorCode, ValidationReason};
+    use crate::mock::fixtures::{refund_params, test_client, test_server, NOTIFY_URL};
+    use crate::notify::EventType;
+    use crate::refund::RefundStatus;
+    use crate::trade::{Amount, TradeState};
+    use serde_json::Value;
+    use trade::PartnerPayer;
+
+    fn partner_jsapi_params(out_trade_no: &str) -> PartnerJsApiCreateTradeParams {
+        PartnerJsApiCreateTradeParams {
+            sp_appid: "wx8888888888888888".to_string(),
+            sp_mchid: "1900000001".to_string(),
+            sub_appid: Some("wxd678efh567hg6999".to_string()),
+            sub_mchid: "1900000109".to_string(),
+            description: "Image形象店-深圳腾大-QQ公仔".to_string(),
+            out_trade_no: out_trade_no.to_string(),
+            time_expire: None,
+            attach: Some("自定义数据".to_string()),
+            notify_url: NOTIFY_URL.to_string(),
+            goods_tag: None,
+            support_fapiao: None,
+            amount: Amount::new_with_cny(100),
+            payer: PartnerPayer::sub_openid("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o".to_string()),
+            detail: None,
+            scene_info: None,
+            settle_info: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_service_provider() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+
+        let prepay_id = client
+            .partner_jsapi_create_trade(&partner_jsapi_params("partner_1"))
+            .await?;
+        assert!(prepay_id.starts_with("wx"));
+        let trade = client
+            .partner_query_trade_by_out_trade_no("1900000109", "partner_1")
+            .await?;
+        assert_eq!(trade.trade_state, TradeState::NotPay);
+        assert_eq!(trade.sp_mchid, "1900000001");
+        assert_eq!(trade.sub_appid.as_deref(), Some("wxd678efh567hg6999"));
+        // 订单不属于请求的子商户
+        let err = client
+            .partner_query_trade_by_out_trade_no("1900000110", "partner_1")
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ErrorCode::OrderNotExist));
+        let req = server.requests().pop().unwrap();
+        assert_eq!(
+            req.uri().query(),
+            Some("sp_mchid=1900000001&sub_mchid=1900000110")
+        );
+
+        server.pay("partner_1")?;
+        let notification = client
+            .handle_partner_notification(server.trade_notification("partner_1")?)
+            .await?;
+        let PartnerNotificationEvent::Trade(trade) = notification.event else {
+            panic!("unexpected event: {:?}", notification.event);
+        };
+        assert_eq!(trade.trade_state, TradeState::Success);
+        assert_eq!(trade.sub_mchid, "1900000109");
+        assert_eq!(
+            trade.payer.unwrap().sub_openid.as_deref(),
+            Some("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o")
+        );
+        let transaction_id = trade.transaction_id.unwrap();
+        let trade = client
+            .partner_query_trade_by_transaction_id("1900000109", &transaction_id)
+            .await?;
+        assert_eq!(trade.amount.unwrap().payer_total, Some(100));
+
+        let params = PartnerRefundParams {
+            sub_mchid: "1900000109".to_string(),
+            refund: refund_params("partner_1", "partner_refund_1", 30),
+        };
+        let refund = client.partner_apply_refund(&params).await?;
+        assert_eq!(refund.status, RefundStatus::Processing);
+        let body: Value = serde_json::from_slice(server.requests().last().unwrap().body())?;
+        assert_eq!(body["sub_mchid"], "1900000109");
+        assert_eq!(body["out_trade_no"], "partner_1");
+        server.complete_refund("partner_refund_1", RefundStatus::Success)?;
+        let refund = client
+            .partner_query_refund("1900000109", "partner_refund_1")
+            .await?;
+        assert_eq!(refund.status, RefundStatus::Success);
+        let notification = client
+            .handle_partner_notification(server.refund_notification("partner_refund_1")?)
+            .await?;
+        assert_eq!(notification.event_type, EventType::RefundSuccess);
+        let PartnerNotificationEvent::Refund(refund) = notification.event else {
+            panic!("unexpected event: {:?}", notification.event);
+        };
+        assert_eq!(refund.sp_mchid, "1900000001");
+        assert_eq!(refund.sub_mchid, "1900000109");
+        assert_eq!(refund.amount.refund, 30);
+
+        // 传入 sub_openid 时必须传入 sub_appid，下单前即校验失败
+        let mut params = partner_jsapi_params("partner_2");
+        params.sub_appid = None;
+        let err = client
+            .partner_jsapi_create_trade(&params)
+            .await
+            .unwrap_err();
+        let Error::Validation(errors) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert_eq!(errors.get("sub_appid"), Some(&ValidationReason::Missing));
+        params.payer = PartnerPayer::sp_openid("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o".to_string());
+        client.partner_jsapi_create_trade(&params).await?;
+        client
+            .partner_close_trade("1900000109", "partner_2")
+            .await?;
+        let trade = client
+            .partner_query_trade_by_out_trade_no("1900000109", "partner_2")
+            .await?;
+        assert_eq!(trade.trade_state, TradeState::Closed);
+        assert_eq!(
+            trade.payer.unwrap().sp_openid.as_deref(),
+            Some("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partner_create_trade_validation() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+        let requests = server.requests().len();
+
+        let mut params = partner_jsapi_params("订单");
+        params.sub_mchid = String::new();
+        params.description = "商品".repeat(64);
+        params.attach = Some("a".repeat(129));
+        params.notify_url = format!("{NOTIFY_URL}?a=1");
+        params.amount = Amount::new_with_cny(0);
+        params.payer = PartnerPayer {
+            sp_openid: None,
+            sub_openid: None,
+        };
+        let err = client
+            .partner_jsapi_create_trade(&params)
+            .await
+            .unwrap_err();
+        let Error::Validation(errors) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert_eq!(errors.get("sub_mchid"), Some(&ValidationReason::Missing));
+        assert_eq!(errors.get("payer"), Some(&ValidationReason::Missing));
+        assert_eq!(
+            errors.get("description"),
+            Some(&ValidationReason::Length {
+                min: 1,
+                max: 127,
+                actual: 128
+            })
+        );
+        assert_eq!(
+            errors.get("out_trade_no"),
+            Some(&ValidationReason::InvalidChar('订'))
+        );
+        assert_eq!(
+            errors.get("attach"),
+            Some(&ValidationReason::TooManyBytes {
+                max: 128,
+                actual: 129
+            })
+        );
+        assert_eq!(
+            errors.get("notify_url"),
+            Some(&ValidationReason::UrlHasQuery)
+        );
+        assert_eq!(
+            errors.get("amount.total"),
+            Some(&ValidationReason::NotPositive(0))
+        );
+        // 校验失败的参数不会发出请求
+        assert_eq!(server.requests().len(), requests);
+        Ok(())
+    }
+}
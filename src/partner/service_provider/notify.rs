@@ -0,0 +1,76 @@
+//! 服务商模式的支付、退款通知。
+
+use super::trade::PartnerTradeQueryResponse;
+use crate::error::Result;
+use crate::notify::{EventType, RefundNotifyAmount, WechatPayNotification};
+use crate::refund::RefundStatus;
+use crate::util::option_datetime_fmt;
+use crate::WechatPayClient;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// 解密服务商模式的支付、退款通知。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_1_5.shtml>
+pub(super) fn decrypt_notification(
+    wxpay: &WechatPayClient,
+    notify: &WechatPayNotification,
+) -> Result<PartnerNotificationEvent> {
+    let plain = wxpay.merchant.aes_decrypt(
+        &notify.resource.ciphertext,
+        &notify.resource.associated_data,
+        &notify.resource.nonce,
+    )?;
+
+    let event_type = &notify.event_type;
+    let original_type = notify.resource.original_type.as_str();
+    let event = if *event_type == EventType::TransactionSuccess || original_type == "transaction" {
+        PartnerNotificationEvent::Trade(serde_json::from_slice(&plain)?)
+    } else if event_type.is_refund() || original_type == "refund" {
+        PartnerNotificationEvent::Refund(serde_json::from_slice(&plain)?)
+    } else {
+        // 新增的通知类型不应导致回调处理失败。
+        PartnerNotificationEvent::Other(serde_json::from_slice(&plain)?)
+    };
+    Ok(event)
+}
+
+/// 解密后的服务商模式通知资源。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PartnerNotificationEvent {
+    /// 支付成功通知。
+    Trade(PartnerTradeQueryResponse),
+    /// 退款成功、异常或关闭通知。
+    Refund(PartnerRefundNotifyResponse),
+    /// 其他通知，资源解密后不做解析。
+    Other(serde_json::Value),
+}
+
+/// 服务商模式退款通知资源解密后的数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartnerRefundNotifyResponse {
+    /// 服务商户号
+    pub sp_mchid: String,
+    /// 子商户号
+    pub sub_mchid: String,
+    /// 商户订单号。不超过 32 字符。
+    pub out_trade_no: String,
+    /// 微信支付订单号。不超过 32 字符。
+    pub transaction_id: String,
+    /// 商户退款单号
+    pub out_refund_no: String,
+    /// 微信支付退款单号。不超过 32 字符。
+    pub refund_id: String,
+    /// 退款入账账户。不超过 64 字符。
+    pub user_received_account: String,
+    /// 退款成功时间，当退款状态为退款成功时有返回。
+    #[serde(
+        with = "option_datetime_fmt",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub success_time: Option<DateTime<Local>>,
+    /// 退款状态。
+    pub refund_status: RefundStatus,
+    /// 金额详细信息
+    pub amount: RefundNotifyAmount,
+}
@@ -0,0 +1,48 @@
+//! 服务商模式的退款。与直连商户使用相同的接口，需指定子商户号。
+
+use crate::error::Result;
+use crate::refund::{RefundParams, RefundQueryResponse};
+use crate::WechatPayClient;
+use serde::{Deserialize, Serialize};
+
+/// 为子商户申请退款。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_1_9.shtml>
+pub(super) async fn apply_refund(
+    wxpay: &WechatPayClient,
+    params: &PartnerRefundParams,
+) -> Result<RefundQueryResponse> {
+    let url = format!("{}/refund/domestic/refunds", wxpay.v3_url());
+    let req = wxpay.client.post(&url).json(params).build()?;
+    let res = wxpay.execute(req, None).await?;
+    let res: RefundQueryResponse = res.json().await?;
+    Ok(res)
+}
+
+/// 查询子商户的退款。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_1_10.shtml>
+pub(super) async fn query_refund(
+    wxpay: &WechatPayClient,
+    sub_mchid: &str,
+    out_refund_no: &str,
+) -> Result<RefundQueryResponse> {
+    let url = format!(
+        "{}/refund/domestic/refunds/{}?sub_mchid={}",
+        wxpay.v3_url(),
+        out_refund_no,
+        sub_mchid
+    );
+    let req = wxpay.client.get(url).build()?;
+    let res = wxpay.execute(req, None).await?;
+    let res: RefundQueryResponse = res.json().await?;
+    Ok(res)
+}
+
+/// 服务商模式申请退款的参数。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartnerRefundParams {
+    /// 子商户号
+    pub sub_mchid: String,
+    /// 其余参数与直连商户相同
+    #[serde(flatten)]
+    pub refund: RefundParams,
+}
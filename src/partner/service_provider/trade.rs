@@ -0,0 +1,522 @@
+//! 服务商模式的下单、查询及关闭订单。
+
+use crate::error::Result;
+use crate::trade::{
+    Amount, CreateTradeFields, CreateTradePromotionDetail, CreateTradeSceneInfo, PaidAmount,
+    SettleInfo, TradePromotionDetail, TradeSceneInfo, TradeState, TradeType,
+};
+use crate::util::option_datetime_fmt;
+use crate::WechatPayClient;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// JSAPI 下单，返回 prepay_id。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_1_1.shtml>
+pub(super) async fn jsapi_create_trade(
+    wxpay: &WechatPayClient,
+    params: &PartnerJsApiCreateTradeParams,
+) -> Result<String> {
+    let res: PrepayResponse = create_trade(wxpay, "jsapi", params).await?;
+    Ok(res.prepay_id)
+}
+
+/// APP 下单，返回 prepay_id。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_2_1.shtml>
+pub(super) async fn app_create_trade(
+    wxpay: &WechatPayClient,
+    params: &PartnerAppCreateTradeParams,
+) -> Result<String> {
+    let res: PrepayResponse = create_trade(wxpay, "app", params).await?;
+    Ok(res.prepay_id)
+}
+
+/// H5 下单，返回 h5_url。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_3_1.shtml>
+pub(super) async fn h5_create_trade(
+    wxpay: &WechatPayClient,
+    params: &PartnerH5CreateTradeParams,
+) -> Result<String> {
+    let res: H5CreateTradeResponse = create_trade(wxpay, "h5", params).await?;
+    Ok(res.h5_url)
+}
+
+/// Native 下单，返回二维码 url (code_url)。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_4_1.shtml>
+pub(super) async fn native_create_trade(
+    wxpay: &WechatPayClient,
+    params: &PartnerNativeCreateTradeParams,
+) -> Result<String> {
+    let res: NativeCreateTradeResponse = create_trade(wxpay, "native", params).await?;
+    Ok(res.code_url)
+}
+
+async fn create_trade<P, R>(wxpay: &WechatPayClient, kind: &str, params: &P) -> Result<R>
+where
+    P: PartnerCreateTradeParams,
+    R: serde::de::DeserializeOwned,
+{
+    params.fields().validate().into_result()?;
+    let url = format!("{}/pay/partner/transactions/{}", wxpay.v3_url(), kind);
+    let req = wxpay.client.post(url).json(params).build()?;
+    let res = wxpay.execute(req, None).await?;
+    let res = res.json().await?;
+    Ok(res)
+}
+
+/// 通过微信支付订单号(transaction_id)查询子商户的订单。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_1_2.shtml>
+pub(super) async fn query_trade_by_transaction_id(
+    wxpay: &WechatPayClient,
+    sub_mchid: &str,
+    transaction_id: &str,
+) -> Result<PartnerTradeQueryResponse> {
+    let url = format!(
+        "{}/pay/partner/transactions/id/{}?sp_mchid={}&sub_mchid={}",
+        wxpay.v3_url(),
+        transaction_id,
+        &wxpay.merchant.mch_id,
+        sub_mchid
+    );
+    let req = wxpay.client.get(url).build()?;
+    let res = wxpay.execute(req, None).await?;
+    let res: PartnerTradeQueryResponse = res.json().await?;
+    Ok(res)
+}
+
+/// 通过商户订单号(out_trade_no)查询子商户的订单。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_1_2.shtml>
+pub(super) async fn query_trade_by_out_trade_no(
+    wxpay: &WechatPayClient,
+    sub_mchid: &str,
+    out_trade_no: &str,
+) -> Result<PartnerTradeQueryResponse> {
+    let url = format!(
+        "{}/pay/partner/transactions/out-trade-no/{}?sp_mchid={}&sub_mchid={}",
+        wxpay.v3_url(),
+        out_trade_no,
+        &wxpay.merchant.mch_id,
+        sub_mchid
+    );
+    let req = wxpay.client.get(url).build()?;
+    let res = wxpay.execute(req, None).await?;
+    let res: PartnerTradeQueryResponse = res.json().await?;
+    Ok(res)
+}
+
+/// 关闭子商户的订单。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3_partner/apis/chapter4_1_3.shtml>
+pub(super) async fn close_trade(
+    wxpay: &WechatPayClient,
+    sub_mchid: &str,
+    out_trade_no: &str,
+) -> Result<()> {
+    #[derive(Debug, Clone, Serialize)]
+    struct CloseTradeRequest<'a> {
+        sp_mchid: &'a str,
+        sub_mchid: &'a str,
+    }
+
+    let url = format!(
+        "{}/pay/partner/transactions/out-trade-no/{}/close",
+        wxpay.v3_url(),
+        out_trade_no
+    );
+    let req = CloseTradeRequest {
+        sp_mchid: &wxpay.merchant.mch_id,
+        sub_mchid,
+    };
+    let req = wxpay.client.post(url).json(&req).build()?;
+    let _res = wxpay.execute(req, None).await?;
+    Ok(())
+}
+
+/// JSAPI、APP 下单响应。
+#[derive(Debug, Clone, Deserialize)]
+struct PrepayResponse {
+    prepay_id: String,
+}
+
+/// H5 下单响应。
+#[derive(Debug, Clone, Deserialize)]
+struct H5CreateTradeResponse {
+    h5_url: String,
+}
+
+/// Native 下单响应
+#[derive(Debug, Clone, Deserialize)]
+struct NativeCreateTradeResponse {
+    /// 此URL用于生成支付二维码，然后提供给用户扫码支付。
+    code_url: String,
+}
+
+/// 服务商模式的支付者。`sp_openid` 与 `sub_openid` 二选一。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PartnerPayer {
+    /// 用户在服务商 sp_appid 下的唯一标识。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sp_openid: Option<String>,
+    /// 用户在子商户 sub_appid 下的唯一标识。下单时传入 sub_openid 则必须传入 sub_appid。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sub_openid: Option<String>,
+}
+
+impl PartnerPayer {
+    /// 服务商 sp_appid 下的支付者。
+    pub fn sp_openid(openid: String) -> PartnerPayer {
+        PartnerPayer {
+            sp_openid: Some(openid),
+            sub_openid: None,
+        }
+    }
+
+    /// 子商户 sub_appid 下的支付者。
+    pub fn sub_openid(openid: String) -> PartnerPayer {
+        PartnerPayer {
+            sp_openid: None,
+            sub_openid: Some(openid),
+        }
+    }
+}
+
+/// 服务商模式 JSAPI 下单参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartnerJsApiCreateTradeParams {
+    /// 服务商应用 ID
+    pub sp_appid: String,
+    /// 服务商户号
+    pub sp_mchid: String,
+    /// 子商户应用 ID。使用 sub_openid 时必填。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sub_appid: Option<String>,
+    /// 子商户号
+    pub sub_mchid: String,
+    /// 商品描述。不超过 127 字符。
+    pub description: String,
+    /// 商户订单号。子商户系统内部订单号，需在同一个子商户号下唯一。只能是数字、大小写字母_-*组成
+    /// 长度应在 [6, 32] 字符之间
+    pub out_trade_no: String,
+    /// 订单失效时间
+    #[serde(with = "option_datetime_fmt", skip_serializing_if = "Option::is_none")]
+    pub time_expire: Option<DateTime<Local>>,
+    /// 附加数据，在查询API和支付通知中原样返回，可作为自定义参数使用，实际情况下只有支付完成状态才会返回该字段。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub attach: Option<String>,
+    /// 接收微信支付结果通知的回调地址，通知url必须为外网可访问的url，不能携带参数。
+    pub notify_url: String,
+    /// 订单优惠标记
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub goods_tag: Option<String>,
+    /// 电子发票入口开放标识。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub support_fapiao: Option<bool>,
+    /// 订单金额
+    pub amount: Amount,
+    /// 支付者
+    pub payer: PartnerPayer,
+    /// 优惠功能
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub detail: Option<CreateTradePromotionDetail>,
+    /// 场景信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scene_info: Option<CreateTradeSceneInfo>,
+    /// 结算信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub settle_info: Option<SettleInfo>,
+}
+
+/// 服务商模式 APP 下单参数。
+/// 相比 PartnerJsApiCreateTradeParams 少了 payer 字段
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartnerAppCreateTradeParams {
+    /// 服务商应用 ID
+    pub sp_appid: String,
+    /// 服务商户号
+    pub sp_mchid: String,
+    /// 子商户应用 ID
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sub_appid: Option<String>,
+    /// 子商户号
+    pub sub_mchid: String,
+    /// 商品描述。不超过 127 字符。
+    pub description: String,
+    /// 商户订单号。子商户系统内部订单号，需在同一个子商户号下唯一。只能是数字、大小写字母_-*组成
+    /// 长度应在 [6, 32] 字符之间
+    pub out_trade_no: String,
+    /// 订单失效时间
+    #[serde(with = "option_datetime_fmt", skip_serializing_if = "Option::is_none")]
+    pub time_expire: Option<DateTime<Local>>,
+    /// 附加数据，在查询API和支付通知中原样返回，可作为自定义参数使用，实际情况下只有支付完成状态才会返回该字段。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub attach: Option<String>,
+    /// 接收微信支付结果通知的回调地址，通知url必须为外网可访问的url，不能携带参数。
+    pub notify_url: String,
+    /// 订单优惠标记
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub goods_tag: Option<String>,
+    /// 电子发票入口开放标识。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub support_fapiao: Option<bool>,
+    /// 订单金额
+    pub amount: Amount,
+    /// 优惠功能
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub detail: Option<CreateTradePromotionDetail>,
+    /// 场景信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scene_info: Option<CreateTradeSceneInfo>,
+    /// 结算信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub settle_info: Option<SettleInfo>,
+}
+
+/// 服务商模式 H5 下单参数。
+/// 相比 PartnerJsApiCreateTradeParams 少了 payer 字段
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartnerH5CreateTradeParams {
+    /// 服务商应用 ID
+    pub sp_appid: String,
+    /// 服务商户号
+    pub sp_mchid: String,
+    /// 子商户应用 ID
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sub_appid: Option<String>,
+    /// 子商户号
+    pub sub_mchid: String,
+    /// 商品描述。不超过 127 字符。
+    pub description: String,
+    /// 商户订单号。子商户系统内部订单号，需在同一个子商户号下唯一。只能是数字、大小写字母_-*组成
+    /// 长度应在 [6, 32] 字符之间
+    pub out_trade_no: String,
+    /// 订单失效时间
+    #[serde(with = "option_datetime_fmt", skip_serializing_if = "Option::is_none")]
+    pub time_expire: Option<DateTime<Local>>,
+    /// 附加数据，在查询API和支付通知中原样返回，可作为自定义参数使用，实际情况下只有支付完成状态才会返回该字段。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub attach: Option<String>,
+    /// 接收微信支付结果通知的回调地址，通知url必须为外网可访问的url，不能携带参数。
+    pub notify_url: String,
+    /// 订单优惠标记
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub goods_tag: Option<String>,
+    /// 电子发票入口开放标识。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub support_fapiao: Option<bool>,
+    /// 订单金额
+    pub amount: Amount,
+    /// 优惠功能
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub detail: Option<CreateTradePromotionDetail>,
+    /// 场景信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scene_info: Option<CreateTradeSceneInfo>,
+    /// 结算信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub settle_info: Option<SettleInfo>,
+}
+
+/// 服务商模式 Native 下单参数。
+/// 相比 PartnerJsApiCreateTradeParams 少了 payer 字段
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartnerNativeCreateTradeParams {
+    /// 服务商应用 ID
+    pub sp_appid: String,
+    /// 服务商户号
+    pub sp_mchid: String,
+    /// 子商户应用 ID
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sub_appid: Option<String>,
+    /// 子商户号
+    pub sub_mchid: String,
+    /// 商品描述。不超过 127 字符。
+    pub description: String,
+    /// 商户订单号。子商户系统内部订单号，需在同一个子商户号下唯一。只能是数字、大小写字母_-*组成
+    /// 长度应在 [6, 32] 字符之间
+    pub out_trade_no: String,
+    /// 订单失效时间
+    #[serde(with = "option_datetime_fmt", skip_serializing_if = "Option::is_none")]
+    pub time_expire: Option<DateTime<Local>>,
+    /// 附加数据，在查询API和支付通知中原样返回，可作为自定义参数使用，实际情况下只有支付完成状态才会返回该字段。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub attach: Option<String>,
+    /// 接收微信支付结果通知的回调地址，通知url必须为外网可访问的url，不能携带参数。
+    pub notify_url: String,
+    /// 订单优惠标记
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub goods_tag: Option<String>,
+    /// 电子发票入口开放标识。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub support_fapiao: Option<bool>,
+    /// 订单金额
+    pub amount: Amount,
+    /// 优惠功能
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub detail: Option<CreateTradePromotionDetail>,
+    /// 场景信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scene_info: Option<CreateTradeSceneInfo>,
+    /// 结算信息
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub settle_info: Option<SettleInfo>,
+}
+
+/// 可以服务商模式下单的参数。
+trait PartnerCreateTradeParams: Serialize {
+    /// 需要校验的字段。
+    fn fields(&self) -> CreateTradeFields<'_>;
+}
+
+impl PartnerJsApiCreateTradeParams {
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+impl PartnerCreateTradeParams for PartnerJsApiCreateTradeParams {
+    fn fields(&self) -> CreateTradeFields<'_> {
+        let mut required = vec![
+            ("sp_appid", Some(self.sp_appid.as_str())),
+            ("sp_mchid", Some(&self.sp_mchid)),
+            ("sub_mchid", Some(&self.sub_mchid)),
+        ];
+        let payer = &self.payer;
+        required.push((
+            "payer",
+            payer.sp_openid.as_deref().or(payer.sub_openid.as_deref()),
+        ));
+        if payer.sub_openid.is_some() {
+            required.push(("sub_appid", self.sub_appid.as_deref()));
+        }
+        CreateTradeFields {
+            required,
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+impl PartnerAppCreateTradeParams {
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+impl PartnerCreateTradeParams for PartnerAppCreateTradeParams {
+    fn fields(&self) -> CreateTradeFields<'_> {
+        CreateTradeFields {
+            required: vec![
+                ("sp_appid", Some(&self.sp_appid)),
+                ("sp_mchid", Some(&self.sp_mchid)),
+                ("sub_mchid", Some(&self.sub_mchid)),
+            ],
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+impl PartnerH5CreateTradeParams {
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+impl PartnerCreateTradeParams for PartnerH5CreateTradeParams {
+    fn fields(&self) -> CreateTradeFields<'_> {
+        CreateTradeFields {
+            required: vec![
+                ("sp_appid", Some(&self.sp_appid)),
+                ("sp_mchid", Some(&self.sp_mchid)),
+                ("sub_mchid", Some(&self.sub_mchid)),
+            ],
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+impl PartnerNativeCreateTradeParams {
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+impl PartnerCreateTradeParams for PartnerNativeCreateTradeParams {
+    fn fields(&self) -> CreateTradeFields<'_> {
+        CreateTradeFields {
+            required: vec![
+                ("sp_appid", Some(&self.sp_appid)),
+                ("sp_mchid", Some(&self.sp_mchid)),
+                ("sub_mchid", Some(&self.sub_mchid)),
+            ],
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+/// 服务商模式订单查询响应，同时也是支付成功通知解密后的数据。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartnerTradeQueryResponse {
+    /// 服务商应用 ID
+    pub sp_appid: String,
+    /// 服务商户号
+    pub sp_mchid: String,
+    /// 子商户应用 ID
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sub_appid: Option<String>,
+    /// 子商户号
+    pub sub_mchid: String,
+    /// 商户订单号
+    pub out_trade_no: String,
+    /// 微信支付订单号。不超过 32 字符。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub transaction_id: Option<String>,
+    /// 交易类型
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub trade_type: Option<TradeType>,
+    /// 交易状态
+    pub trade_state: TradeState,
+    /// 交易状态描述
+    pub trade_state_desc: String,
+    /// 付款银行
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub bank_type: Option<String>,
+    /// 附加数据
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub attach: Option<String>,
+    /// 支付完成时间。
+    #[serde(
+        with = "option_datetime_fmt",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub success_time: Option<DateTime<Local>>,
+    /// 支付者
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub payer: Option<PartnerPayer>,
+    /// 订单金额信息，当支付成功时返回该字段。
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub amount: Option<PaidAmount>,
+    /// 场景信息，支付场景描述
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scene_info: Option<TradeSceneInfo>,
+    /// 优惠功能，享受优惠时返回该字段
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub promotion_detail: Vec<TradePromotionDetail>,
+}
@@ -1,12 +1,14 @@
 pub mod applyment;
+pub mod bill;
 pub mod combine_trade;
 pub mod fund_balance;
 pub mod fund_withdraw;
 pub mod profit_sharing;
 pub mod refund;
 
-use crate::{notify::WechatPayNotification, trade::JsApiTradeSignature, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::notify::{EventType, WechatPayNotification};
+use crate::{trade::JsApiTradeSignature, WechatPayClient};
 use applyment::{
     apply_query::ApplymentQueryResponse,
     utils::{PersonalBankingResponse, UploadResponse},
@@ -36,22 +38,23 @@ fn decrypt_notification(
     wxpay: &WechatPayClient,
     notify: &WechatPayNotification,
 ) -> Result<NotificationEvent> {
-    let plain = wxpay.mch_credential.aes_decrypt(
+    let plain = wxpay.merchant.aes_decrypt(
         &notify.resource.ciphertext,
         &notify.resource.associated_data,
         &notify.resource.nonce,
     )?;
 
-    let event = match notify.resource.original_type.as_str() {
-        "transaction" => NotificationEvent::Trade(serde_json::from_slice(&plain)?),
-        "refund" => NotificationEvent::Refund(serde_json::from_slice(&plain)?),
-        "profitsharing" => NotificationEvent::ProfitShare(serde_json::from_slice(&plain)?),
-        _ => {
-            return Err(anyhow::anyhow!(
-                "unknown notification type: {}",
-                notify.resource.original_type
-            ));
-        }
+    let event_type = &notify.event_type;
+    let original_type = notify.resource.original_type.as_str();
+    let event = if *event_type == EventType::TransactionSuccess || original_type == "transaction" {
+        NotificationEvent::Trade(serde_json::from_slice(&plain)?)
+    } else if event_type.is_refund() || original_type == "refund" {
+        NotificationEvent::Refund(serde_json::from_slice(&plain)?)
+    } else if event_type.is_profit_sharing() || original_type == "profitsharing" {
+        NotificationEvent::ProfitShare(serde_json::from_slice(&plain)?)
+    } else {
+        // 新增的通知类型不应导致回调处理失败。
+        NotificationEvent::Other(serde_json::from_slice(&plain)?)
     };
     Ok(event)
 }
@@ -61,6 +64,8 @@ pub enum NotificationEvent {
     Trade(TradeNotifyData),
     Refund(RefundNotifyData),
     ProfitShare(ProfitShareNotifyData),
+    /// 其他通知，如进件、提现、投诉等，资源解密后不做解析。
+    Other(serde_json::Value),
 }
 
 #[async_trait]
@@ -114,7 +119,7 @@ pub trait ShouFuTong {
 
     fn encrypt(&self, data: &str) -> Result<String>;
 
-    fn sign_jsapi_trade(&self, prepay_id: &str, app_id: &str) -> JsApiTradeSignature;
+    fn sign_jsapi_trade(&self, prepay_id: &str, app_id: &str) -> Result<JsApiTradeSignature>;
 
     fn get_mch_id(&self) -> &str;
 }
@@ -197,14 +202,14 @@ impl ShouFuTong for WechatPayClient {
     }
 
     fn encrypt(&self, data: &str) -> Result<String> {
-        self.platform_certificate.encrypt(data.as_bytes())
+        WechatPayClient::encrypt(self, data)
     }
 
-    fn sign_jsapi_trade(&self, prepay_id: &str, app_id: &str) -> JsApiTradeSignature {
+    fn sign_jsapi_trade(&self, prepay_id: &str, app_id: &str) -> Result<JsApiTradeSignature> {
         self.sign_jsapi_trade(prepay_id, app_id)
     }
 
     fn get_mch_id(&self) -> &str {
-        &self.mch_credential.mch_id
+        &self.merchant.mch_id
     }
 }
@@ -3,8 +3,9 @@ pub mod apply_query;
 pub mod settlement;
 pub mod utils;
 
-use crate::client::{WechatPayClient, BASE_URL};
-use anyhow::Result;
+use crate::client::WechatPayClient;
+use crate::error::Result;
+use crate::sensitive::SensitiveString;
 use serde::{Deserialize, Serialize};
 
 pub use apply_query::query_applyment_by_out_request_no;
@@ -18,9 +19,9 @@ pub(super) async fn submit(
     sub_merchant: &SubMerchantApplication,
 ) -> Result<ApplymentResponse> {
     let url = "ecommerce/applyments/";
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
-    let req = wxpay.client.post(&url).json(sub_merchant).build()?;
+    let req = wxpay.json_request(wxpay.client.post(&url), sub_merchant)?;
     let res = wxpay.execute(req, None).await?;
     let res = res.json().await?;
     Ok(res)
@@ -138,7 +139,7 @@ pub struct ContactInfo {
     /// 1、若管理员类型为“法人”，则该姓名需与法人身份证姓名一致。
     /// 2、若管理员类型为“经办人”，则可填写实际负责人的姓名。
     /// ... (其他约束)
-    pub contact_name: String,
+    pub contact_name: SensitiveString,
 
     /// 超级管理员证件类型
     /// 当超级管理员类型是经办人时，请上传超级管理员证件类型。
@@ -149,7 +150,7 @@ pub struct ContactInfo {
     /// 1、若超级管理员类型为法人，则该身份证号码需与法人身份证号码一致。若超级管理员类型为经办人，则可填写实际经办人的身份证号码。
     /// ... (其他约束)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub contact_id_card_number: Option<String>,
+    pub contact_id_card_number: Option<SensitiveString>,
 
     /// 超级管理员证件正面照片
     /// 1、当超级管理员类型是经办人时，请上传超级管理员证件的正面照片。
@@ -184,7 +185,7 @@ pub struct ContactInfo {
     /// 超级管理员手机
     /// 1、前后不能有空格、制表符、换行符
     /// ... (其他约束)
-    pub mobile_phone: String,
+    pub mobile_phone: SensitiveString,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -204,7 +205,7 @@ pub struct AccountInfo {
     /// 1、选择经营者个人银行卡时，开户名称必须与身份证姓名一致。
     /// 2、选择对公账户时，开户名称必须与营业执照上的“商户名称”一致。
     /// 3、该字段需要使用微信支付公钥加密（推荐），请参考获取微信支付公钥ID说明以及微信支付公钥加密敏感信息指引，也可以使用微信支付平台证书公钥加密，参考获取平台证书序列号、平台证书加密敏感信息指引
-    pub account_name: String,
+    pub account_name: SensitiveString,
 
     /// 开户银行省市编码
     /// 至少精确到市，详细参见省市区编号对照表。
@@ -226,7 +227,7 @@ pub struct AccountInfo {
     /// 银行账号
     /// 1、数字，长度遵循系统支持的对公/对私卡号长度要求表。
     /// 2、该字段需要使用微信支付公钥加密（推荐），请参考获取微信支付公钥ID说明以及微信支付公钥加密敏感信息指引，也可以使用微信支付平台证书公钥加密，参考获取平台证书序列号、平台证书加密敏感信息指引
-    pub account_number: String,
+    pub account_number: SensitiveString,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -267,7 +268,7 @@ pub struct UboInfo {
     /// 3、不能仅含数字、特殊字符
     /// 4、仅能填写数字、英文字母、汉字及特殊字符
     /// 5、该字段需要使用微信支付公钥加密（推荐），请参考获取微信支付公钥ID说明以及微信支付公钥加密敏感信息指引，也可以使用微信支付平台证书公钥加密，参考获取平台证书序列号、平台证书加密敏感信息指引
-    pub ubo_id_doc_name: Option<String>,
+    pub ubo_id_doc_name: Option<SensitiveString>,
 
     /// 证件号码
     /// 1、可传身份证、来往内地通行证、来往大陆通行证、护照等证件号码，号码规范如下：
@@ -279,7 +280,7 @@ pub struct UboInfo {
     /// 外国人居留证：15位 数字|字母
     /// 港澳居住证/台湾居住证：17位数字+1位数字|X
     /// 2、该字段需要使用微信支付公钥加密（推荐），请参考获取微信支付公钥ID说明以及微信支付公钥加密敏感信息指引，也可以使用微信支付平台证书公钥加密，参考获取平台证书序列号、平台证书加密敏感信息指引
-    pub ubo_id_doc_number: Option<String>,
+    pub ubo_id_doc_number: Option<SensitiveString>,
 
     /// 证件居住地址
     /// 1、请按照身份证住址填写，如广东省深圳市南山区xx路xx号xx室
@@ -289,7 +290,7 @@ pub struct UboInfo {
     /// 5、仅能填写数字、英文字母、汉字及特殊字符
     /// 6、仅支持utf-8格式
     /// 7、 该字段需要使用微信支付公钥加密（推荐），请参考获取微信支付公钥ID说明以及微信支付公钥加密敏感信息指引，也可以使用微信支付平台证书公钥加密，参考获取平台证书序列号、平台证书加密敏感信息指引
-    pub ubo_id_doc_address: Option<String>,
+    pub ubo_id_doc_address: Option<SensitiveString>,
 
     /// 证件有效期开始时间
     /// 1、日期格式应满足合法的YYYY-MM-DD格式
@@ -326,7 +327,7 @@ pub struct IdDocInfo {
     /// 4、不能仅含数字、特殊字符
     /// 5、仅能填写数字、英文字母、汉字及特殊字符
     /// 6、该字段需要使用微信支付公钥加密（推荐），请参考获取微信支付公钥ID说明以及微信支付公钥加密敏感信息指引，也可以使用微信支付平台证书公钥加密，参考获取平台证书序列号、平台证书加密敏感信息指引
-    pub id_doc_name: String,
+    pub id_doc_name: SensitiveString,
 
     /// 证件号码
     /// 1、请填写经营者/法定代表人的证件号码。
@@ -337,7 +338,7 @@ pub struct IdDocInfo {
     /// 外国人居留证：15位 数字|字母
     /// 港澳居住证/台湾居住证：17位数字+1位数字|X
     /// 2、该字段需要使用微信支付公钥加密（推荐），请参考获取微信支付公钥ID说明以及微信支付公钥加密敏感信息指引，也可以使用微信支付平台证书公钥加密，参考获取平台证书序列号、平台证书加密敏感信息指引
-    pub id_doc_number: String,
+    pub id_doc_number: SensitiveString,
 
     /// 证件正面照片
     /// 1、证件类型不为“身份证”时，上传证件正面照片。
@@ -384,13 +385,13 @@ pub struct IdCardInfo {
     /// 长度为2-100个字符，前后不能有空格、制表符、换行符。
     /// 不能仅含数字、特殊字符，仅能填写数字、英文字母、汉字及特殊字符。
     /// 该字段需要使用微信支付公钥加密或平台证书公钥加密。
-    pub id_card_name: String,
+    pub id_card_name: SensitiveString,
 
     /// 身份证号码
     /// 请填写经营者/法定代表人对应身份证的号码。
     /// 格式：7位数字+1位数字|X。
     /// 该字段需要使用微信支付公钥加密或平台证书公钥加密。
-    pub id_card_number: String,
+    pub id_card_number: SensitiveString,
 
     /// 身份证开始时间
     /// 日期格式应满足合法的YYYY-MM-DD格式。
@@ -1,5 +1,7 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::credential::Signer;
+use crate::error::Result;
+use crate::sensitive::{DecryptSensitive, EncryptedString};
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 通过业务申请编号查询申请状态
@@ -9,11 +11,12 @@ pub async fn query_applyment_by_out_request_no(
     out_request_no: &str,
 ) -> Result<ApplymentQueryResponse> {
     let url = "ecommerce/applyments/out-request-no";
-    let url = format!("{}/{}/{}", BASE_URL, url, out_request_no);
+    let url = format!("{}/{}/{}", wxpay.v3_url(), url, out_request_no);
 
     let req = wxpay.client.get(&url).build()?;
     let res = wxpay.execute(req, None).await?;
-    let res = res.json().await?;
+    let mut res: ApplymentQueryResponse = res.json().await?;
+    res.decrypt_sensitive(wxpay.merchant.signer.as_ref())?;
     Ok(res)
 }
 
@@ -24,11 +27,12 @@ pub(in crate::partner::shou_fu_tong) async fn query_applyment_by_applyment_id(
     applyment_id: u64,
 ) -> Result<ApplymentQueryResponse> {
     let url = "ecommerce/applyments";
-    let url = format!("{}/{}/{}", BASE_URL, url, applyment_id);
+    let url = format!("{}/{}/{}", wxpay.v3_url(), url, applyment_id);
 
     let req = wxpay.client.get(&url).build()?;
     let res = wxpay.execute(req, None).await?;
-    let res = res.json().await?;
+    let mut res: ApplymentQueryResponse = res.json().await?;
+    res.decrypt_sensitive(wxpay.merchant.signer.as_ref())?;
     Ok(res)
 }
 
@@ -49,8 +53,10 @@ pub struct ApplymentQueryResponse {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccountValidation {
-    pub account_name: String,
-    pub account_no: Option<String>,
+    /// 付款户名。已使用商户私钥解密。
+    pub account_name: EncryptedString,
+    /// 付款卡号。已使用商户私钥解密。
+    pub account_no: Option<EncryptedString>,
     pub pay_amount: u32,
     pub destination_account_number: String,
     pub destination_account_name: String,
@@ -60,6 +66,19 @@ pub struct AccountValidation {
     pub deadline: String,
 }
 
+impl DecryptSensitive for ApplymentQueryResponse {
+    fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()> {
+        self.account_validation.decrypt_sensitive(signer)
+    }
+}
+
+impl DecryptSensitive for AccountValidation {
+    fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()> {
+        self.account_name.decrypt_sensitive(signer)?;
+        self.account_no.decrypt_sensitive(signer)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuditDetail {
     pub param_name: String,
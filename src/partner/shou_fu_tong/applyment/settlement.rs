@@ -1,5 +1,7 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::credential::Signer;
+use crate::error::Result;
+use crate::sensitive::{DecryptSensitive, EncryptedString, SensitiveString};
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 查询结算账户
@@ -9,7 +11,7 @@ pub async fn query_settlement(
     sub_mchid: &str,
 ) -> Result<SettlementQueryResponse> {
     let url = format!("apply4sub/sub_merchants/{}/settlement", sub_mchid);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.get(&url).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -25,9 +27,9 @@ pub async fn modify_settlement(
     data: &SettlementModifyData,
 ) -> Result<SettlementModifyResponse> {
     let url = format!("apply4sub/sub_merchants/{}/modify-settlement", sub_mchid);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
-    let req = wxpay.client.post(&url).json(data).build()?;
+    let req = wxpay.json_request(wxpay.client.post(&url), data)?;
     let res = wxpay.execute(req, None).await?;
     let res = res.json().await?;
     Ok(res)
@@ -44,11 +46,12 @@ pub async fn query_settlement_modify(
         "apply4sub/sub_merchants/{}/application/{}",
         sub_mchid, application_no
     );
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.get(&url).build()?;
     let res = wxpay.execute(req, None).await?;
-    let res = res.json().await?;
+    let mut res: QuerySettlementModifyResponse = res.json().await?;
+    res.decrypt_sensitive(wxpay.merchant.signer.as_ref())?;
     Ok(res)
 }
 
@@ -62,9 +65,11 @@ pub struct SettlementModifyData {
     pub bank_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bank_branch_id: Option<String>,
-    pub account_number: String,
+    /// 银行账号。序列化时使用平台证书加密。
+    pub account_number: SensitiveString,
+    /// 开户名称。序列化时使用平台证书加密。
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub account_name: Option<String>,
+    pub account_name: Option<SensitiveString>,
 }
 /// 修改结算账号Response
 #[derive(Debug, Serialize, Deserialize)]
@@ -75,17 +80,26 @@ pub struct SettlementModifyResponse {
 /// 查询结算账户修改申请状态response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QuerySettlementModifyResponse {
-    pub account_name: String,
+    /// 开户名称。已使用商户私钥解密。
+    pub account_name: EncryptedString,
     pub account_type: String,
     pub account_bank: String,
     pub bank_name: Option<String>,
     pub bank_branch_id: Option<String>,
-    pub account_number: String,
+    /// 银行账号。已使用商户私钥解密。
+    pub account_number: EncryptedString,
     pub verify_result: String,
     pub verify_fail_reason: Option<String>,
     pub verify_finish_time: Option<String>,
 }
 
+impl DecryptSensitive for QuerySettlementModifyResponse {
+    fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()> {
+        self.account_name.decrypt_sensitive(signer)?;
+        self.account_number.decrypt_sensitive(signer)
+    }
+}
+
 /// 结算账号查询Response
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SettlementQueryResponse {
@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::{bail, Context, Result};
+use crate::error::{Error, Result};
+use crate::WechatPayClient;
 use http::{header::CONTENT_TYPE, HeaderMap};
 use reqwest::multipart::{Form, Part};
 use rsa::sha2::{Digest, Sha256};
@@ -16,16 +16,18 @@ pub(in crate::partner::shou_fu_tong) async fn upload_image(
 ) -> Result<UploadResponse> {
     const MAX_SIZE: usize = 2 * 1024 * 1024;
     if image.len() > MAX_SIZE {
-        bail!("image size too large");
+        return Err(Error::InvalidArgument("image size too large".to_string()));
     }
 
     // check image format is supported
-    let ext = filename
-        .split('.')
-        .last()
-        .context("Invalid filename, no extension found")?;
+    let ext = filename.split('.').next_back().ok_or_else(|| {
+        Error::InvalidArgument("Invalid filename, no extension found".to_string())
+    })?;
     if !is_supported_image(ext) {
-        bail!("Unsupported image format: {}", ext);
+        return Err(Error::InvalidArgument(format!(
+            "Unsupported image format: {}",
+            ext
+        )));
     }
 
     // calculate sha256
@@ -57,7 +59,7 @@ pub(in crate::partner::shou_fu_tong) async fn upload_image(
 
     let form = Form::new().part("meta", json_part).part("file", form_part);
 
-    let url = format!("{}/merchant/media/upload", BASE_URL);
+    let url = format!("{}/merchant/media/upload", wxpay.v3_url());
     let req = wxpay.client.post(&url).multipart(form).build()?;
     let res = wxpay.execute(req, Some(meta)).await?;
     let res: UploadResponse = res.json().await?;
@@ -72,8 +74,7 @@ pub(crate) async fn get_personal_banking(
     wxpay: &WechatPayClient,
     url: &str,
 ) -> Result<PersonalBankingResponse> {
-    let base_url = "https://api.mch.weixin.qq.com";
-    let url = format!("{}{}", base_url, url);
+    let url = format!("{}{}", wxpay.base_url(), url);
     let req = wxpay.client.get(&url).build()?;
     let res = wxpay.execute(req, None).await?;
     let res = res.json().await?;
@@ -0,0 +1,122 @@
+//! 二级商户资金账单。
+//! 与普通资金账单不同，账单文件使用 AEAD_AES_256_GCM 加密，
+//! 每个文件的密钥(使用商户公钥加密)及随机串在申请账单的响应中给出。
+
+use crate::bill::{self, FundFlowAccountType, FundFlowBill, TarType};
+use crate::credential::rsa_decrypt;
+use crate::error::{Error, Result};
+use crate::WechatPayClient;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use bytes::Bytes;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// 申请二级商户资金账单
+pub async fn sub_mch_fund_flow_bill(
+    wxpay: &WechatPayClient,
+    params: &SubMchFundFlowBillParams,
+) -> Result<SubMchFundFlowBillResponse> {
+    let url = format!("{}/ecommerce/bill/fundflowbill", wxpay.v3_url());
+    let mut query = vec![
+        ("sub_mchid", params.sub_mchid.clone()),
+        ("bill_date", params.bill_date.format("%Y-%m-%d").to_string()),
+        ("account_type", params.account_type.as_str().to_string()),
+        ("algorithm", "AEAD_AES_256_GCM".to_string()),
+    ];
+    if let Some(tar_type) = params.tar_type {
+        query.push(("tar_type", tar_type.as_str().to_string()));
+    }
+
+    let req = wxpay.client.get(url).query(&query).build()?;
+    let res = wxpay.execute(req, None).await?;
+    let res = res.json().await?;
+
+    Ok(res)
+}
+
+/// 下载一个加密的账单文件：解密、按需解压并校验摘要，返回账单原文。
+pub async fn download_encrypted_bill(
+    wxpay: &WechatPayClient,
+    info: &EncryptedBillDownloadInfo,
+    tar_type: Option<TarType>,
+) -> Result<Bytes> {
+    let file = wxpay.download_bill_file(&info.download_url).await?;
+    let key = rsa_decrypt(wxpay.merchant.signer.as_ref(), &info.encrypt_key)?;
+    let file = aes_decrypt_bill(key.as_bytes(), info.nonce.as_bytes(), &file)?;
+    let content = bill::decompress(file, tar_type)?;
+    bill::verify_hash(&content, &info.hash_type, &info.hash_value)?;
+    Ok(content)
+}
+
+/// 申请并下载二级商户资金账单。账单较大时分为多个文件，按 `bill_sequence` 顺序返回。
+pub async fn download_sub_mch_fund_flow_bill(
+    wxpay: &WechatPayClient,
+    params: &SubMchFundFlowBillParams,
+) -> Result<Vec<FundFlowBill>> {
+    let res = sub_mch_fund_flow_bill(wxpay, params).await?;
+    let mut list = res.download_bill_list;
+    list.sort_by_key(|info| info.bill_sequence);
+
+    let mut bills = Vec::with_capacity(list.len());
+    for info in &list {
+        let content = download_encrypted_bill(wxpay, info, params.tar_type).await?;
+        bills.push(FundFlowBill::from_bytes(content)?);
+    }
+    Ok(bills)
+}
+
+fn aes_decrypt_bill(key: &[u8], nonce: &[u8], file: &[u8]) -> Result<Bytes> {
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|_| Error::Decryption("bill encrypt_key must be 32 bytes".to_string()))?;
+    if nonce.len() != 12 {
+        return Err(Error::Decryption("bill nonce must be 12 bytes".to_string()));
+    }
+    let payload = Payload {
+        msg: file,
+        aad: b"",
+    };
+    let plain = cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|e| Error::Decryption(e.to_string()))?;
+    Ok(plain.into())
+}
+
+/// 申请二级商户资金账单的参数
+#[derive(Debug, Clone)]
+pub struct SubMchFundFlowBillParams {
+    /// 二级商户号
+    pub sub_mchid: String,
+    /// 账单日期
+    pub bill_date: NaiveDate,
+    /// 资金账户类型
+    pub account_type: FundFlowAccountType,
+    /// 压缩类型，不填时不压缩。
+    pub tar_type: Option<TarType>,
+}
+
+/// 申请二级商户资金账单的响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubMchFundFlowBillResponse {
+    /// 下载信息总数
+    pub download_bill_count: u32,
+    /// 下载信息明细
+    pub download_bill_list: Vec<EncryptedBillDownloadInfo>,
+}
+
+/// 加密账单文件的下载信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedBillDownloadInfo {
+    /// 账单文件序号，从 1 开始
+    pub bill_sequence: u32,
+    /// 下载地址，5 分钟内有效
+    pub download_url: String,
+    /// 加密账单文件的密钥，使用商户公钥加密后 base64 编码
+    pub encrypt_key: String,
+    /// 摘要算法，固定为 SHA1
+    pub hash_type: String,
+    /// 账单原文(解压后)的摘要
+    pub hash_value: String,
+    /// 加密账单文件的随机串
+    pub nonce: String,
+}
@@ -1,9 +1,9 @@
 pub mod mini_program_pay;
 pub mod notify;
 
+use crate::error::Result;
 use crate::util::option_datetime_fmt;
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::WechatPayClient;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DefaultOnError};
@@ -17,7 +17,7 @@ pub(super) async fn query_combine_order(
     combine_out_trade_no: &str,
 ) -> Result<CombineOrderQueryResponse> {
     let url = "combine-transactions/out-trade-no";
-    let url = format!("{}/{}/{}", BASE_URL, url, combine_out_trade_no);
+    let url = format!("{}/{}/{}", wxpay.v3_url(), url, combine_out_trade_no);
 
     let req = wxpay.client.get(url).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -37,7 +37,7 @@ pub(super) async fn close_combine_order(
         "combine-transactions/out-trade-no/{}/close",
         combine_out_trade_no
     );
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.post(url).json(data).build()?;
     let _res = wxpay.execute(req, None).await?;
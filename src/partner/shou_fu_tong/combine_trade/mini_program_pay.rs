@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 合单支付-小程序下单
@@ -8,7 +8,7 @@ pub(in crate::partner::shou_fu_tong) async fn mini_program_prepay(
     wxpay: &WechatPayClient,
     data: &MiniProgramPrepayRequest,
 ) -> Result<MiniProgramPrepayResponse> {
-    let url = format!("{}/combine-transactions/jsapi", BASE_URL);
+    let url = format!("{}/combine-transactions/jsapi", wxpay.v3_url());
 
     let req = wxpay.client.post(url).json(data).build()?;
     let res = wxpay.execute(req, None).await?;
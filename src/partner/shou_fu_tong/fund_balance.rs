@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 查询二级商户账户实时余额
@@ -10,7 +10,7 @@ pub async fn query_sub_mch_balance(
     account_type: &str,
 ) -> Result<SubMchBalanceResponse> {
     let url = format!("ecommerce/fund/balance/{}", sub_mchid);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay
         .client
@@ -32,7 +32,7 @@ pub async fn query_sub_mch_end_day_balance(
     date: &str,
 ) -> Result<SubMchBalanceResponse> {
     let url = format!("ecommerce/fund/enddaybalance/{}", sub_mchid);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay
         .client
@@ -52,7 +52,7 @@ pub async fn query_platform_balance(
     account_type: &str,
 ) -> Result<PlatformBalanceResponse> {
     let url = "merchant/fund/balance";
-    let url = format!("{}/{}/{}", BASE_URL, url, account_type);
+    let url = format!("{}/{}/{}", wxpay.v3_url(), url, account_type);
 
     let req = wxpay.client.get(url).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -69,7 +69,7 @@ pub async fn query_platform_end_day_balance(
     date: &str,
 ) -> Result<PlatformBalanceResponse> {
     let url = "merchant/fund/dayendbalance";
-    let url = format!("{}/{}/{}", BASE_URL, url, account_type);
+    let url = format!("{}/{}/{}", wxpay.v3_url(), url, account_type);
 
     let req = wxpay.client.get(url).query(&[("date", date)]).build()?;
     let res = wxpay.execute(req, None).await?;
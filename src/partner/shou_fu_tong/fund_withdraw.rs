@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 二级商户预约提现
@@ -9,7 +9,7 @@ pub async fn sub_mch_withdraw(
     request: &SubMchWithdrawalRequest,
 ) -> Result<SubMchWithdrawResponse> {
     let url = "ecommerce/fund/withdraw";
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.post(url).json(request).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -26,7 +26,7 @@ pub async fn query_sub_mch_withdraw_by_out_req_no(
     out_request_no: &str,
 ) -> Result<QuerySubMchWithdrawResponse> {
     let url = format!("ecommerce/fund/withdraw/out-request-no/{}", out_request_no);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay
         .client
@@ -47,7 +47,7 @@ pub async fn query_sub_mch_withdraw_by_withdraw_id(
     withdraw_id: &str,
 ) -> Result<QuerySubMchWithdrawResponse> {
     let url = format!("ecommerce/fund/withdraw/{}", withdraw_id);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay
         .client
@@ -67,7 +67,7 @@ pub async fn platform_withdraw(
     request: &PlatformWithdrawRequest,
 ) -> Result<PlatformWithdrawResponse> {
     let url = "merchant/fund/withdraw";
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.post(url).json(request).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -83,7 +83,7 @@ pub async fn query_platform_withdraw_by_out_req_no(
     out_request_no: &str,
 ) -> Result<QueryPlatformWithdrawResponse> {
     let url = format!("merchant/fund/withdraw/out-request-no/{}", out_request_no);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.get(url).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -99,7 +99,7 @@ pub async fn query_platform_withdraw_by_withdraw_id(
     withdraw_id: &str,
 ) -> Result<QueryPlatformWithdrawResponse> {
     let url = format!("merchant/fund/withdraw/withdraw-id/{}", withdraw_id);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.get(url).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -116,7 +116,7 @@ pub async fn download_withdraw_fail_file(
     bill_date: &str,
 ) -> Result<WithdrawFailFileInfo> {
     let url = format!("merchant/fund/withdraw/bill-type/{}", bill_type);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay
         .client
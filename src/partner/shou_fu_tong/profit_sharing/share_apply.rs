@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 分账请求
@@ -9,7 +9,7 @@ pub(in crate::partner::shou_fu_tong) async fn share_request(
     data: &ShareRequestBody,
 ) -> Result<ShareResponseBody> {
     let url = "ecommerce/profitsharing/orders";
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.post(url).json(data).build()?;
     let res = wxpay.execute(req, None).await?;
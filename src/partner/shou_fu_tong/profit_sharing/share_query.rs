@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 分账查询
@@ -10,7 +10,7 @@ pub(in crate::partner::shou_fu_tong) async fn query_share(
     transaction_id: &str,
     out_order_no: &str,
 ) -> Result<ShareQueryResponse> {
-    let url = format!("{}/ecommerce/profitsharing/orders", BASE_URL);
+    let url = format!("{}/ecommerce/profitsharing/orders", wxpay.v3_url());
 
     let req = wxpay
         .client
@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 分账剩余未分金额查询
@@ -9,7 +9,7 @@ pub async fn share_remainder_query(
     transaction_id: &str,
 ) -> Result<ShareRemainderQueryResponseBody> {
     let url = format!("ecommerce/profitsharing/orders/{}/amounts", transaction_id);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.get(url).build()?;
     let res = wxpay.execute(req, None).await?;
@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 申请退款
@@ -9,7 +9,7 @@ pub async fn refund_apply(
     data: &RefundRequestBody,
 ) -> Result<RefundResponseBody> {
     let url = "ecommerce/refunds/apply";
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay.client.post(url).json(data).build()?;
     let res = wxpay.execute(req, None).await?;
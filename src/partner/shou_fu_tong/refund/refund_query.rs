@@ -1,5 +1,5 @@
-use crate::{client::BASE_URL, WechatPayClient};
-use anyhow::Result;
+use crate::error::Result;
+use crate::WechatPayClient;
 use serde::{Deserialize, Serialize};
 
 /// 退款查询
@@ -10,7 +10,7 @@ pub async fn refund_query_by_transaction_id(
     sub_mchid: &str,
 ) -> Result<RefundQueryResponseBody> {
     let url = format!("ecommerce/refunds/id/{}", refund_id);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay
         .client
@@ -31,7 +31,7 @@ pub async fn refund_query_by_out_refund_no(
     sub_mchid: &str,
 ) -> Result<RefundQueryResponseBody> {
     let url = format!("ecommerce/refunds/out-refund-no/{}", out_refund_no);
-    let url = format!("{}/{}", BASE_URL, url);
+    let url = format!("{}/{}", wxpay.v3_url(), url);
 
     let req = wxpay
         .client
@@ -1,36 +1,398 @@
-//! 微信支付平台证书。
+//! 微信支付平台证书及微信支付公钥。
 
-use anyhow::Result;
+use crate::credential::Merchant;
+use crate::error::{Error, Result, WechatPayApiError};
+use crate::transport::HttpTransport;
+use crate::util::datetime_fmt;
 use base64::prelude::*;
-use bytes::{BufMut, BytesMut};
-use reqwest::Response;
+use bytes::{BufMut, Bytes, BytesMut};
+use chrono::{DateTime, Local};
+use http::HeaderMap;
+use reqwest::{Method, Request, Response};
+use rsa::pkcs1::DecodeRsaPublicKey;
 use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
 use rsa::sha2::Sha256;
 use rsa::signature::Verifier;
 use rsa::{Oaep, RsaPublicKey};
+use serde::Deserialize;
 use sha1::Sha1;
+use std::collections::BTreeMap;
+use x509_cert::der::{DecodePem, Encode};
+use x509_cert::Certificate;
 
 /// 微信支付平台证书。
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PlatformCertificate {
+    /// 证书序列号
     pub public_id: String,
     pub public_key: RsaPublicKey,
+    /// 证书启用时间。为 None 时视为已启用。
+    pub effective_time: Option<DateTime<Local>>,
+    /// 证书弃用时间。为 None 时视为永不过期。
+    pub expire_time: Option<DateTime<Local>>,
 }
 
 impl PlatformCertificate {
-    /// 对响应进行数字签名验证。
-    pub(crate) async fn verify_response(&self, res: Response) -> Result<Response> {
-        verify_response(&self.public_key, res).await
+    /// 由证书序列号及公钥构造平台证书，不限定有效期。
+    pub fn new(public_id: String, public_key: RsaPublicKey) -> PlatformCertificate {
+        PlatformCertificate {
+            public_id,
+            public_key,
+            effective_time: None,
+            expire_time: None,
+        }
     }
 
-    pub(crate) fn encrypt(&self, data: &[u8]) -> Result<String> {
-        encrypt(&self.public_key, data)
+    /// 从 PEM 格式的 X.509 证书中解析出平台证书。
+    /// 证书序列号即为 `public_id`，以大写十六进制表示；有效期取自证书本身。
+    pub fn from_pem(pem: &str) -> Result<PlatformCertificate> {
+        let cert = Certificate::from_pem(pem.as_bytes()).map_err(crypto_error)?;
+        let tbs = &cert.tbs_certificate;
+        let public_id = serial_number_to_hex(tbs.serial_number.as_bytes());
+        let spki = tbs.subject_public_key_info.to_der().map_err(crypto_error)?;
+        let public_key = RsaPublicKey::from_public_key_der(&spki).map_err(crypto_error)?;
+        Ok(PlatformCertificate {
+            public_id,
+            public_key,
+            effective_time: Some(tbs.validity.not_before.to_system_time().into()),
+            expire_time: Some(tbs.validity.not_after.to_system_time().into()),
+        })
     }
+
+    /// 证书在给定时间是否有效。
+    pub fn is_valid_at(&self, now: DateTime<Local>) -> bool {
+        self.effective_time.is_none_or(|t| t <= now) && self.expire_time.is_none_or(|t| now < t)
+    }
+}
+
+/// 微信支付公钥。
+/// 新入驻的商户不再使用平台证书，而是使用微信支付公钥进行验签及敏感信息加密。
+/// 公钥 ID 以 `PUB_KEY_ID_` 开头，出现在响应及通知的 `Wechatpay-Serial` 中。
+/// 参见 <https://pay.weixin.qq.com/doc/v3/merchant/4012153196>
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PlatformPublicKey {
+    /// 公钥 ID，形如 `PUB_KEY_ID_0114232134912410000000000000`
+    pub key_id: String,
+    pub public_key: RsaPublicKey,
+}
+
+impl PlatformPublicKey {
+    /// 公钥 ID 的前缀。
+    pub const KEY_ID_PREFIX: &'static str = "PUB_KEY_ID_";
+
+    pub fn new(key_id: String, public_key: RsaPublicKey) -> PlatformPublicKey {
+        PlatformPublicKey { key_id, public_key }
+    }
+
+    /// 从商户平台下载的 PEM 格式公钥文件(`pub_key.pem`)中加载微信支付公钥。
+    pub fn from_pem(key_id: impl Into<String>, pem: &str) -> Result<PlatformPublicKey> {
+        let public_key = RsaPublicKey::from_public_key_pem(pem)
+            .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+            .map_err(crypto_error)?;
+        Ok(PlatformPublicKey {
+            key_id: key_id.into(),
+            public_key,
+        })
+    }
+
+    /// `Wechatpay-Serial` 是否为微信支付公钥 ID(而非平台证书序列号)。
+    pub fn is_key_id(serial: &str) -> bool {
+        serial.starts_with(Self::KEY_ID_PREFIX)
+    }
+}
+
+/// 微信支付平台证书及微信支付公钥的集合，以证书序列号或公钥 ID 为键。
+/// 微信支付会不定期更换平台证书，更换期间新旧证书同时有效。
+/// 由平台证书切换到微信支付公钥期间，两者也会同时出现，此时二者均需持有。
+///
+/// 验签时，根据 `Wechatpay-Serial` 选择对应的证书或公钥；
+/// 加密时，若持有微信支付公钥则优先使用最近加入的公钥，否则使用当前有效且最新启用的证书。
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PlatformKeyring {
+    certificates: BTreeMap<String, PlatformCertificate>,
+    public_keys: BTreeMap<String, PlatformPublicKey>,
+    /// 用于加密的微信支付公钥 ID，即最近加入的公钥。
+    encryption_key_id: Option<String>,
+}
+
+impl PlatformKeyring {
+    pub fn new() -> PlatformKeyring {
+        PlatformKeyring::default()
+    }
+
+    /// 加入一个证书。若已存在相同序列号的证书，则替换并返回旧证书。
+    pub fn insert(&mut self, certificate: PlatformCertificate) -> Option<PlatformCertificate> {
+        self.certificates
+            .insert(certificate.public_id.clone(), certificate)
+    }
+
+    /// 加入一个微信支付公钥，此后使用它加密。若已存在相同 ID 的公钥，则替换并返回旧公钥。
+    pub fn insert_public_key(
+        &mut self,
+        public_key: PlatformPublicKey,
+    ) -> Option<PlatformPublicKey> {
+        self.encryption_key_id = Some(public_key.key_id.clone());
+        self.public_keys
+            .insert(public_key.key_id.clone(), public_key)
+    }
+
+    /// 合并另一组证书及公钥。序列号或 ID 相同时，以 `other` 中的为准；
+    /// `other` 持有公钥时，改用其加密所用的公钥。
+    pub fn merge(&mut self, other: PlatformKeyring) {
+        self.certificates.extend(other.certificates);
+        self.public_keys.extend(other.public_keys);
+        if other.encryption_key_id.is_some() {
+            self.encryption_key_id = other.encryption_key_id;
+        }
+    }
+
+    /// 是否持有指定序列号的证书或指定 ID 的公钥。
+    pub fn contains(&self, serial_no: &str) -> bool {
+        self.certificates.contains_key(serial_no) || self.public_keys.contains_key(serial_no)
+    }
+
+    /// 根据序列号获取证书。
+    pub fn get(&self, serial_no: &str) -> Option<&PlatformCertificate> {
+        self.certificates.get(serial_no)
+    }
+
+    /// 移除在给定时间已经过期的证书。
+    pub fn remove_expired(&mut self, now: DateTime<Local>) {
+        self.certificates
+            .retain(|_, c| c.expire_time.is_none_or(|t| now < t));
+    }
+
+    /// 根据 ID 获取微信支付公钥。
+    pub fn get_public_key(&self, key_id: &str) -> Option<&PlatformPublicKey> {
+        self.public_keys.get(key_id)
+    }
+
+    pub fn certificates(&self) -> impl Iterator<Item = &PlatformCertificate> {
+        self.certificates.values()
+    }
+
+    pub fn public_keys(&self) -> impl Iterator<Item = &PlatformPublicKey> {
+        self.public_keys.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.certificates.len() + self.public_keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty() && self.public_keys.is_empty()
+    }
+
+    /// 验签模式，由持有的证书及公钥决定。为空时返回 None。
+    pub fn mode(&self) -> Option<VerificationMode> {
+        match (self.certificates.is_empty(), self.public_keys.is_empty()) {
+            (false, true) => Some(VerificationMode::Certificate),
+            (true, false) => Some(VerificationMode::PublicKey),
+            (false, false) => Some(VerificationMode::Mixed),
+            (true, true) => None,
+        }
+    }
+
+    /// 当前有效且最新启用的证书，用于敏感信息加密及请求的 `Wechatpay-Serial`。
+    pub fn current(&self) -> Option<&PlatformCertificate> {
+        let now = Local::now();
+        self.certificates
+            .values()
+            .filter(|c| c.is_valid_at(now))
+            .max_by_key(|c| c.effective_time)
+    }
+
+    /// 用于加密的公钥及其 ID。优先使用最近加入的微信支付公钥，其次为当前证书。
+    fn encryption_key(&self) -> Option<(&str, &RsaPublicKey)> {
+        let public_key = self
+            .encryption_key_id
+            .as_deref()
+            .and_then(|id| self.public_keys.get(id));
+        if let Some(key) = public_key {
+            return Some((&key.key_id, &key.public_key));
+        }
+        self.current()
+            .map(|c| (c.public_id.as_str(), &c.public_key))
+    }
+
+    /// 加密所用的证书序列号或公钥 ID，即请求中的 `Wechatpay-Serial`。
+    pub fn current_serial(&self) -> Option<&str> {
+        self.encryption_key().map(|(id, _)| id)
+    }
+
+    /// 根据响应的 `Wechatpay-Serial` 选择证书或公钥，对 body 进行验签。
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<()> {
+        let serial = header_str(headers, "Wechatpay-Serial")?;
+        let public_key = if PlatformPublicKey::is_key_id(serial) {
+            self.get_public_key(serial)
+                .map(|k| &k.public_key)
+                .ok_or_else(|| {
+                    Error::Verification(format!("unknown wechatpay public key id: {}", serial))
+                })?
+        } else {
+            self.get(serial).map(|c| &c.public_key).ok_or_else(|| {
+                Error::Verification(format!("unknown platform certificate serial: {}", serial))
+            })?
+        };
+        verify_signature(public_key, headers, body)
+    }
+
+    /// 加密敏感信息，返回所用证书的序列号(或公钥 ID)及密文。
+    pub fn encrypt(&self, data: &[u8]) -> Result<(String, String)> {
+        let (serial, public_key) = self.encryption_key().ok_or_else(|| {
+            Error::Crypto("no valid platform certificate or public key".to_string())
+        })?;
+        let ciphertext = encrypt(public_key, data)?;
+        Ok((serial.to_string(), ciphertext))
+    }
+}
+
+/// 验签模式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationMode {
+    /// 平台证书模式
+    Certificate,
+    /// 微信支付公钥模式
+    PublicKey,
+    /// 平台证书与微信支付公钥并存，用于由平台证书切换到微信支付公钥期间
+    Mixed,
+}
+
+impl From<PlatformCertificate> for PlatformKeyring {
+    fn from(certificate: PlatformCertificate) -> PlatformKeyring {
+        std::iter::once(certificate).collect()
+    }
+}
+
+impl From<PlatformPublicKey> for PlatformKeyring {
+    fn from(public_key: PlatformPublicKey) -> PlatformKeyring {
+        let mut keyring = PlatformKeyring::new();
+        keyring.insert_public_key(public_key);
+        keyring
+    }
+}
+
+impl FromIterator<PlatformCertificate> for PlatformKeyring {
+    fn from_iter<I: IntoIterator<Item = PlatformCertificate>>(iter: I) -> PlatformKeyring {
+        let mut keyring = PlatformKeyring::new();
+        for certificate in iter {
+            keyring.insert(certificate);
+        }
+        keyring
+    }
+}
+
+/// 将证书序列号格式化为大写十六进制字符串，与微信支付返回的 `serial_no` 保持一致。
+pub(crate) fn serial_number_to_hex(bytes: &[u8]) -> String {
+    // DER 编码的正整数可能带有一个前导 0x00 字节，需去掉。
+    let bytes = match bytes {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        _ => bytes,
+    };
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// 下载平台证书。
+/// 平台证书下载接口的响应同样需要验签，但验签所用的证书就在响应之中。
+/// 因此先解密出全部证书，再用与 `Wechatpay-Serial` 对应的证书验证此响应。
+/// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/wechatpay5_1.shtml>
+pub(crate) async fn download_certificates(
+    transport: &HttpTransport,
+    merchant: &Merchant,
+) -> Result<PlatformKeyring> {
+    let url = format!("{}/v3/certificates", transport.base_url());
+    let url = url
+        .parse()
+        .map_err(|_| Error::InvalidArgument(format!("invalid url: {}", url)))?;
+    let mut req = Request::new(Method::GET, url);
+    req.headers_mut()
+        .append("Accept", "application/json".parse().unwrap());
+    let req = merchant.sign_request(req, None)?;
+    let res = transport.send(req).await?;
+
+    if !res.status().is_success() {
+        return Err(WechatPayApiError::from_response(res).await);
+    }
+
+    let headers = res.headers().clone();
+    let body = res.bytes().await?;
+    decrypt_certificates(merchant, &headers, &body)
+}
+
+/// 解密平台证书下载接口的响应，并用其中的证书对响应本身进行验签。
+pub(crate) fn decrypt_certificates(
+    merchant: &Merchant,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<PlatformKeyring> {
+    let res: CertificatesResponse = serde_json::from_slice(body)?;
+
+    let mut keyring = PlatformKeyring::new();
+    for item in res.data {
+        let encrypted = &item.encrypt_certificate;
+        let pem = merchant.aes_decrypt_to_string(
+            &encrypted.ciphertext,
+            &encrypted.associated_data,
+            &encrypted.nonce,
+        )?;
+        let mut certificate = PlatformCertificate::from_pem(&pem)?;
+        if certificate.public_id != item.serial_no {
+            return Err(Error::Decryption(format!(
+                "platform certificate serial mismatch: expected {}, found {}",
+                item.serial_no, certificate.public_id
+            )));
+        }
+        certificate.effective_time = Some(item.effective_time);
+        certificate.expire_time = Some(item.expire_time);
+        keyring.insert(certificate);
+    }
+    keyring.verify(headers, body)?;
+
+    Ok(keyring)
+}
+
+/// 平台证书下载接口的响应。
+#[derive(Debug, Clone, Deserialize)]
+struct CertificatesResponse {
+    data: Vec<EncryptedCertificateData>,
+}
+
+/// 加密的平台证书及其元信息。
+#[derive(Debug, Clone, Deserialize)]
+struct EncryptedCertificateData {
+    /// 证书序列号
+    serial_no: String,
+    /// 证书启用时间
+    #[serde(with = "datetime_fmt")]
+    effective_time: DateTime<Local>,
+    /// 证书弃用时间
+    #[serde(with = "datetime_fmt")]
+    expire_time: DateTime<Local>,
+    /// 使用商户 API v3 密钥加密的证书
+    encrypt_certificate: EncryptCertificate,
+}
+
+/// 证书密文。
+#[derive(Debug, Clone, Deserialize)]
+struct EncryptCertificate {
+    /// 加密算法类型。目前只支持AEAD_AES_256_GCM。
+    #[allow(dead_code)]
+    algorithm: String,
+    /// 随机串
+    nonce: String,
+    /// 附加数据
+    associated_data: String,
+    /// 数据密文。已经过 base64 编码。
+    ciphertext: String,
 }
+
 /// RSA 加密方法，用于对敏感信息进行加密
 pub fn encrypt(public_key: &RsaPublicKey, data: &[u8]) -> Result<String> {
     let mut rng = rand::thread_rng();
-    let enc_data = public_key.encrypt(&mut rng, Oaep::new::<Sha1>(), data)?;
+    let enc_data = public_key
+        .encrypt(&mut rng, Oaep::new::<Sha1>(), data)
+        .map_err(crypto_error)?;
 
     // Convert to base64
     Ok(BASE64_STANDARD.encode(enc_data))
@@ -40,45 +402,242 @@ pub fn encrypt(public_key: &RsaPublicKey, data: &[u8]) -> Result<String> {
 /// 验证响应的签名。
 /// <https://pay.weixin.qq.com/doc/v3/merchant/4013053249>
 pub async fn verify_response(public_key: &RsaPublicKey, res: Response) -> Result<Response> {
-    // 需要这个 builder 重新构建一个 Response 并返回。
-    let mut builder = http::Response::builder()
-        .status(res.status())
-        .version(res.version());
-    for (key, value) in res.headers() {
-        builder = builder.header(key, value);
-    }
-
-    let signature = res
-        .headers()
-        .get("Wechatpay-Signature")
-        .ok_or_else(|| anyhow::format_err!("missing `Wechatpay-Signature` header"))?
-        .to_str()?;
-    let signature = BASE64_STANDARD.decode(signature.as_bytes())?;
-
-    let timestamp = res
-        .headers()
-        .get("Wechatpay-Timestamp")
-        .ok_or_else(|| anyhow::format_err!("missing `Wechatpay-Timestamp` header"))?
-        .to_str()?;
-    let nonce_str = res
-        .headers()
-        .get("Wechatpay-Nonce")
-        .ok_or_else(|| anyhow::format_err!("missing `Wechatpay-Nonce` header"))?
-        .to_str()?;
+    verify_response_with(res, |headers, body| {
+        verify_signature(public_key, headers, body)
+    })
+    .await
+}
+
+/// 读出响应的 body 并进行验签，通过后重新构建一个 Response 返回。
+async fn verify_response_with<F>(res: Response, verify: F) -> Result<Response>
+where
+    F: FnOnce(&HeaderMap, &[u8]) -> Result<()>,
+{
+    let (head, body) = split_response(res).await?;
+    verify(head.headers(), &body)?;
+    Ok(join_response(head, body))
+}
+
+/// 读出响应的 body。返回的 head 保留了状态码、版本及全部 headers，
+/// 验签后可通过 `join_response` 重新构建 Response。
+pub(crate) async fn split_response(res: Response) -> Result<(http::Response<()>, Bytes)> {
+    let mut head = http::Response::new(());
+    *head.status_mut() = res.status();
+    *head.version_mut() = res.version();
+    *head.headers_mut() = res.headers().clone();
+    let body = res.bytes().await?;
+    Ok((head, body))
+}
+
+/// 由 `split_response` 拆分出的 head 与 body 重新构建 Response。
+pub(crate) fn join_response(head: http::Response<()>, body: Bytes) -> Response {
+    let (parts, ()) = head.into_parts();
+    http::Response::from_parts(parts, body).into()
+}
+
+/// 根据 `Wechatpay-Timestamp`, `Wechatpay-Nonce` 与 `Wechatpay-Signature` 头部，
+/// 验证 body 的签名。
+pub fn verify_signature(public_key: &RsaPublicKey, headers: &HeaderMap, body: &[u8]) -> Result<()> {
+    let signature = header_str(headers, "Wechatpay-Signature")?;
+    let signature = BASE64_STANDARD
+        .decode(signature.as_bytes())
+        .map_err(verification_error)?;
+
+    let timestamp = header_str(headers, "Wechatpay-Timestamp")?;
+    let nonce_str = header_str(headers, "Wechatpay-Nonce")?;
 
     let mut msg = BytesMut::new();
     msg.put_slice(timestamp.as_bytes());
     msg.put_u8(b'\n');
     msg.put_slice(nonce_str.as_bytes());
     msg.put_u8(b'\n');
-    let body = res.text().await?;
-    msg.put_slice(body.as_bytes());
+    msg.put_slice(body);
     msg.put_u8(b'\n');
 
     let verifying_key = VerifyingKey::<Sha256>::new(public_key.clone());
-    let signature = Signature::try_from(signature.as_slice())?;
-    verifying_key.verify(&msg, &signature)?;
+    let signature = Signature::try_from(signature.as_slice()).map_err(verification_error)?;
+    verifying_key
+        .verify(&msg, &signature)
+        .map_err(verification_error)?;
+    Ok(())
+}
+
+/// 读取验签所需的头部。
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str> {
+    headers
+        .get(name)
+        .ok_or_else(|| Error::Verification(format!("missing `{}` header", name)))?
+        .to_str()
+        .map_err(verification_error)
+}
+
+fn verification_error(e: impl std::fmt::Display) -> Error {
+    Error::Verification(e.to_string())
+}
+
+fn crypto_error(e: impl std::fmt::Display) -> Error {
+    Error::Crypto(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::MchCredential;
+    use crate::mock::fixtures::{test_credential, PLATFORM_SERIAL};
+    use aes_gcm::aead::{Aead, KeyInit, Payload};
+    use aes_gcm::Aes256Gcm;
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs1v15::SigningKey;
+    use rsa::signature::{RandomizedSigner, SignatureEncoding};
+    use rsa::RsaPrivateKey;
+
+    const PLATFORM_CERT: &str = include_str!("../testdata/platform_cert.pem");
+    const PLATFORM_KEY: &str = include_str!("../testdata/platform_key.pem");
+
+    fn certificates_response(cred: &MchCredential, serial_no: &str) -> String {
+        let nonce = "a1b2c3d4e5f6";
+        let cipher = Aes256Gcm::new(cred.mch_api_v3_key.as_bytes().into());
+        let payload = Payload {
+            msg: PLATFORM_CERT.as_bytes(),
+            aad: b"certificate",
+        };
+        let ciphertext = cipher.encrypt(nonce.as_bytes().into(), payload).unwrap();
+        serde_json::json!({
+            "data": [{
+                "serial_no": serial_no,
+                "effective_time": "2024-01-01T00:00:00+08:00",
+                "expire_time": "2099-01-01T00:00:00+08:00",
+                "encrypt_certificate": {
+                    "algorithm": "AEAD_AES_256_GCM",
+                    "nonce": nonce,
+                    "associated_data": "certificate",
+                    "ciphertext": BASE64_STANDARD.encode(ciphertext),
+                }
+            }]
+        })
+        .to_string()
+    }
+
+    fn signed_headers(body: &str) -> HeaderMap {
+        let timestamp = "1700000000";
+        let nonce = "nonce-for-test";
+        let msg = format!("{}\n{}\n{}\n", timestamp, nonce, body);
+        let key = RsaPrivateKey::from_pkcs1_pem(PLATFORM_KEY).unwrap();
+        let signature = SigningKey::<Sha256>::new(key)
+            .sign_with_rng(&mut rand::thread_rng(), msg.as_bytes())
+            .to_bytes();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Wechatpay-Serial", PLATFORM_SERIAL.parse().unwrap());
+        headers.insert("Wechatpay-Timestamp", timestamp.parse().unwrap());
+        headers.insert("Wechatpay-Nonce", nonce.parse().unwrap());
+        headers.insert(
+            "Wechatpay-Signature",
+            BASE64_STANDARD.encode(signature).parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_from_pem() -> Result<()> {
+        let cert = PlatformCertificate::from_pem(PLATFORM_CERT)?;
+        assert_eq!(cert.public_id, PLATFORM_SERIAL);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_certificates() -> Result<()> {
+        let cred = test_credential();
+        let body = certificates_response(&cred, PLATFORM_SERIAL);
+        let headers = signed_headers(&body);
+        let merchant = Merchant::from(cred);
+
+        let keyring = decrypt_certificates(&merchant, &headers, body.as_bytes())?;
+        assert_eq!(keyring.len(), 1);
+        assert_eq!(keyring.current().unwrap().public_id, PLATFORM_SERIAL);
+
+        // 篡改后的响应无法通过验签
+        let tampered = body.replace("2099", "2098");
+        assert!(decrypt_certificates(&merchant, &headers, tampered.as_bytes()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyring_selects_certificate() -> Result<()> {
+        let cert = PlatformCertificate::from_pem(PLATFORM_CERT)?;
+        let now = Local::now();
+        let old = PlatformCertificate {
+            public_id: "OLD".to_string(),
+            effective_time: Some(now - chrono::Duration::days(365)),
+            expire_time: Some(now + chrono::Duration::days(30)),
+            ..cert.clone()
+        };
+        let expired = PlatformCertificate {
+            public_id: "EXPIRED".to_string(),
+            effective_time: Some(now - chrono::Duration::days(730)),
+            expire_time: Some(now - chrono::Duration::days(1)),
+            ..cert.clone()
+        };
+        let new = PlatformCertificate {
+            public_id: "NEW".to_string(),
+            effective_time: Some(now - chrono::Duration::days(1)),
+            expire_time: Some(now + chrono::Duration::days(365)),
+            ..cert
+        };
+        let mut keyring: PlatformKeyring = [old, expired, new].into_iter().collect();
+        assert_eq!(keyring.current().unwrap().public_id, "NEW");
 
-    let new_res = builder.body(body)?;
-    Ok(new_res.into())
+        // 验签时按 `Wechatpay-Serial` 选择证书
+        let body = r#"{"code":"SUCCESS"}"#;
+        let mut headers = signed_headers(body);
+        headers.insert("Wechatpay-Serial", "OLD".parse().unwrap());
+        keyring.verify(&headers, body.as_bytes())?;
+        headers.insert("Wechatpay-Serial", "UNKNOWN".parse().unwrap());
+        assert!(keyring.verify(&headers, body.as_bytes()).is_err());
+
+        keyring.remove_expired(now);
+        assert_eq!(keyring.len(), 2);
+        assert!(keyring.get("EXPIRED").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyring_public_key_mode() -> Result<()> {
+        const KEY_ID: &str = "PUB_KEY_ID_0119000000012024010100000000000001";
+        let public_key =
+            PlatformPublicKey::from_pem(KEY_ID, include_str!("../testdata/pub_key.pem"))?;
+        let mut keyring = PlatformKeyring::from(public_key);
+        assert_eq!(keyring.mode(), Some(VerificationMode::PublicKey));
+
+        let body = r#"{"code":"SUCCESS"}"#;
+        let mut headers = signed_headers(body);
+        headers.insert("Wechatpay-Serial", KEY_ID.parse().unwrap());
+        keyring.verify(&headers, body.as_bytes())?;
+
+        // 切换期间，平台证书序列号与公钥 ID 均可验签，加密优先使用公钥。
+        keyring.insert(PlatformCertificate::from_pem(PLATFORM_CERT)?);
+        assert_eq!(keyring.mode(), Some(VerificationMode::Mixed));
+        assert_eq!(keyring.current_serial(), Some(KEY_ID));
+        keyring.verify(&headers, body.as_bytes())?;
+        headers.insert("Wechatpay-Serial", PLATFORM_SERIAL.parse().unwrap());
+        keyring.verify(&headers, body.as_bytes())?;
+
+        // 持有多个公钥时，使用最近加入的公钥加密，而非 ID 最小的公钥
+        const NEW_KEY_ID: &str = "PUB_KEY_ID_0119000000012025010100000000000001";
+        const OLD_KEY_ID: &str = "PUB_KEY_ID_0119000000012023010100000000000001";
+        let pem = include_str!("../testdata/pub_key.pem");
+        keyring.insert_public_key(PlatformPublicKey::from_pem(NEW_KEY_ID, pem)?);
+        assert_eq!(keyring.current_serial(), Some(NEW_KEY_ID));
+        let mut other = PlatformKeyring::new();
+        other.insert_public_key(PlatformPublicKey::from_pem(OLD_KEY_ID, pem)?);
+        keyring.merge(other);
+        assert_eq!(keyring.current_serial(), Some(OLD_KEY_ID));
+        assert_eq!(keyring.encrypt(b"data")?.0, OLD_KEY_ID);
+        // 合并不含公钥的证书时，加密所用的公钥不变
+        keyring.merge(PlatformKeyring::from(PlatformCertificate::from_pem(
+            PLATFORM_CERT,
+        )?));
+        assert_eq!(keyring.current_serial(), Some(OLD_KEY_ID));
+        Ok(())
+    }
 }
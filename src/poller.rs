@@ -0,0 +1,565 @@
+//! 订单状态轮询。
+//!
+//! 下单后，商户通常需要查询订单直至支付成功，并在订单失效后关闭订单。
+//! [`TradePoller`] 为每个待支付的订单启动一个后台任务：按退避间隔查询订单，
+//! 状态变化时通过 [`TradeEvents`] 发出 [`TradeEvent`]；收到支付通知后停止查询；
+//! 超过 `time_expire` 仍未支付时关闭订单；查询出现不可重试的错误时停止查询。支持直连商户的订单及电商收付通的合单。
+//!
+//! ```ignore
+//! let (poller, mut events) = TradePoller::new(client.clone());
+//! poller.watch(PolledTrade::direct(out_trade_no, time_expire));
+//!
+//! // 回调通知的 handler 中；合单的通知使用 `on_combine_notification`
+//! poller.on_notification(&notification.event);
+//!
+//! while let Some(event) = events.next().await {
+//!     // 更新本地订单
+//! }
+//! ```
+
+use crate::error::{Error, ErrorCode, Result};
+use crate::notify::NotificationEvent;
+use crate::partner::shou_fu_tong::combine_trade::CombineClosData;
+use crate::partner::shou_fu_tong::{self, ShouFuTong};
+use crate::trade::TradeState;
+use crate::WechatPayClient;
+use chrono::{DateTime, Local};
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::AbortHandle;
+
+/// 轮询订单状态，关闭失效的订单。
+///
+/// 轮询任务随 TradePoller 的 drop 而结束。
+pub struct TradePoller {
+    client: WechatPayClient,
+    initial_interval: Duration,
+    max_interval: Duration,
+    events: mpsc::UnboundedSender<TradeEvent>,
+    watching: Arc<Mutex<HashMap<String, Watching>>>,
+}
+
+/// 轮询中的订单：用于通知任务停止的 sender，及任务的 handle。
+struct Watching {
+    notify: oneshot::Sender<TradeState>,
+    task: AbortHandle,
+}
+
+impl TradePoller {
+    /// 查询间隔从 2 秒开始，每次加倍，最长 30 秒。
+    pub fn new(client: WechatPayClient) -> (TradePoller, TradeEvents) {
+        TradePoller::with_interval(client, Duration::from_secs(2), Duration::from_secs(30))
+    }
+
+    /// 查询间隔从 `initial` 开始，每次加倍，不超过 `max`。
+    pub fn with_interval(
+        client: WechatPayClient,
+        initial: Duration,
+        max: Duration,
+    ) -> (TradePoller, TradeEvents) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let poller = TradePoller {
+            client,
+            initial_interval: initial,
+            max_interval: max.max(initial),
+            events: tx,
+            watching: Default::default(),
+        };
+        (poller, TradeEvents { rx })
+    }
+
+    /// 开始轮询订单，立即查询一次。已在轮询中的订单不重复轮询。
+    ///
+    /// # Panics
+    ///
+    /// 轮询任务运行在当前的 tokio runtime 上，因此须在 runtime 中调用，否则 panic。
+    pub fn watch(&self, trade: PolledTrade) {
+        let mut watching = self.watching.lock().unwrap();
+        if watching.contains_key(&trade.out_trade_no) {
+            return;
+        }
+        let (notify, notified) = oneshot::channel();
+        let out_trade_no = trade.out_trade_no.clone();
+        let task = Task {
+            client: self.client.clone(),
+            trade,
+            initial_interval: self.initial_interval,
+            max_interval: self.max_interval,
+            events: self.events.clone(),
+            watching: self.watching.clone(),
+        };
+        let task = tokio::spawn(task.run(notified)).abort_handle();
+        watching.insert(out_trade_no, Watching { notify, task });
+    }
+
+    /// 通知钩子：收到订单的支付通知后调用，停止轮询此订单。
+    /// 状态与上一次查询到的不同时，发出来源为 [`TransitionSource::Notification`] 的事件。
+    /// 订单不在轮询中时返回 false。
+    pub fn notify(&self, out_trade_no: &str, state: TradeState) -> bool {
+        let watching = self.watching.lock().unwrap().remove(out_trade_no);
+        match watching {
+            Some(watching) => watching.notify.send(state).is_ok(),
+            None => false,
+        }
+    }
+
+    /// 以直连商户的支付通知调用 [`TradePoller::notify`]。非支付通知时返回 false。
+    /// 合单的支付通知见 [`TradePoller::on_combine_notification`]。
+    pub fn on_notification(&self, event: &NotificationEvent) -> bool {
+        match event {
+            NotificationEvent::Trade(trade) => self.notify(&trade.out_trade_no, trade.trade_state),
+            _ => false,
+        }
+    }
+
+    /// 以电商收付通的合单支付通知调用 [`TradePoller::notify`]，合单的状态由子单的状态得出。
+    /// 非支付通知、没有子单或子单状态无法解析时返回 false。
+    pub fn on_combine_notification(&self, event: &shou_fu_tong::NotificationEvent) -> bool {
+        let shou_fu_tong::NotificationEvent::Trade(trade) = event else {
+            return false;
+        };
+        let states = trade.sub_orders.iter().map(|o| o.trade_state.as_str());
+        match combine_state(states) {
+            Ok(Some(state)) => self.notify(&trade.combine_out_trade_no, state),
+            Ok(None) => false,
+            Err(e) => {
+                log::warn!("invalid combine trade notification: {}", e);
+                false
+            }
+        }
+    }
+
+    /// 轮询中的订单数。
+    pub fn len(&self) -> usize {
+        self.watching.lock().unwrap().len()
+    }
+
+    /// 是否没有轮询中的订单。
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for TradePoller {
+    fn drop(&mut self) {
+        for (_, watching) in self.watching.lock().unwrap().drain() {
+            watching.task.abort();
+        }
+    }
+}
+
+/// 待轮询的订单。
+#[derive(Debug)]
+pub struct PolledTrade {
+    out_trade_no: String,
+    time_expire: DateTime<Local>,
+    kind: TradeKind,
+}
+
+#[derive(Debug)]
+enum TradeKind {
+    Direct,
+    Combine(CombineClosData),
+}
+
+impl PolledTrade {
+    /// 直连商户的订单，超过 `time_expire` 仍未支付时关闭。
+    pub fn direct(out_trade_no: impl Into<String>, time_expire: DateTime<Local>) -> PolledTrade {
+        PolledTrade {
+            out_trade_no: out_trade_no.into(),
+            time_expire,
+            kind: TradeKind::Direct,
+        }
+    }
+
+    /// 电商收付通的合单，超过 `time_expire` 仍未支付时以 `close` 关闭合单。
+    /// 所有子单支付成功时，合单的状态为支付成功。
+    pub fn combine(
+        combine_out_trade_no: impl Into<String>,
+        time_expire: DateTime<Local>,
+        close: CombineClosData,
+    ) -> PolledTrade {
+        PolledTrade {
+            out_trade_no: combine_out_trade_no.into(),
+            time_expire,
+            kind: TradeKind::Combine(close),
+        }
+    }
+
+    /// 商户订单号，合单时为合单商户订单号。
+    pub fn out_trade_no(&self) -> &str {
+        &self.out_trade_no
+    }
+}
+
+/// 轮询中发生的事件。
+#[derive(Debug)]
+pub enum TradeEvent {
+    /// 订单状态变化。首次查询到订单时 `from` 为 None。
+    Transition {
+        out_trade_no: String,
+        from: Option<TradeState>,
+        to: TradeState,
+        source: TransitionSource,
+    },
+    /// 关闭失效的订单失败，不再轮询此订单。
+    CloseFailed { out_trade_no: String, error: Error },
+    /// 查询订单出现不可重试的错误(如验签失败、无权限)，不再轮询此订单。
+    QueryFailed { out_trade_no: String, error: Error },
+}
+
+impl TradeEvent {
+    /// 商户订单号，合单时为合单商户订单号。
+    pub fn out_trade_no(&self) -> &str {
+        match self {
+            TradeEvent::Transition { out_trade_no, .. } => out_trade_no,
+            TradeEvent::CloseFailed { out_trade_no, .. } => out_trade_no,
+            TradeEvent::QueryFailed { out_trade_no, .. } => out_trade_no,
+        }
+    }
+}
+
+/// 状态变化的来源。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionSource {
+    /// 查询订单
+    Query,
+    /// 通过 [`TradePoller::notify`] 收到的通知
+    Notification,
+    /// 订单超过失效时间，已关闭
+    Expired,
+}
+
+/// 轮询事件的 [`Stream`]。所有轮询任务结束且 [`TradePoller`] 被 drop 后结束。
+#[derive(Debug)]
+pub struct TradeEvents {
+    rx: mpsc::UnboundedReceiver<TradeEvent>,
+}
+
+impl TradeEvents {
+    /// 等待下一个事件。
+    pub async fn next(&mut self) -> Option<TradeEvent> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for TradeEvents {
+    type Item = TradeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<TradeEvent>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// 一个订单的轮询任务。
+struct Task {
+    client: WechatPayClient,
+    trade: PolledTrade,
+    initial_interval: Duration,
+    max_interval: Duration,
+    events: mpsc::UnboundedSender<TradeEvent>,
+    watching: Arc<Mutex<HashMap<String, Watching>>>,
+}
+
+impl Task {
+    async fn run(self, mut notified: oneshot::Receiver<TradeState>) {
+        let out_trade_no = &self.trade.out_trade_no;
+        let mut state = None;
+        let mut interval = self.initial_interval;
+        loop {
+            match self.query().await {
+                Ok(Some(queried)) => {
+                    self.transition(&mut state, queried, TransitionSource::Query);
+                    if !is_pending(queried) {
+                        break;
+                    }
+                }
+                // 合单尚无子单，无法得出状态，继续查询
+                Ok(None) => log::debug!("combine trade {} has no sub orders", out_trade_no),
+                // 订单可能尚未生成，或查询暂时失败，继续查询
+                Err(e) if e.api_code() == Some(&ErrorCode::OrderNotExist) || e.is_retryable() => {
+                    log::debug!("poll trade {} failed: {}", out_trade_no, e);
+                }
+                Err(error) => {
+                    let out_trade_no = out_trade_no.clone();
+                    let _ = self.events.send(TradeEvent::QueryFailed {
+                        out_trade_no,
+                        error,
+                    });
+                    break;
+                }
+            }
+
+            let now = Local::now();
+            if now >= self.trade.time_expire {
+                match self.close().await {
+                    Ok(()) => {
+                        self.transition(&mut state, TradeState::Closed, TransitionSource::Expired);
+                        break;
+                    }
+                    // 关闭前刚好支付成功，继续查询得到支付结果
+                    Err(e) if e.api_code() == Some(&ErrorCode::OrderPaid) || e.is_retryable() => {
+                        log::debug!("close trade {} failed: {}", out_trade_no, e);
+                    }
+                    Err(error) => {
+                        let out_trade_no = out_trade_no.clone();
+                        let _ = self.events.send(TradeEvent::CloseFailed {
+                            out_trade_no,
+                            error,
+                        });
+                        break;
+                    }
+                }
+            }
+
+            // 订单失效时立即关闭，不必等到下一次查询
+            let until_expire = (self.trade.time_expire - now).to_std().unwrap_or_default();
+            let delay = if until_expire.is_zero() {
+                interval
+            } else {
+                interval.min(until_expire)
+            };
+            interval = interval.saturating_mul(2).min(self.max_interval);
+            match tokio::time::timeout(delay, &mut notified).await {
+                Ok(Ok(notified)) => {
+                    self.transition(&mut state, notified, TransitionSource::Notification);
+                    return;
+                }
+                // TradePoller 已 drop
+                Ok(Err(_)) => return,
+                Err(_) => {}
+            }
+        }
+        self.watching.lock().unwrap().remove(out_trade_no);
+    }
+
+    /// 状态变化时发出事件。
+    fn transition(&self, state: &mut Option<TradeState>, to: TradeState, source: TransitionSource) {
+        if *state == Some(to) {
+            return;
+        }
+        let event = TradeEvent::Transition {
+            out_trade_no: self.trade.out_trade_no.clone(),
+            from: state.replace(to),
+            to,
+            source,
+        };
+        let _ = self.events.send(event);
+    }
+
+    /// 查询订单状态。合单没有子单时返回 None。
+    async fn query(&self) -> Result<Option<TradeState>> {
+        let out_trade_no = &self.trade.out_trade_no;
+        match &self.trade.kind {
+            TradeKind::Direct => {
+                let trade = self
+                    .client
+                    .query_trade_by_out_trade_no(out_trade_no)
+                    .await?;
+                Ok(Some(trade.trade_state))
+            }
+            TradeKind::Combine(_) => {
+                let order = self.client.query_combine_order(out_trade_no).await?;
+                let states = order.sub_orders.iter().flatten();
+                combine_state(states.map(|o| o.trade_state.as_str()))
+            }
+        }
+    }
+
+    async fn close(&self) -> Result<()> {
+        let out_trade_no = &self.trade.out_trade_no;
+        match &self.trade.kind {
+            TradeKind::Direct => self.client.close_trade(out_trade_no).await,
+            TradeKind::Combine(close) => self.client.close_combine_order(out_trade_no, close).await,
+        }
+    }
+}
+
+/// 未支付或支付中的订单需要继续查询。
+fn is_pending(state: TradeState) -> bool {
+    matches!(state, TradeState::NotPay | TradeState::UserPaying)
+}
+
+/// 合单的状态：所有子单已支付时为子单的状态(支付成功或转入退款)，否则为第一个未支付子单的状态。
+/// 没有子单时无法得出状态，返回 None。
+fn combine_state<'a>(
+    trade_states: impl IntoIterator<Item = &'a str>,
+) -> Result<Option<TradeState>> {
+    let mut states = Vec::new();
+    for trade_state in trade_states {
+        let state = serde_json::from_value(trade_state.into())?;
+        states.push(state);
+    }
+    let unpaid = states
+        .iter()
+        .find(|s| !matches!(s, TradeState::Success | TradeState::Refund));
+    let state = match (unpaid, states.first()) {
+        (Some(state), _) => *state,
+        (None, Some(_)) if states.contains(&TradeState::Refund) => TradeState::Refund,
+        (None, Some(_)) => TradeState::Success,
+        (None, None) => return Ok(None),
+    };
+    Ok(Some(state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::*;
+    use crate::partner::shou_fu_tong::combine_trade::ReqCloseSubOrder;
+    use serde_json::json;
+
+    /// 创建只有一个子单的合单，返回关闭合单的参数。
+    async fn create_combine(
+        client: &WechatPayClient,
+        combine_out_trade_no: &str,
+        out_trade_no: &str,
+    ) -> Result<CombineClosData> {
+        let combine = json!({
+            "combine_appid": "wxd678efh567hg6787",
+            "combine_mchid": "1900000001",
+            "combine_out_trade_no": combine_out_trade_no,
+            "sub_orders": [{
+                "mchid": "1900000001",
+                "sub_mchid": "1900000109",
+                "amount": { "total_amount": 100, "currency": "CNY" },
+                "out_trade_no": out_trade_no,
+                "description": "腾讯充值中心-QQ会员充值",
+            }],
+            "combine_payer_info": { "openid": "oUpF8uMuAJO_M2pxb1Q9zNjWeS6o" },
+            "notify_url": NOTIFY_URL,
+        });
+        let url = format!("{}/combine-transactions/jsapi", client.v3_url());
+        let req = client.client.post(url).json(&combine).build()?;
+        client.execute(req, None).await?;
+        Ok(CombineClosData {
+            combine_appid: "wxd678efh567hg6787".to_string(),
+            sub_orders: vec![ReqCloseSubOrder {
+                mchid: "1900000001".to_string(),
+                out_trade_no: out_trade_no.to_string(),
+                sub_mchid: Some("1900000109".to_string()),
+                sub_appid: None,
+            }],
+        })
+    }
+
+    async fn next_transition(
+        events: &mut TradeEvents,
+        source: TransitionSource,
+    ) -> (String, Option<TradeState>, TradeState) {
+        match events.next().await.unwrap() {
+            TradeEvent::Transition {
+                out_trade_no,
+                from,
+                to,
+                source: actual,
+            } => {
+                assert_eq!(actual, source, "{}", out_trade_no);
+                (out_trade_no, from, to)
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trade_poller() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+        let (poller, mut events) = TradePoller::with_interval(
+            client.clone(),
+            std::time::Duration::from_millis(10),
+            std::time::Duration::from_millis(40),
+        );
+        let later = chrono::Local::now() + chrono::Duration::minutes(30);
+        // 查询到支付成功
+        client.jsapi_create_trade(&jsapi_params("poller_1")).await?;
+        poller.watch(PolledTrade::direct("poller_1", later));
+        let first = next_transition(&mut events, TransitionSource::Query).await;
+        assert_eq!(first, ("poller_1".to_string(), None, TradeState::NotPay));
+        server.pay("poller_1")?;
+        let paid = next_transition(&mut events, TransitionSource::Query).await;
+        assert_eq!(
+            paid,
+            (
+                "poller_1".to_string(),
+                Some(TradeState::NotPay),
+                TradeState::Success
+            )
+        );
+
+        // 超过失效时间时关闭
+        client
+            .native_create_trade(&native_params("poller_2"))
+            .await?;
+        let expire = chrono::Local::now() + chrono::Duration::milliseconds(50);
+        poller.watch(PolledTrade::direct("poller_2", expire));
+        next_transition(&mut events, TransitionSource::Query).await;
+        let (_, from, to) = next_transition(&mut events, TransitionSource::Expired).await;
+        assert_eq!((from, to), (Some(TradeState::NotPay), TradeState::Closed));
+        let trade = client.query_trade_by_out_trade_no("poller_2").await?;
+        assert_eq!(trade.trade_state, TradeState::Closed);
+
+        // 收到通知后停止查询
+        client.jsapi_create_trade(&jsapi_params("poller_3")).await?;
+        poller.watch(PolledTrade::direct("poller_3", later));
+        next_transition(&mut events, TransitionSource::Query).await;
+        assert!(poller.notify("poller_3", TradeState::Success));
+        let (_, from, to) = next_transition(&mut events, TransitionSource::Notification).await;
+        assert_eq!((from, to), (Some(TradeState::NotPay), TradeState::Success));
+        assert!(!poller.notify("poller_3", TradeState::Success));
+
+        // 合单
+        let close = create_combine(&client, "poller_combine", "poller_sub_1").await?;
+        poller.watch(PolledTrade::combine("poller_combine", later, close));
+        let first = next_transition(&mut events, TransitionSource::Query).await;
+        assert_eq!(first.2, TradeState::NotPay);
+        server.pay_combine("poller_combine")?;
+        let (out_trade_no, _, to) = next_transition(&mut events, TransitionSource::Query).await;
+        assert_eq!(out_trade_no, "poller_combine");
+        assert_eq!(to, TradeState::Success);
+
+        // 合单的支付通知。查询间隔足够长，状态变化只能来自通知
+        let hour = std::time::Duration::from_secs(3600);
+        let (slow, mut slow_events) = TradePoller::with_interval(client.clone(), hour, hour);
+        let close = create_combine(&client, "poller_combine_2", "poller_sub_2").await?;
+        slow.watch(PolledTrade::combine("poller_combine_2", later, close));
+        next_transition(&mut slow_events, TransitionSource::Query).await;
+        server.pay_combine("poller_combine_2")?;
+        let req = server.combine_trade_notification("poller_combine_2")?;
+        let req = ShouFuTong::verify_notification(&client, req).await?;
+        let notification = serde_json::from_slice(req.body())?;
+        let event = client.decrypt_shou_fu_tong_notification(&notification)?;
+        assert!(!slow.on_notification(&NotificationEvent::Other(json!({}))));
+        assert!(slow.on_combine_notification(&event));
+        let (out_trade_no, from, to) =
+            next_transition(&mut slow_events, TransitionSource::Notification).await;
+        assert_eq!(out_trade_no, "poller_combine_2");
+        assert_eq!((from, to), (Some(TradeState::NotPay), TradeState::Success));
+        assert!(slow.is_empty());
+
+        // 查询出现不可重试的错误时停止查询
+        client.jsapi_create_trade(&jsapi_params("poller_4")).await?;
+        server.fail_next(403, "NO_AUTH", "商户无权限");
+        poller.watch(PolledTrade::direct("poller_4", later));
+        match events.next().await.unwrap() {
+            TradeEvent::QueryFailed {
+                out_trade_no,
+                error,
+            } => {
+                assert_eq!(out_trade_no, "poller_4");
+                assert_eq!(error.api_code(), Some(&ErrorCode::NoAuth));
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+        assert_eq!(combine_state([]).unwrap(), None);
+
+        drop(poller);
+        assert!(events.next().await.is_none());
+        Ok(())
+    }
+}
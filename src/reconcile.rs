@@ -0,0 +1,455 @@
+//! 对账：比较交易账单与商户本地的订单记录。
+//!
+//! 交易账单包含账单日期内支付成功的订单及申请的退款。本地记录应为同一日期内支付或退款的订单，
+//! 实现 [`LocalTrade`] 后传给 [`reconcile`]，得到不一致的记录。
+//!
+//! ```ignore
+//! let bill = client.download_trade_bill(&TradeBillParams::new(date)).await?;
+//! let report = reconcile(&bill, orders.iter())?;
+//! for mismatch in &report.mismatches {
+//!     log::warn!("{:?}", mismatch);
+//! }
+//! ```
+
+use crate::bill::{TradeBill, TradeBillRow};
+use crate::error::Result;
+use crate::refund::RefundStatus;
+use crate::trade::TradeState;
+use std::collections::BTreeMap;
+
+/// 商户本地的订单记录。
+pub trait LocalTrade {
+    /// 商户订单号
+    fn out_trade_no(&self) -> &str;
+
+    /// 订单金额，单位为分。
+    fn total(&self) -> i64;
+
+    /// 订单状态
+    fn trade_state(&self) -> TradeState;
+
+    /// 订单的退款。
+    fn refunds(&self) -> Vec<LocalRefund> {
+        Vec::new()
+    }
+}
+
+impl<T: LocalTrade + ?Sized> LocalTrade for &T {
+    fn out_trade_no(&self) -> &str {
+        (**self).out_trade_no()
+    }
+
+    fn total(&self) -> i64 {
+        (**self).total()
+    }
+
+    fn trade_state(&self) -> TradeState {
+        (**self).trade_state()
+    }
+
+    fn refunds(&self) -> Vec<LocalRefund> {
+        (**self).refunds()
+    }
+}
+
+/// 商户本地的退款记录。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalRefund {
+    /// 商户退款单号
+    pub out_refund_no: String,
+    /// 退款金额，单位为分。
+    pub refund: i64,
+    /// 退款状态
+    pub status: RefundStatus,
+}
+
+/// 对账结果。
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileReport {
+    /// 账单与本地记录一致的订单数。
+    pub matched: usize,
+    /// 不一致的记录，按商户订单号排序。
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReconcileReport {
+    /// 账单与本地记录是否完全一致。
+    pub fn is_balanced(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// 账单与本地记录不一致之处。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// 账单中有支付记录，本地没有此订单。
+    MissingLocally {
+        out_trade_no: String,
+        transaction_id: String,
+        /// 账单中的订单金额
+        total: i64,
+        /// 账单中的交易状态
+        trade_state: TradeState,
+    },
+    /// 本地订单已支付，账单中没有此订单的记录。
+    MissingRemotely {
+        out_trade_no: String,
+        /// 本地的订单金额
+        total: i64,
+        /// 本地的订单状态
+        trade_state: TradeState,
+    },
+    /// 订单金额不一致。
+    AmountMismatch {
+        out_trade_no: String,
+        local: i64,
+        remote: i64,
+    },
+    /// 订单状态不一致。已支付的订单在本地为 [`TradeState::Refund`] 时视为一致。
+    StateMismatch {
+        out_trade_no: String,
+        local: TradeState,
+        remote: TradeState,
+    },
+    /// 账单中的退款在本地没有记录，或金额、状态与本地不一致。
+    RefundNotReflected {
+        out_trade_no: String,
+        out_refund_no: String,
+        /// 账单中的退款金额
+        refund: i64,
+        /// 账单中的退款状态
+        status: Option<RefundStatus>,
+        /// 本地的退款记录，没有时为 None。
+        local: Option<LocalRefund>,
+    },
+    /// 本地退款已成功，账单中没有此退款的记录。
+    RefundMissingRemotely {
+        out_trade_no: String,
+        /// 本地的退款记录
+        local: LocalRefund,
+    },
+}
+
+/// 账单中同一订单的记录。
+#[derive(Default)]
+struct RemoteTrade {
+    payment: Option<TradeBillRow>,
+    refunds: Vec<TradeBillRow>,
+}
+
+/// 比较交易账单与本地订单记录。本地记录的商户订单号应唯一。
+///
+/// 账单中只有退款记录的订单(在账单日期之前支付)，只比较退款，不比较订单金额及状态；
+/// 未支付的本地订单不应出现在账单中，不会报告为 [`Mismatch::MissingRemotely`]。
+pub fn reconcile<L: LocalTrade>(
+    bill: &TradeBill,
+    local: impl IntoIterator<Item = L>,
+) -> Result<ReconcileReport> {
+    let mut remote: BTreeMap<String, RemoteTrade> = BTreeMap::new();
+    for row in bill.rows() {
+        let row = row?;
+        let trade = remote.entry(row.out_trade_no.clone()).or_default();
+        if row.is_refund() {
+            trade.refunds.push(row);
+        } else {
+            trade.payment = Some(row);
+        }
+    }
+
+    let mut mismatches = BTreeMap::<String, Vec<Mismatch>>::new();
+    let mut matched = 0;
+    for trade in local {
+        let out_trade_no = trade.out_trade_no().to_string();
+        let found = match remote.remove(&out_trade_no) {
+            Some(remote) => compare(&trade, remote),
+            None if is_paid(trade.trade_state()) => vec![Mismatch::MissingRemotely {
+                out_trade_no: out_trade_no.clone(),
+                total: trade.total(),
+                trade_state: trade.trade_state(),
+            }],
+            None => continue,
+        };
+        if found.is_empty() {
+            matched += 1;
+        } else {
+            mismatches.entry(out_trade_no).or_default().extend(found);
+        }
+    }
+
+    for (out_trade_no, trade) in remote {
+        let found = mismatches.entry(out_trade_no.clone()).or_default();
+        if let Some(payment) = trade.payment {
+            found.push(Mismatch::MissingLocally {
+                out_trade_no: out_trade_no.clone(),
+                total: remote_total(&payment),
+                transaction_id: payment.transaction_id,
+                trade_state: payment.trade_state,
+            });
+        }
+        for refund in &trade.refunds {
+            found.push(refund_not_reflected(refund, None));
+        }
+    }
+
+    Ok(ReconcileReport {
+        matched,
+        mismatches: mismatches.into_values().flatten().collect(),
+    })
+}
+
+/// 比较同一订单的本地记录与账单记录。
+fn compare(local: &impl LocalTrade, remote: RemoteTrade) -> Vec<Mismatch> {
+    let out_trade_no = local.out_trade_no();
+    let mut found = Vec::new();
+    if let Some(payment) = &remote.payment {
+        let remote_total = remote_total(payment);
+        if local.total() != remote_total {
+            found.push(Mismatch::AmountMismatch {
+                out_trade_no: out_trade_no.to_string(),
+                local: local.total(),
+                remote: remote_total,
+            });
+        }
+        let local_state = local.trade_state();
+        let same_state = local_state == payment.trade_state
+            || (is_paid(local_state) && is_paid(payment.trade_state));
+        if !same_state {
+            found.push(Mismatch::StateMismatch {
+                out_trade_no: out_trade_no.to_string(),
+                local: local_state,
+                remote: payment.trade_state,
+            });
+        }
+    }
+
+    let local_refunds = local.refunds();
+    for refund in &remote.refunds {
+        let out_refund_no = refund.out_refund_no.as_deref().unwrap_or_default();
+        let local_refund = local_refunds
+            .iter()
+            .find(|r| r.out_refund_no == out_refund_no);
+        let reflected = local_refund.is_some_and(|r| {
+            r.refund == remote_refund(refund) && refund.refund_status.is_none_or(|s| s == r.status)
+        });
+        if !reflected {
+            found.push(refund_not_reflected(refund, local_refund.cloned()));
+        }
+    }
+    for local_refund in local_refunds {
+        let in_bill = remote
+            .refunds
+            .iter()
+            .any(|r| r.out_refund_no.as_deref() == Some(&local_refund.out_refund_no));
+        if local_refund.status == RefundStatus::Success && !in_bill {
+            found.push(Mismatch::RefundMissingRemotely {
+                out_trade_no: out_trade_no.to_string(),
+                local: local_refund,
+            });
+        }
+    }
+    found
+}
+
+fn refund_not_reflected(refund: &TradeBillRow, local: Option<LocalRefund>) -> Mismatch {
+    Mismatch::RefundNotReflected {
+        out_trade_no: refund.out_trade_no.clone(),
+        out_refund_no: refund.out_refund_no.clone().unwrap_or_default(),
+        refund: remote_refund(refund),
+        status: refund.refund_status,
+        local,
+    }
+}
+
+fn is_paid(state: TradeState) -> bool {
+    matches!(state, TradeState::Success | TradeState::Refund)
+}
+
+/// 账单中的订单金额。没有订单金额列时使用应结订单金额与代金券金额之和。
+fn remote_total(row: &TradeBillRow) -> i64 {
+    row.total
+        .unwrap_or(row.settlement_total + row.coupon_amount)
+}
+
+/// 账单中的申请退款金额。没有此列时使用退款金额。
+fn remote_refund(row: &TradeBillRow) -> i64 {
+    row.refund_amount
+        .or(row.settlement_refund)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "交易时间,公众账号ID,商户号,特约商户号,设备号,微信订单号,商户订单号,用户标识,交易类型,交易状态,付款银行,货币种类,应结订单金额,代金券金额,微信退款单号,商户退款单号,退款金额,充值券退款金额,退款类型,退款状态,商品名称,商户数据包,手续费,费率,订单金额,申请退款金额,费率备注";
+    const SUMMARY: &str = "总交易单数,应结订单总金额,退款总金额,充值券退款总金额,手续费总金额,订单总金额,申请退款总金额\n`0,`0.00,`0.00,`0.00,`0.00000,`0.00,`0.00";
+
+    fn payment(out_trade_no: &str, total: &str) -> String {
+        format!(
+            "`2024-05-21 11:50:55,`wx8888888888888888,`1900000001,`0,`,`4200{0},`{0},`openid,`JSAPI,`SUCCESS,`OTHERS,`CNY,`{1},`0.00,`0,`0,`0.00,`0.00,`,`,`商品,`,`0.00600,`0.60%,`{1},`0.00,`",
+            out_trade_no, total
+        )
+    }
+
+    fn refund(out_trade_no: &str, out_refund_no: &str, amount: &str) -> String {
+        format!(
+            "`2024-05-21 12:00:00,`wx8888888888888888,`1900000001,`0,`,`4200{0},`{0},`openid,`JSAPI,`REFUND,`OTHERS,`CNY,`0.00,`0.00,`5030{1},`{1},`{2},`0.00,`ORIGINAL,`SUCCESS,`商品,`,`-0.00180,`0.60%,`0.00,`{2},`",
+            out_trade_no, out_refund_no, amount
+        )
+    }
+
+    fn bill(rows: &[String]) -> TradeBill {
+        TradeBill::parse(format!("{}\n{}\n{}\n", HEADER, rows.join("\n"), SUMMARY)).unwrap()
+    }
+
+    struct Order {
+        out_trade_no: &'static str,
+        total: i64,
+        state: TradeState,
+        refunds: Vec<LocalRefund>,
+    }
+
+    impl LocalTrade for Order {
+        fn out_trade_no(&self) -> &str {
+            self.out_trade_no
+        }
+
+        fn total(&self) -> i64 {
+            self.total
+        }
+
+        fn trade_state(&self) -> TradeState {
+            self.state
+        }
+
+        fn refunds(&self) -> Vec<LocalRefund> {
+            self.refunds.clone()
+        }
+    }
+
+    fn order(out_trade_no: &'static str, total: i64, state: TradeState) -> Order {
+        Order {
+            out_trade_no,
+            total,
+            state,
+            refunds: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_balanced() {
+        let bill = bill(&[
+            payment("order_1", "1.00"),
+            payment("order_2", "2.00"),
+            refund("order_2", "refund_1", "0.50"),
+            // 之前支付的订单今天退款
+            refund("order_0", "refund_0", "0.10"),
+        ]);
+        let mut order_2 = order("order_2", 200, TradeState::Refund);
+        order_2.refunds.push(LocalRefund {
+            out_refund_no: "refund_1".to_string(),
+            refund: 50,
+            status: RefundStatus::Success,
+        });
+        let mut order_0 = order("order_0", 100, TradeState::Refund);
+        order_0.refunds.push(LocalRefund {
+            out_refund_no: "refund_0".to_string(),
+            refund: 10,
+            status: RefundStatus::Success,
+        });
+        let orders = [
+            order("order_1", 100, TradeState::Success),
+            order_2,
+            order_0,
+            // 未支付的订单不在账单中
+            order("order_3", 100, TradeState::NotPay),
+        ];
+        let report = reconcile(&bill, orders.iter()).unwrap();
+        assert!(report.is_balanced(), "{:?}", report.mismatches);
+        assert_eq!(report.matched, 3);
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let bill = bill(&[
+            payment("order_1", "1.00"),
+            payment("order_2", "2.00"),
+            payment("order_3", "3.00"),
+            refund("order_3", "refund_1", "0.50"),
+            payment("order_5", "5.00"),
+            payment("order_6", "6.00"),
+        ]);
+        let mut order_3 = order("order_3", 300, TradeState::Refund);
+        order_3.refunds.push(LocalRefund {
+            out_refund_no: "refund_1".to_string(),
+            refund: 50,
+            status: RefundStatus::Processing,
+        });
+        let mut order_6 = order("order_6", 600, TradeState::Refund);
+        order_6.refunds.push(LocalRefund {
+            out_refund_no: "refund_2".to_string(),
+            refund: 100,
+            status: RefundStatus::Success,
+        });
+        // 处理中的退款可能尚未出现在账单中
+        order_6.refunds.push(LocalRefund {
+            out_refund_no: "refund_3".to_string(),
+            refund: 100,
+            status: RefundStatus::Processing,
+        });
+        let orders = vec![
+            order("order_1", 100, TradeState::Success),
+            order("order_2", 201, TradeState::NotPay),
+            order_3,
+            order("order_4", 400, TradeState::Success),
+            order_6,
+        ];
+        let report = reconcile(&bill, orders).unwrap();
+        assert_eq!(report.matched, 1);
+        assert_eq!(
+            report.mismatches,
+            vec![
+                Mismatch::AmountMismatch {
+                    out_trade_no: "order_2".to_string(),
+                    local: 201,
+                    remote: 200,
+                },
+                Mismatch::StateMismatch {
+                    out_trade_no: "order_2".to_string(),
+                    local: TradeState::NotPay,
+                    remote: TradeState::Success,
+                },
+                Mismatch::RefundNotReflected {
+                    out_trade_no: "order_3".to_string(),
+                    out_refund_no: "refund_1".to_string(),
+                    refund: 50,
+                    status: Some(RefundStatus::Success),
+                    local: Some(LocalRefund {
+                        out_refund_no: "refund_1".to_string(),
+                        refund: 50,
+                        status: RefundStatus::Processing,
+                    }),
+                },
+                Mismatch::MissingRemotely {
+                    out_trade_no: "order_4".to_string(),
+                    total: 400,
+                    trade_state: TradeState::Success,
+                },
+                Mismatch::MissingLocally {
+                    out_trade_no: "order_5".to_string(),
+                    transaction_id: "4200order_5".to_string(),
+                    total: 500,
+                    trade_state: TradeState::Success,
+                },
+                Mismatch::RefundMissingRemotely {
+                    out_trade_no: "order_6".to_string(),
+                    local: LocalRefund {
+                        out_refund_no: "refund_2".to_string(),
+                        refund: 100,
+                        status: RefundStatus::Success,
+                    },
+                },
+            ]
+        );
+    }
+}
@@ -1,10 +1,9 @@
 //! 退款相关接口。
 
 use crate::client::WechatPayClient;
-use crate::client::BASE_URL;
+use crate::error::Result;
 use crate::util::datetime_fmt;
 use crate::util::option_datetime_fmt;
-use anyhow::Result;
 use chrono::{DateTime, Local};
 use serde::Deserializer;
 use serde::{Deserialize, Serialize};
@@ -13,7 +12,7 @@ impl WechatPayClient {
     /// 申请退款。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_1_9.shtml>
     pub async fn apply_refund(&self, params: &RefundParams) -> Result<RefundQueryResponse> {
-        let url = format!("{}/refund/domestic/refunds", BASE_URL);
+        let url = format!("{}/refund/domestic/refunds", self.v3_url());
         let req = self.client.post(&url).json(params).build()?;
         let res = self.execute(req, None).await?;
         let res: RefundQueryResponse = res.json().await?;
@@ -23,7 +22,11 @@ impl WechatPayClient {
     /// 查询退款。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_1_10.shtml>
     pub async fn query_refund(&self, out_refund_no: &str) -> Result<RefundQueryResponse> {
-        let url = format!("{}/refund/domestic/refunds/{}", BASE_URL, out_refund_no);
+        let url = format!(
+            "{}/refund/domestic/refunds/{}",
+            self.v3_url(),
+            out_refund_no
+        );
         let req = self.client.get(url).build()?;
         let res = self.execute(req, None).await?;
         let res: RefundQueryResponse = res.json().await?;
@@ -0,0 +1,410 @@
+//! 防重放。
+//!
+//! 验签只能保证响应与通知来自微信支付，无法阻止截获的通知被再次发送。因此验签通过后还会：
+//! * 检查 `Wechatpay-Timestamp` 与本地时间的偏差，拒绝过旧(或过新)的响应及通知；
+//! * 记录通知的 `Wechatpay-Nonce`，拒绝重复的随机串；
+//! * 记录已成功应答的通知 ID。微信支付未收到成功应答时，会以相同的通知 ID、新的随机串重发通知；
+//!   此前已成功应答的通知 ID 再次出现时，按 [`DuplicatePolicy`] 拒绝，或将其标记为重发([`Redelivery`])。
+//!   通知 ID 在业务处理成功后才记录，处理失败后重发的通知仍按新通知处理。
+//!
+//! 默认使用进程内的 [`MemoryNonceStore`]。多实例部署时，可基于 Redis 等实现 [`NonceStore`]，
+//! 在实例间共享记录。
+
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use chrono::Local;
+use http::HeaderMap;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 默认允许的时钟偏差。
+pub const DEFAULT_MAX_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// 默认保留通知 ID 的时长，覆盖微信支付重发通知的周期(约 24 小时)。
+pub const DEFAULT_NOTIFICATION_ID_TTL: Duration = Duration::from_secs(48 * 60 * 60);
+
+/// 随机串及通知 ID 的存储。
+///
+/// 随机串的 key 形如 `nonce:{Wechatpay-Nonce}`，通知 ID 的 key 形如 `id:{id}`。
+/// 基于 Redis 的实现可使用 `SET key 1 NX PX ttl` 及 `EXISTS key`。
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    /// 记录 key，保留 `ttl`。key 尚未记录(或已过期)时返回 `true`，已存在时返回 `false`。
+    async fn insert(&self, key: &str, ttl: Duration) -> Result<bool>;
+
+    /// key 是否已记录且未过期。
+    async fn contains(&self, key: &str) -> Result<bool>;
+}
+
+/// 进程内的 [`NonceStore`]。容量有限，超出时淘汰最早的记录。
+pub struct MemoryNonceStore {
+    capacity: usize,
+    inner: Mutex<MemoryInner>,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    /// key 及其过期时间。
+    entries: HashMap<String, Instant>,
+    /// 按插入顺序排列，用于淘汰。
+    order: VecDeque<(String, Instant)>,
+}
+
+impl MemoryNonceStore {
+    /// 默认容量。
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    /// 最多保存 `capacity` 条记录。
+    pub fn new(capacity: usize) -> MemoryNonceStore {
+        MemoryNonceStore {
+            capacity: capacity.max(1),
+            inner: Mutex::new(MemoryInner::default()),
+        }
+    }
+
+    /// 当前保存的记录数(包括已过期但尚未淘汰的记录)。
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// 是否没有记录。
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for MemoryNonceStore {
+    fn default() -> MemoryNonceStore {
+        MemoryNonceStore::new(MemoryNonceStore::DEFAULT_CAPACITY)
+    }
+}
+
+impl MemoryInner {
+    /// 淘汰队首的记录。key 被重新插入过时，只在过期时间一致时移除。
+    fn pop_front(&mut self) {
+        if let Some((key, expires)) = self.order.pop_front() {
+            if self.entries.get(&key) == Some(&expires) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl NonceStore for MemoryNonceStore {
+    async fn insert(&self, key: &str, ttl: Duration) -> Result<bool> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        while inner
+            .order
+            .front()
+            .is_some_and(|(_, expires)| *expires <= now)
+        {
+            inner.pop_front();
+        }
+        if inner.entries.get(key).is_some_and(|expires| *expires > now) {
+            return Ok(false);
+        }
+        while inner.entries.len() >= self.capacity {
+            inner.pop_front();
+        }
+        let expires = now + ttl;
+        inner.entries.insert(key.to_string(), expires);
+        inner.order.push_back((key.to_string(), expires));
+        Ok(true)
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .entries
+            .get(key)
+            .is_some_and(|expires| *expires > Instant::now()))
+    }
+}
+
+impl fmt::Debug for MemoryNonceStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryNonceStore")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// 收到重复的通知 ID 时的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// 标记为重发，在验签后的请求中加入 [`Redelivery`] 扩展。
+    /// 微信支付重发的通知属于正常情况，商户应按幂等处理并返回成功应答。
+    #[default]
+    Flag,
+    /// 拒绝，返回 [`Error::Replay`]。
+    Reject,
+}
+
+/// 重发的通知。
+/// [`WechatPayClient::verify_notification`](crate::WechatPayClient::verify_notification)
+/// 遇到已成功应答过的通知 ID 时，将其加入返回请求的 extensions。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redelivery;
+
+/// 防重放配置。
+///
+/// ```ignore
+/// let client = WechatPayClient::builder()
+///     .mch_credential(credential)
+///     .fetch_platform_certificates()
+///     .replay_protection(
+///         ReplayProtection::new()
+///             .max_clock_skew(Duration::from_secs(60))
+///             .store(RedisNonceStore::new(redis))
+///             .on_duplicate(DuplicatePolicy::Reject),
+///     )
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct ReplayProtection {
+    max_clock_skew: Option<Duration>,
+    notification_id_ttl: Duration,
+    store: Option<Arc<dyn NonceStore>>,
+    on_duplicate: DuplicatePolicy,
+}
+
+impl ReplayProtection {
+    /// 默认配置：允许 5 分钟的时钟偏差，使用 [`MemoryNonceStore`]，重复的通知 ID 标记为重发。
+    pub fn new() -> ReplayProtection {
+        ReplayProtection::default()
+    }
+
+    /// 不做任何检查，仅验签。
+    pub fn none() -> ReplayProtection {
+        ReplayProtection {
+            max_clock_skew: None,
+            notification_id_ttl: DEFAULT_NOTIFICATION_ID_TTL,
+            store: None,
+            on_duplicate: DuplicatePolicy::Flag,
+        }
+    }
+
+    /// 设置响应及通知的 `Wechatpay-Timestamp` 与本地时间允许的最大偏差。
+    pub fn max_clock_skew(mut self, max_clock_skew: Duration) -> Self {
+        self.max_clock_skew = Some(max_clock_skew);
+        self
+    }
+
+    /// 不检查时间戳。
+    pub fn without_clock_skew_check(mut self) -> Self {
+        self.max_clock_skew = None;
+        self
+    }
+
+    /// 设置随机串及通知 ID 的存储。
+    pub fn store(mut self, store: impl NonceStore + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// 设置通知 ID 的保留时长，默认为 [`DEFAULT_NOTIFICATION_ID_TTL`]。
+    pub fn notification_id_ttl(mut self, ttl: Duration) -> Self {
+        self.notification_id_ttl = ttl;
+        self
+    }
+
+    /// 设置收到重复的通知 ID 时的处理方式。默认为 [`DuplicatePolicy::Flag`]。
+    pub fn on_duplicate(mut self, on_duplicate: DuplicatePolicy) -> Self {
+        self.on_duplicate = on_duplicate;
+        self
+    }
+
+    /// 检查 `Wechatpay-Timestamp` 与本地时间的偏差。响应与通知均需检查。
+    pub(crate) fn check_timestamp(&self, headers: &HeaderMap) -> Result<()> {
+        let Some(max_clock_skew) = self.max_clock_skew else {
+            return Ok(());
+        };
+        let timestamp = headers
+            .get("Wechatpay-Timestamp")
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| Error::Verification("missing `Wechatpay-Timestamp` header".into()))?;
+        let timestamp: i64 = timestamp.parse().map_err(|_| {
+            Error::Verification(format!("invalid `Wechatpay-Timestamp`: {}", timestamp))
+        })?;
+        let skew = Local::now().timestamp().abs_diff(timestamp);
+        if skew > max_clock_skew.as_secs() {
+            return Err(Error::Verification(format!(
+                "`Wechatpay-Timestamp` is {}s away from local time",
+                skew
+            )));
+        }
+        Ok(())
+    }
+
+    /// 记录通知的随机串，并检查通知 ID。随机串重复时拒绝；通知 ID 已成功应答过时按配置拒绝，
+    /// 或返回 `true` 表示为重发的通知。调用前须已验签。
+    /// 此处不记录通知 ID，业务处理成功后由 [`commit_notification`](Self::commit_notification) 记录。
+    pub(crate) async fn check_notification(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<bool> {
+        let Some(store) = &self.store else {
+            return Ok(false);
+        };
+
+        let nonce = headers
+            .get("Wechatpay-Nonce")
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| Error::Verification("missing `Wechatpay-Nonce` header".into()))?;
+        // 时间戳之外的随机串已被时间戳检查拒绝，无需保留更久。
+        let nonce_ttl = self
+            .max_clock_skew
+            .map_or(self.notification_id_ttl, |skew| skew * 2);
+        if !store.insert(&format!("nonce:{}", nonce), nonce_ttl).await? {
+            return Err(Error::Replay(format!("duplicate nonce: {}", nonce)));
+        }
+
+        #[derive(Deserialize)]
+        struct NotificationId {
+            id: String,
+        }
+        let NotificationId { id } = serde_json::from_slice(body)?;
+        if !store.contains(&format!("id:{}", id)).await? {
+            return Ok(false);
+        }
+        match self.on_duplicate {
+            DuplicatePolicy::Flag => Ok(true),
+            DuplicatePolicy::Reject => {
+                Err(Error::Replay(format!("duplicate notification id: {}", id)))
+            }
+        }
+    }
+
+    /// 记录已成功应答的通知 ID。此后相同 ID 的通知视为重发。
+    pub(crate) async fn commit_notification(&self, id: &str) -> Result<()> {
+        if let Some(store) = &self.store {
+            store
+                .insert(&format!("id:{}", id), self.notification_id_ttl)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for ReplayProtection {
+    fn default() -> ReplayProtection {
+        ReplayProtection {
+            max_clock_skew: Some(DEFAULT_MAX_CLOCK_SKEW),
+            notification_id_ttl: DEFAULT_NOTIFICATION_ID_TTL,
+            store: Some(Arc::new(MemoryNonceStore::default())),
+            on_duplicate: DuplicatePolicy::Flag,
+        }
+    }
+}
+
+impl fmt::Debug for ReplayProtection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayProtection")
+            .field("max_clock_skew", &self.max_clock_skew)
+            .field("notification_id_ttl", &self.notification_id_ttl)
+            .field("store", &self.store.is_some())
+            .field("on_duplicate", &self.on_duplicate)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(timestamp: i64, nonce: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Wechatpay-Timestamp",
+            timestamp.to_string().parse().unwrap(),
+        );
+        headers.insert("Wechatpay-Nonce", nonce.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() -> Result<()> {
+        let store = MemoryNonceStore::new(2);
+        let ttl = Duration::from_secs(60);
+        assert!(store.insert("a", ttl).await?);
+        assert!(!store.insert("a", ttl).await?);
+        assert!(store.insert("b", ttl).await?);
+        // 超出容量时淘汰最早的记录
+        assert!(store.insert("c", ttl).await?);
+        assert_eq!(store.len(), 2);
+        assert!(store.insert("a", ttl).await?);
+        assert!(!store.insert("c", ttl).await?);
+        assert!(store.contains("c").await?);
+        assert!(!store.contains("b").await?);
+
+        // 过期的记录可以重新插入
+        let store = MemoryNonceStore::default();
+        assert!(store.insert("a", Duration::ZERO).await?);
+        assert!(!store.contains("a").await?);
+        assert!(store.insert("a", ttl).await?);
+        assert!(!store.insert("a", ttl).await?);
+        assert_eq!(store.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_timestamp() {
+        let replay = ReplayProtection::new().max_clock_skew(Duration::from_secs(60));
+        let now = Local::now().timestamp();
+        assert!(replay.check_timestamp(&headers(now, "n")).is_ok());
+        assert!(replay.check_timestamp(&headers(now - 30, "n")).is_ok());
+        assert!(matches!(
+            replay.check_timestamp(&headers(now - 120, "n")),
+            Err(Error::Verification(_))
+        ));
+        assert!(replay.check_timestamp(&headers(now + 120, "n")).is_err());
+        assert!(replay.check_timestamp(&HeaderMap::new()).is_err());
+
+        let replay = replay.without_clock_skew_check();
+        assert!(replay.check_timestamp(&headers(now - 3600, "n")).is_ok());
+        assert!(ReplayProtection::none()
+            .check_timestamp(&HeaderMap::new())
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_notification() -> Result<()> {
+        let now = Local::now().timestamp();
+        let body = br#"{"id":"EV-2018022511223320873"}"#;
+
+        let replay = ReplayProtection::new();
+        assert!(!replay.check_notification(&headers(now, "n1"), body).await?);
+        // 相同的随机串为重放
+        assert!(matches!(
+            replay.check_notification(&headers(now, "n1"), body).await,
+            Err(Error::Replay(_))
+        ));
+        // 尚未成功应答的通知，重发时仍按新通知处理
+        assert!(!replay.check_notification(&headers(now, "n2"), body).await?);
+        // 成功应答后，新的随机串、相同的通知 ID 为重发
+        replay.commit_notification("EV-2018022511223320873").await?;
+        assert!(replay.check_notification(&headers(now, "n3"), body).await?);
+
+        let replay = ReplayProtection::new().on_duplicate(DuplicatePolicy::Reject);
+        assert!(!replay.check_notification(&headers(now, "n1"), body).await?);
+        replay.commit_notification("EV-2018022511223320873").await?;
+        assert!(matches!(
+            replay.check_notification(&headers(now, "n2"), body).await,
+            Err(Error::Replay(_))
+        ));
+
+        let replay = ReplayProtection::none();
+        assert!(!replay.check_notification(&headers(now, "n1"), body).await?);
+        replay.commit_notification("EV-2018022511223320873").await?;
+        assert!(!replay.check_notification(&headers(now, "n1"), body).await?);
+        Ok(())
+    }
+}
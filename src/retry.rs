@@ -0,0 +1,192 @@
+//! 请求重试策略。
+//!
+//! 微信支付文档说明，遇到 `SYSTEM_ERROR`、`BANK_ERROR`、`FREQUENCY_LIMITED` 或 5xx 错误时，
+//! 可以使用相同的商户订单号(`out_trade_no`、`out_refund_no`、`out_request_no` 等)重新调用。
+//! 重试时每次都会重新签名，使用新的时间戳与随机串。
+
+use crate::error::{Error, ErrorCode};
+use rand::Rng;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 判断错误是否可以重试。
+pub type RetryClassifier = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+
+/// 请求重试策略。
+///
+/// 第 n 次重试前等待 `initial_backoff * 2^(n-1)`，不超过 `max_backoff`，
+/// 开启 jitter 时在 `[backoff/2, backoff]` 内随机取值，避免大量请求同时重试。
+///
+/// ```ignore
+/// let policy = RetryPolicy::new(3).backoff(Duration::from_millis(200), Duration::from_secs(2));
+/// let client = WechatPayClient::builder()
+///     .mch_credential(credential)
+///     .fetch_platform_certificates()
+///     .retry_policy(policy)
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    classifier: RetryClassifier,
+}
+
+impl RetryPolicy {
+    /// 最多发送 `max_attempts` 次请求(包括第一次)。
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// 不重试。
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::new(1)
+    }
+
+    /// 设置退避时间的初始值及上限。
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// 是否对退避时间加入随机抖动。默认开启。
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// 自定义哪些错误可以重试。默认为 [`Error::is_retryable`]。
+    pub fn classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.classifier = Arc::new(classifier);
+        self
+    }
+
+    /// 最多发送请求的次数(包括第一次)。
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// 第 `attempt` 次请求失败后，是否应当重试。`attempt` 从 1 开始。
+    pub fn should_retry(&self, attempt: u32, error: &Error) -> bool {
+        attempt < self.max_attempts && (self.classifier)(error)
+    }
+
+    /// 第 `attempt` 次请求失败后，重试前的等待时间。`attempt` 从 1 开始。
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << exp)
+            .min(self.max_backoff);
+        if self.jitter && !backoff.is_zero() {
+            let half = backoff / 2;
+            half + rand::thread_rng().gen_range(Duration::ZERO..=backoff - half)
+        } else {
+            backoff
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// 默认不重试；设置了次数时，退避时间从 100ms 开始，最长 5s。
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+            classifier: Arc::new(Error::is_retryable),
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Error {
+    /// 是否为微信支付文档中说明可以重试的错误：
+    /// 网络错误、5xx 响应，以及 `SYSTEM_ERROR`、`BANK_ERROR`、`FREQUENCY_LIMITED` 错误码。
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Transport(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            Error::Api(e) => {
+                e.status.is_server_error()
+                    || matches!(
+                        e.code,
+                        ErrorCode::SystemError | ErrorCode::BankError | ErrorCode::FrequencyLimited
+                    )
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::WechatPayApiError;
+    use http::StatusCode;
+
+    fn api_error(status: StatusCode, code: &str) -> Error {
+        WechatPayApiError {
+            status,
+            code: code.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(api_error(StatusCode::TOO_MANY_REQUESTS, "FREQUENCY_LIMITED").is_retryable());
+        assert!(api_error(StatusCode::INTERNAL_SERVER_ERROR, "SYSTEM_ERROR").is_retryable());
+        assert!(api_error(StatusCode::FORBIDDEN, "BANK_ERROR").is_retryable());
+        assert!(api_error(StatusCode::BAD_GATEWAY, "").is_retryable());
+        assert!(!api_error(StatusCode::BAD_REQUEST, "PARAM_ERROR").is_retryable());
+        assert!(!api_error(StatusCode::FORBIDDEN, "NOT_ENOUGH").is_retryable());
+        assert!(!Error::Verification("bad signature".to_string()).is_retryable());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new(10)
+            .backoff(Duration::from_millis(100), Duration::from_millis(500))
+            .jitter(false);
+        assert_eq!(policy.backoff_for(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_for(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff_for(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff_for(40), Duration::from_millis(500));
+
+        let policy = policy.jitter(true);
+        for attempt in 1..5 {
+            let backoff = policy.backoff_for(attempt);
+            assert!(backoff >= Duration::from_millis(50) && backoff <= Duration::from_millis(500));
+        }
+
+        let error = api_error(StatusCode::INTERNAL_SERVER_ERROR, "SYSTEM_ERROR");
+        assert!(policy.should_retry(9, &error));
+        assert!(!policy.should_retry(10, &error));
+        assert!(!RetryPolicy::none().should_retry(1, &error));
+        assert!(!policy.classifier(|_| false).should_retry(1, &error));
+    }
+}
@@ -0,0 +1,342 @@
+//! 敏感信息字段。
+//! 商户上送的敏感信息(如姓名、证件号码、银行账号、手机号等)需使用微信支付平台公钥加密；
+//! 微信支付对应答中的敏感信息使用商户公钥加密，商户需使用自己的私钥解密。
+
+use crate::credential::{self, Signer};
+use crate::error::Result;
+use crate::platform_certificate::PlatformKeyring;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// 请求中需使用平台公钥加密的敏感信息字段。
+/// 由 [`WechatPayClient`](crate::WechatPayClient) 序列化请求时，使用当前的平台证书
+/// (或微信支付公钥)加密，并将所用的序列号设置为请求的 `Wechatpay-Serial`。
+/// 在此之外序列化(如直接使用 `RequestBuilder::json`)会失败，避免明文上送。
+#[derive(Clone, PartialEq, Eq)]
+pub struct SensitiveString(String);
+
+impl SensitiveString {
+    /// 由明文构造。
+    pub fn new(plaintext: impl Into<String>) -> SensitiveString {
+        SensitiveString(plaintext.into())
+    }
+
+    /// 明文。
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SensitiveString {
+    fn from(plaintext: String) -> SensitiveString {
+        SensitiveString(plaintext)
+    }
+}
+
+impl From<&str> for SensitiveString {
+    fn from(plaintext: &str) -> SensitiveString {
+        SensitiveString(plaintext.to_string())
+    }
+}
+
+impl Debug for SensitiveString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 明文不输出到日志
+        f.write_str("SensitiveString(\"...\")")
+    }
+}
+
+impl Serialize for SensitiveString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let ciphertext = ENCRYPTION.with_borrow_mut(|encryption| {
+            let encryption = encryption.as_mut().ok_or_else(|| {
+                S::Error::custom("sensitive field must be serialized by WechatPayClient")
+            })?;
+            let (serial, ciphertext) = encryption
+                .keyring
+                .encrypt(self.0.as_bytes())
+                .map_err(S::Error::custom)?;
+            encryption.serial.get_or_insert(serial);
+            Ok(ciphertext)
+        })?;
+        serializer.serialize_str(&ciphertext)
+    }
+}
+
+impl<'de> Deserialize<'de> for SensitiveString {
+    fn deserialize<D>(deserializer: D) -> Result<SensitiveString, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(SensitiveString)
+    }
+}
+
+/// 序列化请求时使用的平台证书，及实际用于加密的序列号。
+struct Encryption {
+    keyring: Arc<PlatformKeyring>,
+    serial: Option<String>,
+}
+
+thread_local! {
+    static ENCRYPTION: RefCell<Option<Encryption>> = const { RefCell::new(None) };
+}
+
+/// 将 value 序列化为 JSON，其中的 [`SensitiveString`] 使用 keyring 加密。
+/// 返回 JSON 及加密所用的序列号；value 不含敏感信息字段时序列号为 `None`。
+pub(crate) fn to_json_encrypted<T: Serialize + ?Sized>(
+    keyring: Arc<PlatformKeyring>,
+    value: &T,
+) -> Result<(Vec<u8>, Option<String>)> {
+    /// 序列化结束(包括 panic)时恢复之前的状态。
+    struct Restore(Option<Encryption>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            ENCRYPTION.set(self.0.take());
+        }
+    }
+
+    let encryption = Encryption {
+        keyring,
+        serial: None,
+    };
+    let restore = Restore(ENCRYPTION.replace(Some(encryption)));
+    let json = serde_json::to_vec(value);
+    let serial = ENCRYPTION.with_borrow_mut(|e| e.as_mut().and_then(|e| e.serial.take()));
+    drop(restore);
+    Ok((json?, serial))
+}
+
+/// 应答中经商户公钥加密的敏感信息字段。
+/// 反序列化时保存密文，经 [`DecryptSensitive::decrypt_sensitive`] 解密后可读取明文。
+/// 本项目的查询接口在返回前已自动完成解密。
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptedString {
+    ciphertext: String,
+    plaintext: Option<String>,
+}
+
+impl EncryptedString {
+    /// 由密文构造。
+    pub fn new(ciphertext: impl Into<String>) -> EncryptedString {
+        EncryptedString {
+            ciphertext: ciphertext.into(),
+            plaintext: None,
+        }
+    }
+
+    /// 密文。已经过 base64 编码。
+    pub fn ciphertext(&self) -> &str {
+        &self.ciphertext
+    }
+
+    /// 明文。尚未解密时为 `None`。
+    pub fn plaintext(&self) -> Option<&str> {
+        self.plaintext.as_deref()
+    }
+
+    /// 取出明文。尚未解密时为 `None`。
+    pub fn into_plaintext(self) -> Option<String> {
+        self.plaintext
+    }
+
+    /// 使用商户 RSA 私钥解密，并返回明文。已解密时直接返回。
+    pub fn decrypt(&mut self, signer: &dyn Signer) -> Result<&str> {
+        if self.plaintext.is_none() {
+            self.plaintext = Some(credential::rsa_decrypt(signer, &self.ciphertext)?);
+        }
+        Ok(self.plaintext.as_deref().unwrap())
+    }
+}
+
+impl Debug for EncryptedString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 明文不输出到日志
+        f.debug_struct("EncryptedString")
+            .field("ciphertext", &"...")
+            .field("decrypted", &self.plaintext.is_some())
+            .finish()
+    }
+}
+
+impl Serialize for EncryptedString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.ciphertext)
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptedString {
+    fn deserialize<D>(deserializer: D) -> Result<EncryptedString, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(EncryptedString::new)
+    }
+}
+
+/// 包含敏感信息字段的应答，可使用商户 RSA 私钥解密其中的全部敏感信息。
+pub trait DecryptSensitive {
+    /// 解密全部敏感信息字段。
+    fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()>;
+}
+
+impl DecryptSensitive for EncryptedString {
+    fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()> {
+        self.decrypt(signer).map(|_| ())
+    }
+}
+
+impl<T: DecryptSensitive> DecryptSensitive for Option<T> {
+    fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()> {
+        match self {
+            Some(v) => v.decrypt_sensitive(signer),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: DecryptSensitive> DecryptSensitive for Vec<T> {
+    fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()> {
+        self.iter_mut()
+            .try_for_each(|v| v.decrypt_sensitive(signer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::{
+        test_client, test_credential, test_platform_certificate, test_server,
+    };
+    use crate::partner::shou_fu_tong::applyment::settlement;
+    use crate::platform_certificate::encrypt;
+    use base64::prelude::*;
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::{Oaep, RsaPrivateKey};
+    use serde_json::json;
+    use sha1::Sha1;
+
+    #[derive(Debug, Deserialize)]
+    struct Account {
+        account_name: EncryptedString,
+        account_no: Option<EncryptedString>,
+    }
+
+    impl DecryptSensitive for Account {
+        fn decrypt_sensitive(&mut self, signer: &dyn Signer) -> Result<()> {
+            self.account_name.decrypt_sensitive(signer)?;
+            self.account_no.decrypt_sensitive(signer)
+        }
+    }
+
+    #[test]
+    fn test_decrypt_sensitive() -> Result<()> {
+        let cred = test_credential();
+        let public_key = cred.mch_rsa_private_key.to_public_key();
+        let ciphertext = encrypt(&public_key, "张三".as_bytes())?;
+        let mut account: Account = serde_json::from_value(json!({
+            "account_name": ciphertext,
+        }))
+        .unwrap();
+        assert_eq!(account.account_name.plaintext(), None);
+        assert!(!format!("{:?}", account).contains(&ciphertext));
+
+        account.decrypt_sensitive(&cred)?;
+        assert_eq!(account.account_name.plaintext(), Some("张三"));
+        assert_eq!(account.account_name.ciphertext(), ciphertext);
+        assert!(account.account_no.is_none());
+        assert!(!format!("{:?}", account).contains("张三"));
+        assert_eq!(
+            serde_json::to_value(&account.account_name).unwrap(),
+            json!(ciphertext)
+        );
+
+        let mut invalid = EncryptedString::new("not base64");
+        assert!(invalid.decrypt(&cred).is_err());
+        Ok(())
+    }
+
+    #[derive(Serialize)]
+    struct Contact {
+        contact_type: String,
+        contact_name: SensitiveString,
+        mobile_phone: Option<SensitiveString>,
+    }
+
+    #[test]
+    fn test_encrypt_sensitive() -> Result<()> {
+        let certificate = test_platform_certificate();
+        let serial = certificate.public_id.clone();
+        let private_key =
+            RsaPrivateKey::from_pkcs1_pem(include_str!("../testdata/platform_key.pem")).unwrap();
+        let keyring = Arc::new(PlatformKeyring::from(certificate));
+
+        let contact = Contact {
+            contact_type: "65".to_string(),
+            contact_name: "张三".into(),
+            mobile_phone: Some("13900000000".into()),
+        };
+        assert!(!format!("{:?}", contact.contact_name).contains("张三"));
+        // 未经 WechatPayClient 序列化时失败，避免明文上送
+        assert!(serde_json::to_vec(&contact).is_err());
+
+        let (json, used) = to_json_encrypted(keyring.clone(), &contact)?;
+        assert_eq!(used.as_deref(), Some(serial.as_str()));
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value["contact_type"], "65");
+        let decrypt = |v: &serde_json::Value| {
+            let ciphertext = BASE64_STANDARD.decode(v.as_str().unwrap()).unwrap();
+            let plaintext = private_key
+                .decrypt(Oaep::new::<Sha1>(), &ciphertext)
+                .unwrap();
+            String::from_utf8(plaintext).unwrap()
+        };
+        assert_eq!(decrypt(&value["contact_name"]), "张三");
+        assert_eq!(decrypt(&value["mobile_phone"]), "13900000000");
+
+        // 不含敏感信息时不设置序列号
+        let (_, used) = to_json_encrypted(keyring, &json!({ "a": 1 }))?;
+        assert!(used.is_none());
+        assert!(serde_json::to_vec(&contact).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_response() -> anyhow::Result<()> {
+        let server = test_server();
+        let client = test_client(&server).await;
+
+        let data = settlement::SettlementModifyData {
+            modify_mode: "MODIFY_MODE_ASYNC".to_string(),
+            account_type: "ACCOUNT_TYPE_BUSINESS".to_string(),
+            account_bank: "工商银行".to_string(),
+            bank_name: None,
+            bank_branch_id: None,
+            account_number: "6222020000000000078".into(),
+            account_name: Some("深圳腾大有限公司".into()),
+        };
+        // 敏感信息不能以明文上送
+        let url = format!(
+            "{}/apply4sub/sub_merchants/1900000109/modify-settlement",
+            client.v3_url()
+        );
+        assert!(client.client.post(url).json(&data).build().is_err());
+
+        let res = settlement::modify_settlement(&client, "1900000109", &data).await?;
+        let res =
+            settlement::query_settlement_modify(&client, "1900000109", &res.application_no).await?;
+        assert_eq!(res.account_name.plaintext(), Some("深圳腾大有限公司"));
+        assert_eq!(res.account_number.plaintext(), Some("6222020000000000078"));
+        assert_ne!(res.account_number.ciphertext(), "6222020000000000078");
+        Ok(())
+    }
+}
@@ -1,22 +1,20 @@
 //! 交易相关接口的实现
 
-use crate::client::{WechatPayClient, BASE_URL};
+use crate::client::WechatPayClient;
 use crate::credential::generate_none_str;
+use crate::error::{Result, ValidationErrors, ValidationReason};
 use crate::util::option_datetime_fmt;
-use anyhow::Result;
-use base64::prelude::*;
 use chrono::{DateTime, Local};
 use rand::Rng;
-use rsa::pkcs1v15::SigningKey;
-use rsa::sha2::Sha256;
-use rsa::signature::{RandomizedSigner, SignatureEncoding};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::marker::PhantomData;
 
 impl WechatPayClient {
     /// JSAPI 下单，返回 prepay_id。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_1_1.shtml>
     pub async fn jsapi_create_trade(&self, params: &JsApiCreateTradeParams) -> Result<String> {
-        let url = format!("{}/pay/transactions/jsapi", BASE_URL);
+        params.validate()?;
+        let url = format!("{}/pay/transactions/jsapi", self.v3_url());
         let req = self.client.post(url).json(params).build()?;
         let res = self.execute(req, None).await?;
         let res: JsApiCreateTradeResponse = res.json().await?;
@@ -26,7 +24,8 @@ impl WechatPayClient {
     /// APP 下单，返回 `prepay_id`。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_2_1.shtml>
     pub async fn app_create_trade(&self, params: &AppCreateTradeParams) -> Result<String> {
-        let url = format!("{}/pay/transactions/app", BASE_URL);
+        params.validate()?;
+        let url = format!("{}/pay/transactions/app", self.v3_url());
         let req = self.client.post(url).json(params).build()?;
         let res = self.execute(req, None).await?;
         let res: AppCreateTradeResponse = res.json().await?;
@@ -36,7 +35,8 @@ impl WechatPayClient {
     /// H5 下单，返回 h5_url。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_3_1.shtml>
     pub async fn h5_create_trade(&self, params: &H5CreateTradeParams) -> Result<String> {
-        let url = format!("{}/pay/transactions/h5", BASE_URL);
+        params.validate()?;
+        let url = format!("{}/pay/transactions/h5", self.v3_url());
         let req = self.client.post(url).json(params).build()?;
         let res = self.execute(req, None).await?;
         let res: H5CreateTradeResponse = res.json().await?;
@@ -47,7 +47,8 @@ impl WechatPayClient {
     /// code_url 用于生成支付二维码，然后提供给用户扫码支付。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_4_1.shtml>
     pub async fn native_create_trade(&self, params: &NativeCreateTradeParams) -> Result<String> {
-        let url = format!("{}/pay/transactions/native", BASE_URL);
+        params.validate()?;
+        let url = format!("{}/pay/transactions/native", self.v3_url());
         let req = self.client.post(url).json(params).build()?;
         let res = self.execute(req, None).await?;
         let res: NativeCreateTradeResponse = res.json().await?;
@@ -62,7 +63,9 @@ impl WechatPayClient {
     ) -> Result<TradeQueryResponse> {
         let url = format!(
             "{}/pay/transactions/id/{}?mchid={}",
-            BASE_URL, transaction_id, &self.mch_credential.mch_id
+            self.v3_url(),
+            transaction_id,
+            &self.merchant.mch_id
         );
         let req = self.client.get(url).build()?;
         let res = self.execute(req, None).await?;
@@ -78,7 +81,9 @@ impl WechatPayClient {
     ) -> Result<TradeQueryResponse> {
         let url = format!(
             "{}/pay/transactions/out-trade-no/{}?mchid={}",
-            BASE_URL, out_trade_no, &self.mch_credential.mch_id
+            self.v3_url(),
+            out_trade_no,
+            &self.merchant.mch_id
         );
         let req = self.client.get(url).build()?;
         let res = self.execute(req, None).await?;
@@ -97,10 +102,11 @@ impl WechatPayClient {
 
         let url = format!(
             "{}/pay/transactions/out-trade-no/{}/close",
-            BASE_URL, out_trade_no
+            self.v3_url(),
+            out_trade_no
         );
         let req = CloseTradeRequest {
-            mch_id: self.mch_credential.mch_id.clone(),
+            mch_id: self.merchant.mch_id.clone(),
         };
         let req = self.client.post(url).json(&req).build()?;
         let _res = self.execute(req, None).await?;
@@ -112,29 +118,106 @@ impl WechatPayClient {
     /// 对 JSAPI 下单返回的 prepay_id 进行签名。
     /// 前端在调起微信支付时，需要这些参数。
     /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_1_4.shtml>
-    pub fn sign_jsapi_trade(&self, prepay_id: &str, app_id: &str) -> JsApiTradeSignature {
-        let timestamp = Local::now().timestamp();
-        let nonce_str = generate_none_str(32);
+    pub fn sign_jsapi_trade(&self, prepay_id: &str, app_id: &str) -> Result<JsApiTradeSignature> {
         let package = format!("prepay_id={}", prepay_id);
-        let msg = format!("{}\n{}\n{}\n{}\n", app_id, timestamp, nonce_str, package);
-
-        let mut rng = rand::thread_rng();
-        let signing_key =
-            SigningKey::<Sha256>::new(self.mch_credential.mch_rsa_private_key.clone());
-        let signature = signing_key
-            .sign_with_rng(&mut rng, msg.as_bytes())
-            .to_bytes();
-        let signature = BASE64_STANDARD.encode(&signature);
+        let (timestamp, nonce_str, signature) = self.sign_prepay(app_id, &package)?;
 
-        JsApiTradeSignature {
+        Ok(JsApiTradeSignature {
             app_id: app_id.to_string(),
-            timestamp: timestamp.to_string(),
+            timestamp,
             nonce_str,
             package,
             sign_type: "RSA".to_string(),
             pay_sign: signature,
-        }
+        })
+    }
+
+    /// 对小程序下单(即 JSAPI 下单)返回的 prepay_id 进行签名。
+    /// 返回 `wx.requestPayment` 所需的参数，不含 `appId`，但签名中仍使用小程序的 app_id。
+    /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_5_4.shtml>
+    pub fn sign_mini_program_trade(
+        &self,
+        prepay_id: &str,
+        app_id: &str,
+    ) -> Result<MiniProgramTradeSignature> {
+        let package = format!("prepay_id={}", prepay_id);
+        let (timestamp, nonce_str, signature) = self.sign_prepay(app_id, &package)?;
+
+        Ok(MiniProgramTradeSignature {
+            timestamp,
+            nonce_str,
+            package,
+            sign_type: "RSA".to_string(),
+            pay_sign: signature,
+        })
+    }
+
+    /// 对 APP 下单返回的 prepay_id 进行签名。
+    /// APP 在调起微信支付时，需要这些参数。`partnerid` 为客户端的商户号。
+    /// 参见 <https://pay.weixin.qq.com/wiki/doc/apiv3/apis/chapter3_2_4.shtml>
+    pub fn sign_app_trade(&self, prepay_id: &str, app_id: &str) -> Result<AppTradeSignature> {
+        let (timestamp, nonce_str, signature) = self.sign_prepay(app_id, prepay_id)?;
+
+        Ok(AppTradeSignature {
+            app_id: app_id.to_string(),
+            partner_id: self.merchant.mch_id.clone(),
+            prepay_id: prepay_id.to_string(),
+            package: "Sign=WXPay".to_string(),
+            nonce_str,
+            timestamp,
+            sign: signature,
+        })
+    }
+
+    /// 调起支付的签名。签名串为 `应用ID\n时间戳\n随机字符串\n{content}\n`，
+    /// 其中 content 在 JSAPI、小程序为 `prepay_id=xxx`，在 APP 为 prepay_id。
+    /// 返回时间戳、随机串及签名。
+    fn sign_prepay(&self, app_id: &str, content: &str) -> Result<(String, String, String)> {
+        let timestamp = Local::now().timestamp();
+        let nonce_str = generate_none_str(32);
+        let msg = format!("{}\n{}\n{}\n{}\n", app_id, timestamp, nonce_str, content);
+
+        let signature = self.merchant.sign(msg.as_bytes())?;
+        Ok((timestamp.to_string(), nonce_str, signature))
+    }
+
+    /// JSAPI 下单，并对返回的 prepay_id 签名。
+    pub async fn jsapi_create_signed_trade(
+        &self,
+        params: &JsApiCreateTradeParams,
+    ) -> Result<SignedTrade<JsApiTradeSignature>> {
+        let prepay_id = self.jsapi_create_trade(params).await?;
+        let payload = self.sign_jsapi_trade(&prepay_id, &params.app_id)?;
+        Ok(SignedTrade { prepay_id, payload })
     }
+
+    /// 小程序下单(即 JSAPI 下单)，并对返回的 prepay_id 签名。
+    pub async fn mini_program_create_signed_trade(
+        &self,
+        params: &JsApiCreateTradeParams,
+    ) -> Result<SignedTrade<MiniProgramTradeSignature>> {
+        let prepay_id = self.jsapi_create_trade(params).await?;
+        let payload = self.sign_mini_program_trade(&prepay_id, &params.app_id)?;
+        Ok(SignedTrade { prepay_id, payload })
+    }
+
+    /// APP 下单，并对返回的 prepay_id 签名。
+    pub async fn app_create_signed_trade(
+        &self,
+        params: &AppCreateTradeParams,
+    ) -> Result<SignedTrade<AppTradeSignature>> {
+        let prepay_id = self.app_create_trade(params).await?;
+        let payload = self.sign_app_trade(&prepay_id, &params.app_id)?;
+        Ok(SignedTrade { prepay_id, payload })
+    }
+}
+
+/// 下单并签名的结果。`payload` 可直接交给前端或 APP 调起支付；
+/// prepay_id 有效期为 2 小时，可保存下来以便在有效期内重新签名。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedTrade<T> {
+    pub prepay_id: String,
+    pub payload: T,
 }
 
 /// JSAPI 下单时，针对返回的 prepay_id 生成的签名，
@@ -153,6 +236,38 @@ pub struct JsApiTradeSignature {
     pub pay_sign: String,
 }
 
+/// 小程序调起支付(`wx.requestPayment`)的参数。相比 JsApiTradeSignature 少了 app_id。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MiniProgramTradeSignature {
+    #[serde(rename = "timeStamp")]
+    pub timestamp: String, // 注意，单位为秒。类型为 string。
+    pub nonce_str: String,
+    // 须形如 `prepay_id=xxxxx`。
+    pub package: String,
+    // 统一为 RSA
+    pub sign_type: String,
+    pub pay_sign: String,
+}
+
+/// APP 调起支付的参数。字段名均为小写，与 OpenSDK 的 PayReq 对应。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppTradeSignature {
+    #[serde(rename = "appid")]
+    pub app_id: String,
+    /// 商户号
+    #[serde(rename = "partnerid")]
+    pub partner_id: String,
+    #[serde(rename = "prepayid")]
+    pub prepay_id: String,
+    // 固定为 `Sign=WXPay`
+    pub package: String,
+    #[serde(rename = "noncestr")]
+    pub nonce_str: String,
+    pub timestamp: String, // 单位为秒。
+    pub sign: String,
+}
+
 /// JSAPI 下单参数
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsApiCreateTradeParams {
@@ -557,6 +672,449 @@ pub struct NativeCreateTradeParams {
     pub settle_info: Option<SettleInfo>,
 }
 
+/// 下单参数的构造器，四种下单参数共用。
+/// `build` 时按文档的限制校验参数，所有不符合要求的字段以 [`Error::Validation`](crate::Error::Validation) 一并返回。
+///
+/// ```ignore
+/// let params = JsApiCreateTradeParams::builder()
+///     .app_id("wxd678efh567hg6787")
+///     .mch_id("1230000109")
+///     .description("Image形象店-深圳腾大-QQ公仔")
+///     .out_trade_no(generate_out_trade_no())
+///     .notify_url("https://www.weixin.qq.com/wxpay/pay.php")
+///     .total(100)
+///     .payer_openid("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o")
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct CreateTradeBuilder<T> {
+    app_id: String,
+    mch_id: String,
+    description: String,
+    out_trade_no: String,
+    time_expire: Option<DateTime<Local>>,
+    attach: Option<String>,
+    notify_url: String,
+    goods_tag: Option<String>,
+    support_fapiao: Option<bool>,
+    amount: Amount,
+    payer_openid: Option<String>,
+    detail: Option<CreateTradePromotionDetail>,
+    scene_info: Option<CreateTradeSceneInfo>,
+    settle_info: Option<SettleInfo>,
+    _params: PhantomData<fn() -> T>,
+}
+
+impl<T> CreateTradeBuilder<T> {
+    fn new() -> CreateTradeBuilder<T> {
+        CreateTradeBuilder {
+            app_id: String::new(),
+            mch_id: String::new(),
+            description: String::new(),
+            out_trade_no: String::new(),
+            time_expire: None,
+            attach: None,
+            notify_url: String::new(),
+            goods_tag: None,
+            support_fapiao: None,
+            amount: Amount::new_with_cny(0),
+            payer_openid: None,
+            detail: None,
+            scene_info: None,
+            settle_info: None,
+            _params: PhantomData,
+        }
+    }
+
+    /// 应用 ID
+    pub fn app_id(mut self, app_id: impl Into<String>) -> Self {
+        self.app_id = app_id.into();
+        self
+    }
+
+    /// 商户号
+    pub fn mch_id(mut self, mch_id: impl Into<String>) -> Self {
+        self.mch_id = mch_id.into();
+        self
+    }
+
+    /// 商品描述。不超过 127 字符。
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// 商户订单号。只能是数字、大小写字母_-*组成，长度应在 [6, 32] 字符之间。
+    pub fn out_trade_no(mut self, out_trade_no: impl Into<String>) -> Self {
+        self.out_trade_no = out_trade_no.into();
+        self
+    }
+
+    /// 订单失效时间
+    pub fn time_expire(mut self, time_expire: DateTime<Local>) -> Self {
+        self.time_expire = Some(time_expire);
+        self
+    }
+
+    /// 附加数据。不超过 128 字节。
+    pub fn attach(mut self, attach: impl Into<String>) -> Self {
+        self.attach = Some(attach.into());
+        self
+    }
+
+    /// 支付结果通知的回调地址，不能携带参数。
+    pub fn notify_url(mut self, notify_url: impl Into<String>) -> Self {
+        self.notify_url = notify_url.into();
+        self
+    }
+
+    /// 订单优惠标记
+    pub fn goods_tag(mut self, goods_tag: impl Into<String>) -> Self {
+        self.goods_tag = Some(goods_tag.into());
+        self
+    }
+
+    /// 电子发票入口开放标识
+    pub fn support_fapiao(mut self, support_fapiao: bool) -> Self {
+        self.support_fapiao = Some(support_fapiao);
+        self
+    }
+
+    /// 以人民币为单位的订单金额(单位: 分)，必须大于 0。
+    pub fn total(mut self, total: i32) -> Self {
+        self.amount = Amount::new_with_cny(total);
+        self
+    }
+
+    /// 订单金额
+    pub fn amount(mut self, amount: Amount) -> Self {
+        self.amount = amount;
+        self
+    }
+
+    /// 优惠功能
+    pub fn detail(mut self, detail: CreateTradePromotionDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    /// 场景信息
+    pub fn scene_info(mut self, scene_info: CreateTradeSceneInfo) -> Self {
+        self.scene_info = Some(scene_info);
+        self
+    }
+
+    /// 结算信息
+    pub fn settle_info(mut self, settle_info: SettleInfo) -> Self {
+        self.settle_info = Some(settle_info);
+        self
+    }
+}
+
+impl CreateTradeBuilder<JsApiCreateTradeParams> {
+    /// 支付者的 openid。JSAPI 下单必填。
+    pub fn payer_openid(mut self, openid: impl Into<String>) -> Self {
+        self.payer_openid = Some(openid.into());
+        self
+    }
+}
+
+impl<T: CreateTradeKind> CreateTradeBuilder<T> {
+    /// 校验并构造下单参数。
+    pub fn build(self) -> Result<T> {
+        let params = T::from_builder(self);
+        params.fields().validate().into_result()?;
+        Ok(params)
+    }
+}
+
+impl JsApiCreateTradeParams {
+    /// JSAPI 下单参数的构造器
+    pub fn builder() -> CreateTradeBuilder<JsApiCreateTradeParams> {
+        CreateTradeBuilder::new()
+    }
+
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+impl AppCreateTradeParams {
+    /// APP 下单参数的构造器
+    pub fn builder() -> CreateTradeBuilder<AppCreateTradeParams> {
+        CreateTradeBuilder::new()
+    }
+
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+impl H5CreateTradeParams {
+    /// H5 下单参数的构造器
+    pub fn builder() -> CreateTradeBuilder<H5CreateTradeParams> {
+        CreateTradeBuilder::new()
+    }
+
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+impl NativeCreateTradeParams {
+    /// Native 下单参数的构造器
+    pub fn builder() -> CreateTradeBuilder<NativeCreateTradeParams> {
+        CreateTradeBuilder::new()
+    }
+
+    /// 按文档的限制校验参数。下单前会自动校验。
+    pub fn validate(&self) -> Result<()> {
+        self.fields().validate().into_result()
+    }
+}
+
+/// 下单参数共用的构造及校验。置于私有模块中，不对外公开。
+mod kind {
+    use super::CreateTradeBuilder;
+
+    /// 下单参数中需要校验的字段。直连与服务商模式的下单参数共用。
+    pub struct CreateTradeFields<'a> {
+        /// 必填的字段，以请求中的字段名标识，如 `appid`、`mchid`、`payer.openid`。
+        pub required: Vec<(&'static str, Option<&'a str>)>,
+        pub description: &'a str,
+        pub out_trade_no: &'a str,
+        pub attach: Option<&'a str>,
+        pub notify_url: &'a str,
+        pub total: i32,
+    }
+
+    /// 可由 [`CreateTradeBuilder`] 构造的下单参数。
+    pub trait CreateTradeKind: Sized {
+        /// 由构造器中的字段构造下单参数，不做校验。
+        fn from_builder(builder: CreateTradeBuilder<Self>) -> Self;
+
+        /// 需要校验的字段。
+        fn fields(&self) -> CreateTradeFields<'_>;
+    }
+}
+
+pub(crate) use kind::CreateTradeFields;
+use kind::CreateTradeKind;
+
+impl CreateTradeKind for JsApiCreateTradeParams {
+    fn from_builder(builder: CreateTradeBuilder<Self>) -> Self {
+        JsApiCreateTradeParams {
+            app_id: builder.app_id,
+            mch_id: builder.mch_id,
+            description: builder.description,
+            out_trade_no: builder.out_trade_no,
+            time_expire: builder.time_expire,
+            attach: builder.attach,
+            notify_url: builder.notify_url,
+            goods_tag: builder.goods_tag,
+            support_fapiao: builder.support_fapiao,
+            amount: builder.amount,
+            payer: Payer {
+                openid: builder.payer_openid,
+            },
+            detail: builder.detail,
+            scene_info: builder.scene_info,
+            settle_info: builder.settle_info,
+        }
+    }
+
+    fn fields(&self) -> CreateTradeFields<'_> {
+        CreateTradeFields {
+            required: vec![
+                ("appid", Some(&self.app_id)),
+                ("mchid", Some(&self.mch_id)),
+                ("payer.openid", self.payer.openid.as_deref()),
+            ],
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+impl CreateTradeKind for AppCreateTradeParams {
+    fn from_builder(builder: CreateTradeBuilder<Self>) -> Self {
+        AppCreateTradeParams {
+            app_id: builder.app_id,
+            mch_id: builder.mch_id,
+            description: builder.description,
+            out_trade_no: builder.out_trade_no,
+            time_expire: builder.time_expire,
+            attach: builder.attach,
+            notify_url: builder.notify_url,
+            goods_tag: builder.goods_tag,
+            support_fapiao: builder.support_fapiao,
+            amount: builder.amount,
+            detail: builder.detail,
+            scene_info: builder.scene_info,
+            settle_info: builder.settle_info,
+        }
+    }
+
+    fn fields(&self) -> CreateTradeFields<'_> {
+        CreateTradeFields {
+            required: vec![("appid", Some(&self.app_id)), ("mchid", Some(&self.mch_id))],
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+impl CreateTradeKind for H5CreateTradeParams {
+    fn from_builder(builder: CreateTradeBuilder<Self>) -> Self {
+        H5CreateTradeParams {
+            app_id: builder.app_id,
+            mch_id: builder.mch_id,
+            description: builder.description,
+            out_trade_no: builder.out_trade_no,
+            time_expire: builder.time_expire,
+            attach: builder.attach,
+            notify_url: builder.notify_url,
+            goods_tag: builder.goods_tag,
+            support_fapiao: builder.support_fapiao,
+            amount: builder.amount,
+            detail: builder.detail,
+            scene_info: builder.scene_info,
+            settle_info: builder.settle_info,
+        }
+    }
+
+    fn fields(&self) -> CreateTradeFields<'_> {
+        CreateTradeFields {
+            required: vec![("appid", Some(&self.app_id)), ("mchid", Some(&self.mch_id))],
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+impl CreateTradeKind for NativeCreateTradeParams {
+    fn from_builder(builder: CreateTradeBuilder<Self>) -> Self {
+        NativeCreateTradeParams {
+            app_id: builder.app_id,
+            mch_id: builder.mch_id,
+            description: builder.description,
+            out_trade_no: builder.out_trade_no,
+            time_expire: builder.time_expire,
+            attach: builder.attach,
+            notify_url: builder.notify_url,
+            goods_tag: builder.goods_tag,
+            support_fapiao: builder.support_fapiao,
+            amount: builder.amount,
+            detail: builder.detail,
+            scene_info: builder.scene_info,
+            settle_info: builder.settle_info,
+        }
+    }
+
+    fn fields(&self) -> CreateTradeFields<'_> {
+        CreateTradeFields {
+            required: vec![("appid", Some(&self.app_id)), ("mchid", Some(&self.mch_id))],
+            description: &self.description,
+            out_trade_no: &self.out_trade_no,
+            attach: self.attach.as_deref(),
+            notify_url: &self.notify_url,
+            total: self.amount.total,
+        }
+    }
+}
+
+impl CreateTradeFields<'_> {
+    /// 按文档的限制校验，返回所有不符合要求的字段。
+    pub(crate) fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::default();
+        for (field, value) in &self.required {
+            if value.is_none_or(str::is_empty) {
+                errors.push(field, ValidationReason::Missing);
+            }
+        }
+
+        let len = self.description.chars().count();
+        if len == 0 {
+            errors.push("description", ValidationReason::Missing);
+        } else if len > 127 {
+            let reason = ValidationReason::Length {
+                min: 1,
+                max: 127,
+                actual: len,
+            };
+            errors.push("description", reason);
+        }
+
+        if let Some(reason) = check_out_trade_no(self.out_trade_no) {
+            errors.push("out_trade_no", reason);
+        }
+
+        if let Some(attach) = self.attach {
+            if attach.len() > 128 {
+                let reason = ValidationReason::TooManyBytes {
+                    max: 128,
+                    actual: attach.len(),
+                };
+                errors.push("attach", reason);
+            }
+        }
+
+        if self.notify_url.is_empty() {
+            errors.push("notify_url", ValidationReason::Missing);
+        } else {
+            match reqwest::Url::parse(self.notify_url) {
+                Ok(url) if url.query().is_some() => {
+                    errors.push("notify_url", ValidationReason::UrlHasQuery)
+                }
+                Ok(_) => {}
+                Err(e) => errors.push("notify_url", ValidationReason::InvalidUrl(e.to_string())),
+            }
+        }
+
+        if self.total <= 0 {
+            errors.push(
+                "amount.total",
+                ValidationReason::NotPositive(self.total.into()),
+            );
+        }
+        errors
+    }
+}
+
+/// 商户订单号只能是数字、大小写字母_-*组成，长度应在 [6, 32] 字符之间。
+fn check_out_trade_no(out_trade_no: &str) -> Option<ValidationReason> {
+    if out_trade_no.is_empty() {
+        return Some(ValidationReason::Missing);
+    }
+    let invalid = out_trade_no
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '*')));
+    if let Some(c) = invalid {
+        return Some(ValidationReason::InvalidChar(c));
+    }
+    let len = out_trade_no.len();
+    if !(6..=32).contains(&len) {
+        return Some(ValidationReason::Length {
+            min: 6,
+            max: 32,
+            actual: len,
+        });
+    }
+    None
+}
+
 /// 订单查询响应
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeQueryResponse {
@@ -750,6 +1308,10 @@ pub fn generate_out_trade_no() -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock::fixtures::{test_credential, test_platform_certificate};
+    use crate::Error;
+    use base64::prelude::*;
+    use rsa::sha2::{Digest, Sha256};
 
     #[test]
     fn test_trade_type_serde() -> anyhow::Result<()> {
@@ -794,4 +1356,202 @@ mod tests {
         assert_eq!(s.chars().nth(13), Some('-'));
         assert_eq!(s.chars().nth(20), Some('-'));
     }
+
+    #[test]
+    fn test_create_trade_builder() -> anyhow::Result<()> {
+        let params = JsApiCreateTradeParams::builder()
+            .app_id("wxd678efh567hg6787")
+            .mch_id("1230000109")
+            .description("Image形象店-深圳腾大-QQ公仔")
+            .out_trade_no("1217752501201407033233368018")
+            .notify_url("https://www.weixin.qq.com/wxpay/pay.php")
+            .attach("自定义数据")
+            .total(100)
+            .payer_openid("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o")
+            .build()?;
+        assert_eq!(params.amount.total, 100);
+        assert_eq!(params.amount.currency, "CNY");
+        assert_eq!(
+            params.payer.openid.as_deref(),
+            Some("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o")
+        );
+        let json = serde_json::to_value(&params)?;
+        assert_eq!(json["appid"], "wxd678efh567hg6787");
+        assert!(json.get("goods_tag").is_none());
+
+        let params = NativeCreateTradeParams::builder()
+            .app_id("wxd678efh567hg6787")
+            .mch_id("1230000109")
+            .description("Image形象店-深圳腾大-QQ公仔")
+            .out_trade_no("a_b-c*d")
+            .notify_url("https://www.weixin.qq.com/wxpay/pay.php")
+            .total(1)
+            .build()?;
+        assert_eq!(params.out_trade_no, "a_b-c*d");
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_trade_validation() {
+        let err = H5CreateTradeParams::builder()
+            .app_id("wxd678efh567hg6787")
+            .description("描".repeat(128))
+            .out_trade_no("12345")
+            .notify_url("https://www.weixin.qq.com/wxpay/pay.php?id=1")
+            .attach("a".repeat(129))
+            .build()
+            .unwrap_err();
+        let Error::Validation(errors) = err else {
+            panic!("unexpected error: {:?}", err);
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(
+            fields,
+            [
+                "mchid",
+                "description",
+                "out_trade_no",
+                "attach",
+                "notify_url",
+                "amount.total"
+            ]
+        );
+        assert_eq!(
+            errors.get("description"),
+            Some(&ValidationReason::Length {
+                min: 1,
+                max: 127,
+                actual: 128
+            })
+        );
+        assert_eq!(
+            errors.get("out_trade_no"),
+            Some(&ValidationReason::Length {
+                min: 6,
+                max: 32,
+                actual: 5
+            })
+        );
+        assert_eq!(
+            errors.get("attach"),
+            Some(&ValidationReason::TooManyBytes {
+                max: 128,
+                actual: 129
+            })
+        );
+        assert_eq!(
+            errors.get("notify_url"),
+            Some(&ValidationReason::UrlHasQuery)
+        );
+        assert_eq!(
+            errors.get("amount.total"),
+            Some(&ValidationReason::NotPositive(0))
+        );
+        assert!(errors.to_string().starts_with("`mchid` is required; "));
+
+        assert_eq!(
+            check_out_trade_no("订单123456"),
+            Some(ValidationReason::InvalidChar('订'))
+        );
+        assert_eq!(
+            check_out_trade_no(&"1".repeat(33)),
+            Some(ValidationReason::Length {
+                min: 6,
+                max: 32,
+                actual: 33
+            })
+        );
+
+        // 直接构造的参数同样校验
+        let mut params = JsApiCreateTradeParams::new(
+            "wxd678efh567hg6787".to_string(),
+            "1230000109".to_string(),
+            "Image形象店-深圳腾大-QQ公仔".to_string(),
+            "1217752501201407033233368018".to_string(),
+            None,
+            None,
+            "not a url".to_string(),
+            Amount::new_with_cny(-1),
+            String::new(),
+        );
+        let Err(Error::Validation(errors)) = params.validate() else {
+            panic!("params should be invalid");
+        };
+        assert!(matches!(
+            errors.get("notify_url"),
+            Some(ValidationReason::InvalidUrl(_))
+        ));
+        assert_eq!(
+            errors.get("amount.total"),
+            Some(&ValidationReason::NotPositive(-1))
+        );
+        assert_eq!(errors.get("payer.openid"), Some(&ValidationReason::Missing));
+        params.notify_url = "https://www.weixin.qq.com/wxpay/pay.php".to_string();
+        params.amount.total = 1;
+        params.payer = Payer::new("oUpF8uMuAJO_M2pxb1Q9zNjWeS6o".to_string());
+        assert!(params.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_sign_trade() -> anyhow::Result<()> {
+        let credential = test_credential();
+        let public_key = credential.mch_rsa_private_key.to_public_key();
+        let client = WechatPayClient::builder()
+            .mch_credential(credential)
+            .platform_certificate(test_platform_certificate())
+            .build()
+            .await?;
+        let verify = |msg: String, signature: &str| -> anyhow::Result<()> {
+            let digest = Sha256::digest(msg.as_bytes());
+            public_key.verify(
+                rsa::Pkcs1v15Sign::new::<Sha256>(),
+                &digest,
+                &BASE64_STANDARD.decode(signature)?,
+            )?;
+            Ok(())
+        };
+        let prepay_id = "wx201410272009395522657a690389285100";
+
+        let signature = client.sign_mini_program_trade(prepay_id, "wxd678efh567hg6787")?;
+        let payload = serde_json::to_value(&signature)?;
+        assert!(payload.get("appId").is_none());
+        assert_eq!(payload["package"], format!("prepay_id={}", prepay_id));
+        assert_eq!(payload["signType"], "RSA");
+        verify(
+            format!(
+                "wxd678efh567hg6787\n{}\n{}\n{}\n",
+                payload["timeStamp"].as_str().unwrap(),
+                payload["nonceStr"].as_str().unwrap(),
+                payload["package"].as_str().unwrap()
+            ),
+            payload["paySign"].as_str().unwrap(),
+        )?;
+
+        let signature = client.sign_jsapi_trade(prepay_id, "wxd678efh567hg6787")?;
+        assert_eq!(signature.app_id, "wxd678efh567hg6787");
+        verify(
+            format!(
+                "{}\n{}\n{}\n{}\n",
+                signature.app_id, signature.timestamp, signature.nonce_str, signature.package
+            ),
+            &signature.pay_sign,
+        )?;
+
+        let signature = client.sign_app_trade(prepay_id, "wxd678efh567hg6787")?;
+        let payload = serde_json::to_value(&signature)?;
+        assert_eq!(payload["appid"], "wxd678efh567hg6787");
+        assert_eq!(payload["partnerid"], "1900000001");
+        assert_eq!(payload["prepayid"], prepay_id);
+        assert_eq!(payload["package"], "Sign=WXPay");
+        verify(
+            format!(
+                "wxd678efh567hg6787\n{}\n{}\n{}\n",
+                payload["timestamp"].as_str().unwrap(),
+                payload["noncestr"].as_str().unwrap(),
+                prepay_id
+            ),
+            payload["sign"].as_str().unwrap(),
+        )?;
+        Ok(())
+    }
 }
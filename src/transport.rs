@@ -0,0 +1,423 @@
+//! HTTP 传输。
+//!
+//! WechatPayClient 的所有请求(包括平台证书下载)都通过 [`Transport`] 发送。
+//! 默认使用 `reqwest::Client`；也可以传入预先配置好代理、mTLS、DNS 的 `reqwest::Client`，
+//! 或自行实现 [`Transport`]。测试时可使用 [`MockTransport`] 预置响应。
+
+use crate::error::{Error, Result};
+use crate::middleware::BoxError;
+use async_trait::async_trait;
+use base64::prelude::*;
+use bytes::Bytes;
+use chrono::Local;
+use http::header::USER_AGENT;
+use http::{HeaderMap, HeaderValue, StatusCode};
+use reqwest::{Request, Response};
+use rsa::pkcs1v15::SigningKey;
+use rsa::sha2::Sha256;
+use rsa::signature::{RandomizedSigner, SignatureEncoding};
+use rsa::RsaPrivateKey;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower::Service;
+
+/// 发送 HTTP 请求。
+/// 请求已签名；返回的响应由 WechatPayClient 负责验签。
+#[async_trait]
+pub trait Transport: Send + Sync + 'static {
+    async fn send(&self, req: Request) -> Result<Response>;
+}
+
+#[async_trait]
+impl Transport for reqwest::Client {
+    async fn send(&self, req: Request) -> Result<Response> {
+        Ok(self.execute(req).await?)
+    }
+}
+
+/// WechatPayClient 持有的传输，同时负责补上 User-Agent，以及切换到备用域名。
+/// (`reqwest::Request` 构造时不带 client 的默认 headers)
+#[derive(Clone)]
+pub(crate) struct HttpTransport {
+    transport: Arc<dyn Transport>,
+    user_agent: HeaderValue,
+    base_url: Arc<str>,
+    backup_base_url: Option<Arc<str>>,
+}
+
+impl HttpTransport {
+    pub(crate) fn new(
+        transport: Arc<dyn Transport>,
+        user_agent: &str,
+        base_url: &str,
+        backup_base_url: Option<&str>,
+    ) -> Result<HttpTransport> {
+        let user_agent = user_agent
+            .parse()
+            .map_err(|_| Error::InvalidArgument(format!("invalid user agent: {}", user_agent)))?;
+        Ok(HttpTransport {
+            transport,
+            user_agent,
+            base_url: normalize_base_url(base_url)?,
+            backup_base_url: backup_base_url.map(normalize_base_url).transpose()?,
+        })
+    }
+
+    /// 接口的根地址，如 `https://api.mch.weixin.qq.com`。
+    pub(crate) fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// 发送请求。设置了备用域名时，若请求主域名遇到连接错误或 5xx 响应，
+    /// 使用备用域名重新发送一次。签名只包含请求的路径，因此无需重新签名。
+    pub(crate) async fn send(&self, mut req: Request) -> Result<Response> {
+        if !req.headers().contains_key(USER_AGENT) {
+            req.headers_mut()
+                .insert(USER_AGENT, self.user_agent.clone());
+        }
+
+        let backup_req = self.backup_request(&req);
+        let result = self.transport.send(req).await;
+        let Some(backup_req) = backup_req else {
+            return result;
+        };
+        let failover = match &result {
+            Ok(res) => res.status().is_server_error(),
+            Err(Error::Transport(e)) => e.is_connect(),
+            Err(_) => false,
+        };
+        if !failover {
+            return result;
+        }
+        log::warn!(
+            "wechatpay request to {} failed, fail over to {}",
+            self.base_url,
+            backup_req.url()
+        );
+        self.transport.send(backup_req).await
+    }
+
+    /// 将发往主域名的请求改为发往备用域名。未设置备用域名，或 body 无法复制时返回 None。
+    fn backup_request(&self, req: &Request) -> Option<Request> {
+        let backup_base_url = self.backup_base_url.as_deref()?;
+        let path = req.url().as_str().strip_prefix(&*self.base_url)?;
+        let url = format!("{}{}", backup_base_url, path).parse().ok()?;
+        let mut backup_req = req.try_clone()?;
+        *backup_req.url_mut() = url;
+        Some(backup_req)
+    }
+}
+
+/// 检查并去掉末尾的 `/`。
+fn normalize_base_url(base_url: &str) -> Result<Arc<str>> {
+    let base_url = base_url.trim_end_matches('/');
+    reqwest::Url::parse(base_url)
+        .map_err(|e| Error::InvalidArgument(format!("invalid base url {}: {}", base_url, e)))?;
+    Ok(base_url.into())
+}
+
+impl Service<Request> for HttpTransport {
+    type Response = Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let transport = self.clone();
+        Box::pin(async move { transport.send(req).await.map_err(Into::into) })
+    }
+}
+
+impl fmt::Debug for HttpTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpTransport")
+            .field("user_agent", &self.user_agent)
+            .field("base_url", &self.base_url)
+            .field("backup_base_url", &self.backup_base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 内存中的传输，按顺序返回预置的响应，并记录收到的请求。用于测试。
+///
+/// 设置了平台私钥时，响应带有正确的 `Wechatpay-Serial`、`Wechatpay-Timestamp`、
+/// `Wechatpay-Nonce` 与 `Wechatpay-Signature`，可以通过验签。
+///
+/// ```ignore
+/// let transport = MockTransport::new().signer(platform_serial, platform_private_key);
+/// transport.push_json(200, r#"{"prepay_id":"wx201410272009395522657a690389285100"}"#);
+/// let client = WechatPayClient::builder()
+///     .mch_credential(credential)
+///     .platform_certificate(platform_certificate)
+///     .transport(transport.clone())
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    inner: Arc<Mutex<MockState>>,
+    signer: Option<Arc<(String, SigningKey<Sha256>)>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    responses: VecDeque<http::Response<Bytes>>,
+    requests: Vec<http::Request<Bytes>>,
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// 使用平台私钥对响应签名。`serial` 为平台证书序列号或微信支付公钥 ID。
+    pub fn signer(mut self, serial: impl Into<String>, private_key: RsaPrivateKey) -> Self {
+        self.signer = Some(Arc::new((serial.into(), SigningKey::new(private_key))));
+        self
+    }
+
+    /// 预置一个响应。设置了平台私钥时，对 2xx 响应签名。
+    pub fn push_response(&self, res: http::Response<Bytes>) {
+        let res = match &self.signer {
+            Some(signer) if res.status().is_success() => sign_response(signer, res),
+            _ => res,
+        };
+        self.inner.lock().unwrap().responses.push_back(res);
+    }
+
+    /// 预置一个 JSON 响应。
+    pub fn push_json(&self, status: u16, body: impl Into<String>) {
+        let res = http::Response::builder()
+            .status(StatusCode::from_u16(status).expect("invalid status code"))
+            .header("Content-Type", "application/json")
+            .header("Request-ID", "08F78BB5AF0610D302")
+            .body(Bytes::from(body.into()))
+            .unwrap();
+        self.push_response(res);
+    }
+
+    /// 已收到的请求。
+    pub fn requests(&self) -> Vec<http::Request<Bytes>> {
+        self.inner.lock().unwrap().requests.clone()
+    }
+
+    /// 尚未返回的预置响应数。
+    pub fn remaining(&self) -> usize {
+        self.inner.lock().unwrap().responses.len()
+    }
+}
+
+/// 对响应签名，加上验签所需的头部。
+fn sign_response(
+    signer: &(String, SigningKey<Sha256>),
+    res: http::Response<Bytes>,
+) -> http::Response<Bytes> {
+    let (serial, signing_key) = signer;
+    let (mut parts, body) = res.into_parts();
+    sign_headers(&mut parts.headers, serial, signing_key, &body);
+    http::Response::from_parts(parts, body)
+}
+
+/// 以平台私钥对 body 签名，写入 `Wechatpay-Serial`、`Wechatpay-Timestamp`、
+/// `Wechatpay-Nonce` 与 `Wechatpay-Signature`。响应与通知的签名方式相同。
+pub(crate) fn sign_headers(
+    headers: &mut HeaderMap,
+    serial: &str,
+    signing_key: &SigningKey<Sha256>,
+    body: &[u8],
+) {
+    let timestamp = Local::now().timestamp().to_string();
+    let nonce = crate::credential::generate_none_str(32);
+    let mut msg = Vec::with_capacity(body.len() + 64);
+    msg.extend_from_slice(format!("{}\n{}\n", timestamp, nonce).as_bytes());
+    msg.extend_from_slice(body);
+    msg.push(b'\n');
+    let signature = signing_key.sign_with_rng(&mut rand::thread_rng(), &msg);
+    let signature = BASE64_STANDARD.encode(signature.to_bytes());
+
+    headers.insert("Wechatpay-Serial", serial.parse().unwrap());
+    headers.insert("Wechatpay-Timestamp", timestamp.parse().unwrap());
+    headers.insert("Wechatpay-Nonce", nonce.parse().unwrap());
+    headers.insert("Wechatpay-Signature", signature.parse().unwrap());
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn send(&self, req: Request) -> Result<Response> {
+        let mut recorded = http::Request::builder()
+            .method(req.method().clone())
+            .uri(req.url().as_str())
+            .body(Bytes::copy_from_slice(
+                req.body().and_then(|b| b.as_bytes()).unwrap_or_default(),
+            ))?;
+        *recorded.headers_mut() = req.headers().clone();
+
+        let mut state = self.inner.lock().unwrap();
+        state.requests.push(recorded);
+        let res = state.responses.pop_front().ok_or_else(|| {
+            Error::Service(
+                format!("no scripted response for {} {}", req.method(), req.url()).into(),
+            )
+        })?;
+        Ok(res.into())
+    }
+}
+
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("remaining", &self.remaining())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::fixtures::{test_credential, test_platform_certificate, PLATFORM_SERIAL};
+    use crate::WechatPayClient;
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+
+    fn builder(transport: MockTransport) -> crate::client::WechatPayClientBuilder {
+        WechatPayClient::builder()
+            .mch_credential(test_credential())
+            .platform_certificate(test_platform_certificate())
+            .transport(transport)
+    }
+
+    async fn client(transport: MockTransport) -> WechatPayClient {
+        builder(transport).build().await.unwrap()
+    }
+
+    fn platform_key() -> RsaPrivateKey {
+        RsaPrivateKey::from_pkcs1_pem(include_str!("../testdata/platform_key.pem")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport() -> Result<()> {
+        let transport = MockTransport::new().signer(PLATFORM_SERIAL, platform_key());
+        transport.push_json(
+            200,
+            r#"{"prepay_id":"wx201410272009395522657a690389285100"}"#,
+        );
+        let client = client(transport.clone()).await;
+
+        let req = client
+            .client
+            .post("https://api.mch.weixin.qq.com/v3/pay/transactions/jsapi")
+            .body(r#"{"out_trade_no":"1217752501201407033233368018"}"#)
+            .build()?;
+        let res = client.execute(req, None).await?;
+        assert_eq!(
+            res.text().await?,
+            r#"{"prepay_id":"wx201410272009395522657a690389285100"}"#
+        );
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        let headers = requests[0].headers();
+        assert!(headers["Authorization"]
+            .to_str()
+            .unwrap()
+            .starts_with("WECHATPAY2-SHA256-RSA2048 mchid=\"1900000001\""));
+        assert_eq!(headers["Wechatpay-Serial"], PLATFORM_SERIAL);
+        assert!(headers.contains_key(USER_AGENT));
+        assert_eq!(
+            requests[0].body(),
+            r#"{"out_trade_no":"1217752501201407033233368018"}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_unsigned() -> Result<()> {
+        // 未设置平台私钥时，响应无法通过验签。
+        let transport = MockTransport::new();
+        transport.push_json(200, "{}");
+        transport.push_json(404, r#"{"code":"ORDER_NOT_EXIST","message":"订单不存在"}"#);
+        let client = client(transport.clone()).await;
+
+        let url = "https://api.mch.weixin.qq.com/v3/pay/transactions/id/1";
+        let err = client
+            .execute(client.client.get(url).build()?, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Verification(_)));
+
+        let err = client
+            .execute(client.client.get(url).build()?, None)
+            .await
+            .unwrap_err();
+        let Error::Api(e) = err else {
+            panic!("unexpected error: {:?}", err)
+        };
+        assert_eq!(e.status, StatusCode::NOT_FOUND);
+        assert_eq!(e.request_id.as_deref(), Some("08F78BB5AF0610D302"));
+
+        let err = client
+            .execute(client.client.get(url).build()?, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Service(_)));
+        assert_eq!(transport.remaining(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failover() -> Result<()> {
+        let transport = MockTransport::new().signer(PLATFORM_SERIAL, platform_key());
+        transport.push_json(503, r#"{"code":"SYSTEM_ERROR","message":"系统繁忙"}"#);
+        transport.push_json(200, r#"{"trade_state":"SUCCESS"}"#);
+        transport.push_json(400, r#"{"code":"PARAM_ERROR","message":"参数错误"}"#);
+        let client = builder(transport.clone())
+            .base_url("http://127.0.0.1:8080/")
+            .backup_base_url(crate::client::BACKUP_BASE_URL)
+            .build()
+            .await?;
+        assert_eq!(client.base_url(), "http://127.0.0.1:8080");
+
+        let url = format!(
+            "{}/v3/pay/transactions/id/1?mchid=1900000001",
+            client.base_url()
+        );
+        let res = client
+            .execute(client.client.get(&url).build()?, None)
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // 4xx 不切换域名。
+        let err = client
+            .execute(client.client.get(&url).build()?, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(&crate::ErrorCode::ParamError));
+
+        let uris: Vec<_> = transport
+            .requests()
+            .iter()
+            .map(|r| r.uri().to_string())
+            .collect();
+        assert_eq!(
+            uris,
+            vec![
+                "http://127.0.0.1:8080/v3/pay/transactions/id/1?mchid=1900000001",
+                "https://api2.mch.weixin.qq.com/v3/pay/transactions/id/1?mchid=1900000001",
+                "http://127.0.0.1:8080/v3/pay/transactions/id/1?mchid=1900000001",
+            ]
+        );
+        // 切换域名时沿用原请求的签名。
+        let requests = transport.requests();
+        assert_eq!(
+            requests[0].headers()["Authorization"],
+            requests[1].headers()["Authorization"]
+        );
+        Ok(())
+    }
+}